@@ -0,0 +1,98 @@
+//! Notification action buttons.
+//!
+//! libnotify delivers action callbacks through the glib main context, so the daemon waits
+//! between updates with [`wait_until`] instead of sleeping, which iterates the context and
+//! hands back any action the user picked.
+
+use glib::translate::ToGlibPtr;
+use libnotify::Notification;
+use libnotify_sys::{NotifyActionCallback, NotifyNotification};
+use std::ffi::{c_char, CStr, CString};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use strum::{EnumString, IntoStaticStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, IntoStaticStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum Action {
+    Suspend,
+    Snooze,
+}
+
+impl Action {
+    fn label(self) -> &'static str {
+        match self {
+            Action::Suspend => "Suspend now",
+            Action::Snooze => "Snooze 10 min",
+        }
+    }
+}
+
+static PENDING: Mutex<Vec<Action>> = Mutex::new(Vec::new());
+
+// Not exposed by libnotify-sys because of a metadata mismatch on `free_func`
+#[link(name = "notify")]
+extern "C" {
+    fn notify_notification_add_action(
+        notification: *mut NotifyNotification,
+        action: *const c_char,
+        label: *const c_char,
+        callback: NotifyActionCallback,
+        user_data: glib_sys::gpointer,
+        free_func: glib_sys::GDestroyNotify,
+    );
+}
+
+unsafe extern "C" fn on_action(
+    _notification: *mut NotifyNotification,
+    action: *mut c_char,
+    _user_data: glib_sys::gpointer,
+) {
+    let action = CStr::from_ptr(action).to_string_lossy();
+    match action.parse::<Action>() {
+        Ok(a) => PENDING.lock().unwrap().push(a),
+        Err(_) => debug!("Ignoring unknown notification action '{action}'"),
+    }
+}
+
+/// Whether the notification server can display action buttons
+pub fn supported() -> bool {
+    libnotify::get_server_caps().iter().any(|c| c == "actions")
+}
+
+/// Attach an action button to a notification.
+/// The notification must be kept alive for the action to be delivered.
+pub fn add(notification: &Notification, action: Action) {
+    let id = CString::new(<&str>::from(action)).unwrap();
+    let label = CString::new(action.label()).unwrap();
+
+    // libnotify copies both strings
+    unsafe {
+        notify_notification_add_action(
+            notification.to_glib_none().0,
+            id.as_ptr(),
+            label.as_ptr(),
+            Some(on_action),
+            std::ptr::null_mut(),
+            None,
+        );
+    }
+}
+
+/// Wait until `deadline` or until `woken` returns true, returning early with any action the
+/// user picked in the meantime
+pub fn wait_until(deadline: Instant, mut woken: impl FnMut() -> bool) -> Option<Action> {
+    loop {
+        unsafe { while glib_sys::g_main_context_iteration(std::ptr::null_mut(), 0) != 0 {} }
+
+        if let Some(action) = PENDING.lock().unwrap().pop() {
+            return Some(action);
+        }
+
+        let now = Instant::now();
+        if now >= deadline || woken() {
+            return None;
+        }
+        std::thread::sleep((deadline - now).min(Duration::from_millis(100)));
+    }
+}
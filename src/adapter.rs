@@ -0,0 +1,70 @@
+use crate::battery::POWER_SUPPLY_ROOT;
+use crate::device::{self, Device, DeviceKind};
+use crate::poll::PolledValue;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The `online` attribute of an adapter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Online(bool);
+
+impl FromStr for Online {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<u8>()? {
+            0 => Ok(Online(false)),
+            1 => Ok(Online(true)),
+            // Some drivers report how the adapter is connected, like 2 for a fixed supply
+            other => {
+                debug!("Treating adapter online value {other} as online");
+                Ok(Online(true))
+            }
+        }
+    }
+}
+
+/// An AC adapter, the mains counterpart to `Battery`
+#[derive(Debug)]
+pub struct Adapter {
+    pub name: String,
+    online: PolledValue<Online>,
+}
+
+impl Adapter {
+    /// Find the first adapter by name, if the system has one
+    pub fn find() -> Option<Adapter> {
+        Adapter::find_all().into_iter().next()
+    }
+
+    /// Find every adapter, in name order
+    pub fn find_all() -> Vec<Adapter> {
+        Adapter::find_all_in(Path::new(POWER_SUPPLY_ROOT))
+    }
+
+    fn find_all_in(root: &Path) -> Vec<Adapter> {
+        device::list(root)
+            .into_iter()
+            .filter(|(name, _)| Device::from(root.join(name)).kind() == DeviceKind::Mains)
+            .map(|(name, _)| {
+                let mut adapter = Adapter {
+                    online: PolledValue::new(Online::default(), root.join(&name).join("online")),
+                    name,
+                };
+                adapter.update();
+                adapter
+            })
+            .collect()
+    }
+
+    pub fn update(&mut self) {
+        if let Err(e) = self.online.update() {
+            debug!("Failed to update adapter {}: {e}", self.name);
+        }
+    }
+
+    /// Whether the adapter is plugged in, as of the last update
+    pub fn is_online(&self) -> bool {
+        self.online.0
+    }
+}
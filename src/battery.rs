@@ -1,20 +1,230 @@
-use crate::device::Device;
-use crate::poll::PolledValue;
+use crate::cache::Cache;
+use crate::clock::{Clock, SystemClock};
+use crate::device::{self, AttributeValue, Candidate, Device, DeviceRating};
+use crate::error::BatmonError;
+use crate::format::TimeFormat;
+use crate::poll::{PollSchedule, PolledValue, ReadWorker};
 use crate::status::ChargingStatus;
-use std::str::FromStr;
+use crate::trend::ChargeTrend;
+use crate::uevent::UeventData;
+use crate::units::{self, CurrentSource, UnitFamily};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use strum::{Display, EnumString};
+
+/// Kernel estimates longer than this are treated as bogus
+const MAX_KERNEL_ESTIMATE: u64 = 7 * 24 * 60 * 60;
+
+/// Where the kernel lists power supply devices
+pub const POWER_SUPPLY_ROOT: &str = "/sys/class/power_supply";
+
+/// Names the device to use by default, instead of detecting one
+pub const PRIMARY_ENV: &str = "BATMON_PRIMARY";
+
+/// A comma separated list of device names or patterns to leave out of detection
+pub const IGNORE_ENV: &str = "BATMON_IGNORE";
+
+/// A comma separated list of device names or patterns to prefer in detection, most preferred
+/// first
+pub const PRIORITY_ENV: &str = "BATMON_PRIORITY";
+
+/// The comma separated items of the environment variable `var`
+pub fn env_list(var: &str) -> Vec<String> {
+    std::env::var(var)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// What to consider when detecting the system battery
+#[derive(Debug, Clone, Default)]
+pub struct FindOptions {
+    /// Also consider UPS devices, which are otherwise only considered without a system battery
+    pub include_ups: bool,
+    /// Directories to scan for devices as well as /sys/class/power_supply.
+    /// Their devices are rated together with the standard ones.
+    pub extra_roots: Vec<PathBuf>,
+    /// Names of devices to leave out of detection, as with a dock's bogus battery.
+    /// `*` and `?` match any run of characters and any single character.
+    pub ignore: Vec<String>,
+    /// Names of devices to prefer over detection's own ranking, most preferred first.
+    /// Patterns are matched like `ignore`.
+    pub priority: Vec<String>,
+}
+
+impl FindOptions {
+    /// Whether the device named `name` is left out of detection
+    pub fn ignores(&self, name: &str) -> bool {
+        self.ignore.iter().any(|p| device::glob_matches(p, name))
+    }
+
+    /// The place of the first pattern in the priority list matching `name`
+    pub fn priority_of(&self, name: &str) -> Option<usize> {
+        self.priority
+            .iter()
+            .position(|p| device::glob_matches(p, name))
+    }
+
+    /// A device detection would accept that the priority list ranks above `name`, if any
+    fn preferred_over(&self, name: &str, roots: &[PathBuf]) -> Option<String> {
+        let rank = self.priority_of(name).unwrap_or(usize::MAX);
+        roots
+            .iter()
+            .flat_map(|root| device::list(root).into_iter().map(move |(n, _)| (root, n)))
+            .filter(|(_, n)| self.priority_of(n).is_some_and(|p| p < rank) && !self.ignores(n))
+            .find(|(root, n)| {
+                let d = Device::from(root.join(n));
+                d.is_system_battery() || (self.include_ups && d.is_ups())
+            })
+            .map(|(_, n)| n)
+    }
+
+    /// Leave out ignored devices, logging each one
+    fn not_ignored(&self, d: &Device) -> bool {
+        let name = d.path.file_name().unwrap_or_default().to_string_lossy();
+        let ignored = self.ignores(&name);
+        if ignored {
+            debug!("Ignoring device '{name}'");
+        }
+        !ignored
+    }
+
+    /// Every root to scan, skipping extra roots that can't be read
+    pub(crate) fn roots(&self) -> Vec<PathBuf> {
+        let mut roots = vec![PathBuf::from(POWER_SUPPLY_ROOT)];
+        for root in &self.extra_roots {
+            match std::fs::read_dir(root) {
+                Ok(_) => roots.push(root.clone()),
+                Err(e) => warn!("Skipping device root '{}': {e}", root.display()),
+            }
+        }
+        roots
+    }
+}
 
 #[derive(Debug)]
 pub struct Battery {
     pub name: String,
-    level: PolledValue<u8>,
+    path: PathBuf,
+    family: UnitFamily,
+    current_source: CurrentSource,
+    /// The level reported by the device, or `None` to derive it from the charge
+    level: Option<PolledValue<u8>>,
     capacity: PolledValue<u64>,
     charge: PolledValue<u64>,
-    current: PolledValue<u64>,
+    /// Signed, as some drivers report a negative current while discharging
+    current: PolledValue<i64>,
+    /// Whether the device has been seen reporting a negative current, so its sign
+    /// can be trusted to tell the direction
+    signed_current: bool,
     cycles: PolledValue<u64>,
+    voltage: PolledValue<u64>,
     status: PolledValue<ChargingStatus>,
+    estimator: Estimator,
+    /// The charge over recent updates, kept with the trend estimator
+    trend: ChargeTrend,
+    /// The kernel's own estimates in seconds, for devices that provide them
+    time_to_empty: Option<PolledValue<u64>>,
+    time_to_full: Option<PolledValue<u64>>,
+    schedule: PollSchedule,
+    /// The worker attributes are read through, when reads time out
+    reader: Option<Arc<ReadWorker>>,
+    time_format: TimeFormat,
+    /// The number of updates so far, for the schedule
+    ticks: u64,
+    /// Whether the displayed level is rescaled so the end threshold reads as 100%
+    scale_to_threshold: bool,
+    capacity_ref: CapacityRef,
+    /// The coarse level from the uevent, or the capacity_level attribute
+    capacity_level: Option<String>,
+    /// Whether values with a unit suffix are accepted, see `set_unit_suffixes`
+    unit_suffixes: bool,
+    /// Whether implausibly small voltages and draws are scaled up, see `set_unit_heuristics`
+    unit_heuristics: bool,
+    /// What the voltage and the draw are scaled by, as the heuristics last decided
+    voltage_scale: u64,
+    draw_scale: u64,
+}
+
+/// The capacity the time until full is estimated against
+#[derive(Debug, Clone, Copy, Default, Display, EnumString, PartialEq, Eq)]
+#[strum(serialize_all = "lowercase")]
+pub enum CapacityRef {
+    /// The current full capacity, which shrinks as the battery ages
+    #[default]
+    Full,
+    /// The design capacity, for estimates that stay comparable over the battery's life.
+    /// Falls back to the full capacity for devices that don't report it.
+    Design,
 }
 
-#[derive(Debug, Clone)]
+/// A value a battery reads, for requiring that it could be read (see `Battery::require`)
+/// or asking whether a state's field was (see `BatteryState::is_field_valid`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatteryValue {
+    Level,
+    Capacity,
+    Charge,
+    Current,
+    Cycles,
+    Voltage,
+    Status,
+}
+
+/// A set of `BatteryValue`s, one bit each
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct ValueSet(u8);
+
+impl ValueSet {
+    pub(crate) fn insert(&mut self, value: BatteryValue) {
+        self.0 |= 1 << value as u8;
+    }
+
+    fn contains(self, value: BatteryValue) -> bool {
+        self.0 & (1 << value as u8) != 0
+    }
+
+    pub(crate) fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl FromIterator<BatteryValue> for ValueSet {
+    fn from_iter<I: IntoIterator<Item = BatteryValue>>(values: I) -> Self {
+        let mut set = Self::default();
+        for value in values {
+            set.insert(value);
+        }
+        set
+    }
+}
+
+/// How the remaining time is estimated
+#[derive(Debug, Clone, Copy, Default, Display, EnumString, PartialEq, Eq)]
+#[strum(serialize_all = "lowercase")]
+pub enum Estimator {
+    /// The kernel's estimate when the device provides a sensible one, otherwise computed
+    #[default]
+    Auto,
+    /// Always computed from the charge and the current draw
+    Computed,
+    /// Computed from how fast the charge changed over the last minutes, for devices whose
+    /// current draw is missing or wrong. Only known after a few updates.
+    Trend,
+}
+
+/// A battery's values at one point in time.
+/// Fields may be added in minor releases, so states are made with `Default` or `builder`.
+/// Values that were never read hold a placeholder (see `is_field_valid`) and are
+/// serialized as null.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "StateRepr", from = "StateRepr")]
+#[non_exhaustive]
 pub struct BatteryState {
     pub level: u8,
     pub capacity: u64,
@@ -22,59 +232,460 @@ pub struct BatteryState {
     pub current: u64,
     pub cycles: u64,
     pub status: ChargingStatus,
+    pub family: UnitFamily,
+    /// The voltage in uV, or 0 if the device doesn't report it
+    pub voltage: u64,
+    /// When the state was read
+    pub captured_at: SystemTime,
+    /// The coarse level some devices report instead of (or as well as) a percentage,
+    /// like `Low` or `Critical`
+    pub capacity_level: Option<String>,
+    /// The values that were never read, so hold placeholders
+    pub(crate) unread: ValueSet,
+}
+
+/// How a `BatteryState` is serialized, with null for the values that were never read
+#[derive(Serialize, Deserialize)]
+struct StateRepr {
+    level: Option<u8>,
+    capacity: Option<u64>,
+    charge: Option<u64>,
+    current: Option<u64>,
+    cycles: Option<u64>,
+    status: Option<ChargingStatus>,
+    #[serde(default)]
+    family: UnitFamily,
+    #[serde(default)]
+    voltage: Option<u64>,
+    #[serde(with = "crate::timestamp", default = "crate::timestamp::unknown")]
+    captured_at: SystemTime,
+    #[serde(default)]
+    capacity_level: Option<String>,
+}
+
+impl From<BatteryState> for StateRepr {
+    fn from(state: BatteryState) -> Self {
+        let read = |value| !state.unread.contains(value);
+        Self {
+            level: read(BatteryValue::Level).then_some(state.level),
+            capacity: read(BatteryValue::Capacity).then_some(state.capacity),
+            charge: read(BatteryValue::Charge).then_some(state.charge),
+            current: read(BatteryValue::Current).then_some(state.current),
+            cycles: read(BatteryValue::Cycles).then_some(state.cycles),
+            status: read(BatteryValue::Status).then_some(state.status),
+            family: state.family,
+            voltage: read(BatteryValue::Voltage).then_some(state.voltage),
+            captured_at: state.captured_at,
+            capacity_level: state.capacity_level,
+        }
+    }
+}
+
+impl From<StateRepr> for BatteryState {
+    fn from(repr: StateRepr) -> Self {
+        let mut unread = ValueSet::default();
+        let mut value = |value: Option<_>, field| {
+            if value.is_none() {
+                unread.insert(field);
+            }
+            value.unwrap_or_default()
+        };
+        Self {
+            level: value(repr.level.map(u64::from), BatteryValue::Level) as u8,
+            capacity: value(repr.capacity, BatteryValue::Capacity),
+            charge: value(repr.charge, BatteryValue::Charge),
+            current: value(repr.current, BatteryValue::Current),
+            cycles: value(repr.cycles, BatteryValue::Cycles),
+            voltage: value(repr.voltage, BatteryValue::Voltage),
+            status: repr.status.unwrap_or_else(|| {
+                unread.insert(BatteryValue::Status);
+                ChargingStatus::Unknown
+            }),
+            family: repr.family,
+            captured_at: repr.captured_at,
+            capacity_level: repr.capacity_level,
+            unread,
+        }
+    }
+}
+
+/// An empty, battery-less state: every value 0, an unknown status, the charge family,
+/// no coarse level, and captured at the epoch like states written before timestamps
+impl Default for BatteryState {
+    fn default() -> Self {
+        Self {
+            level: 0,
+            capacity: 0,
+            charge: 0,
+            current: 0,
+            cycles: 0,
+            status: ChargingStatus::Unknown,
+            family: UnitFamily::default(),
+            voltage: 0,
+            captured_at: crate::timestamp::unknown(),
+            capacity_level: None,
+            unread: ValueSet::default(),
+        }
+    }
+}
+
+/// Builds a `BatteryState` from the default one, for tests and programs embedding batmon,
+/// e.g. `BatteryState::builder().level(42).status(ChargingStatus::Discharging).build()`
+#[derive(Debug, Clone, Default)]
+pub struct BatteryStateBuilder {
+    state: BatteryState,
+}
+
+impl BatteryStateBuilder {
+    pub fn level(mut self, level: u8) -> Self {
+        self.state.level = level;
+        self
+    }
+
+    pub fn capacity(mut self, capacity: u64) -> Self {
+        self.state.capacity = capacity;
+        self
+    }
+
+    pub fn charge(mut self, charge: u64) -> Self {
+        self.state.charge = charge;
+        self
+    }
+
+    pub fn current(mut self, current: u64) -> Self {
+        self.state.current = current;
+        self
+    }
+
+    pub fn cycles(mut self, cycles: u64) -> Self {
+        self.state.cycles = cycles;
+        self
+    }
+
+    pub fn status(mut self, status: ChargingStatus) -> Self {
+        self.state.status = status;
+        self
+    }
+
+    pub fn family(mut self, family: UnitFamily) -> Self {
+        self.state.family = family;
+        self
+    }
+
+    pub fn voltage(mut self, voltage: u64) -> Self {
+        self.state.voltage = voltage;
+        self
+    }
+
+    pub fn captured_at(mut self, captured_at: SystemTime) -> Self {
+        self.state.captured_at = captured_at;
+        self
+    }
+
+    pub fn capacity_level(mut self, capacity_level: impl Into<String>) -> Self {
+        self.state.capacity_level = Some(capacity_level.into());
+        self
+    }
+
+    /// Mark `value` as never read, leaving its placeholder
+    pub fn unread(mut self, value: BatteryValue) -> Self {
+        self.state.unread.insert(value);
+        self
+    }
+
+    pub fn build(self) -> BatteryState {
+        self.state
+    }
+}
+
+impl BatteryState {
+    pub fn builder() -> BatteryStateBuilder {
+        BatteryStateBuilder::default()
+    }
+
+    /// Whether `value` was read, rather than being a placeholder (e.g. a charge of 0 for a
+    /// device whose charge couldn't be read). States made by hand have every value read.
+    pub fn is_field_valid(&self, value: BatteryValue) -> bool {
+        !self.unread.contains(value)
+    }
+
+    /// The time until empty, or until full against `capacity`, at the current draw.
+    /// `None` without a draw or a known status, or when the charge or the draw was never
+    /// read; zero when the battery isn't changing.
+    pub fn remaining_at_draw(&self, capacity: u64) -> Option<Duration> {
+        let changing = matches!(
+            self.status,
+            ChargingStatus::Charging | ChargingStatus::Discharging
+        );
+        let read =
+            self.is_field_valid(BatteryValue::Charge) && self.is_field_valid(BatteryValue::Current);
+        if changing && !read {
+            return None;
+        }
+        let total_seconds = match self.status {
+            ChargingStatus::Full | ChargingStatus::NotCharging => 0,
+            ChargingStatus::Unknown => return None,
+            ChargingStatus::Discharging => self
+                .charge
+                .checked_mul(60 * 60)?
+                .checked_div(self.current)?,
+            ChargingStatus::Charging => capacity
+                .saturating_sub(self.charge)
+                .checked_mul(60 * 60)?
+                .checked_div(self.current)?,
+        };
+        Some(Duration::from_secs(total_seconds))
+    }
+
+    /// Whether the level is at or below `threshold` percent
+    pub fn is_low(&self, threshold: u8) -> bool {
+        self.level <= threshold
+    }
+
+    /// Whether the level is at or below `threshold` percent and still dropping
+    pub fn is_critical(&self, threshold: u8) -> bool {
+        self.is_discharging() && self.is_low(threshold)
+    }
+
+    pub fn is_charging(&self) -> bool {
+        self.status == ChargingStatus::Charging
+    }
+
+    pub fn is_discharging(&self) -> bool {
+        self.status == ChargingStatus::Discharging
+    }
+
+    /// The charge as a percentage of the capacity, more precise than the reported level
+    /// where the device reports both
+    pub fn percent_f64(&self) -> f64 {
+        if self.capacity > 0 {
+            (self.charge as f64 * 100.0 / self.capacity as f64).min(100.0)
+        } else {
+            f64::from(self.level)
+        }
+    }
+
+    /// The current draw, positive when charging and negative when discharging
+    pub fn net_flow(&self) -> i64 {
+        let current = i64::try_from(self.current).unwrap_or(i64::MAX);
+        match self.status {
+            ChargingStatus::Charging => current,
+            ChargingStatus::Discharging => -current,
+            _ => 0,
+        }
+    }
+
+    /// Whether the battery will last at least `duration` at the current draw.
+    /// A battery that isn't discharging, or has no draw, always lasts.
+    pub fn will_last(&self, duration: Duration) -> bool {
+        !self.is_discharging()
+            || self
+                .remaining_at_draw(self.capacity)
+                .is_none_or(|remaining| remaining >= duration)
+    }
+
+    /// The full capacity as a percentage of `design`, which must be in the same unit
+    /// (uAh or uWh), or `None` if the design capacity is 0
+    pub fn percent_of_design(&self, design: u64) -> Option<f64> {
+        (design > 0).then(|| self.capacity as f64 * 100.0 / design as f64)
+    }
+
+    /// Convert a charge in the family's units to Wh at `voltage_uv`.
+    /// uAh x uV is 1e-12 Wh, while uWh only needs scaling by 1e-6 and ignores the voltage.
+    fn wh_at(&self, value: u64, voltage_uv: u64) -> f64 {
+        match self.family {
+            UnitFamily::Energy => value as f64 / 1e6,
+            UnitFamily::Charge => value as f64 * voltage_uv as f64 / 1e12,
+        }
+    }
+
+    /// The energy currently stored in Wh at `voltage_uv`.
+    /// Charge (uAh) batteries are converted as uAh x uV / 1e12; energy (uWh) batteries are
+    /// already in energy, so the voltage is ignored and the charge is divided by 1e6.
+    pub fn energy_wh(&self, voltage_uv: u64) -> f64 {
+        self.wh_at(self.charge, voltage_uv)
+    }
+
+    /// The current draw in W at `voltage_uv`.
+    /// Charge batteries report uA, converted as uA x uV / 1e12; energy batteries report uW,
+    /// divided by 1e6 regardless of the voltage.
+    pub fn charge_rate_w(&self, voltage_uv: u64) -> f64 {
+        self.wh_at(self.current, voltage_uv)
+    }
+
+    /// The voltage to convert with, or `None` for a charge battery that doesn't report it
+    fn conversion_voltage(&self) -> Option<u64> {
+        match self.family {
+            UnitFamily::Charge if self.voltage == 0 => None,
+            _ => Some(self.voltage),
+        }
+    }
+
+    /// The current power draw in W
+    pub fn power_w(&self) -> Option<f64> {
+        Some(self.charge_rate_w(self.conversion_voltage()?))
+    }
+
+    /// The energy stored when full in Wh
+    pub fn energy_full_wh(&self) -> Option<f64> {
+        Some(self.wh_at(self.capacity, self.conversion_voltage()?))
+    }
+
+    /// The charge out of the capacity, with a unit (e.g. `3520/4200 mAh`),
+    /// or `?` if either was never read
+    pub fn absolute(&self, precision: usize) -> String {
+        if !self.is_field_valid(BatteryValue::Charge)
+            || !self.is_field_valid(BatteryValue::Capacity)
+        {
+            return String::from("?");
+        }
+        self.family
+            .format_absolute(self.charge, self.capacity, precision)
+    }
+
+    /// The energy currently stored in Wh
+    pub fn energy_now_wh(&self) -> Option<f64> {
+        Some(self.energy_wh(self.conversion_voltage()?))
+    }
 }
 
 impl Battery {
     pub fn find(use_cache: bool) -> Option<Self> {
-        if use_cache && std::fs::metadata("/tmp/batmon-battery").is_ok() {
+        let cache = if use_cache {
+            Cache::default_path()
+        } else {
+            None
+        };
+        Battery::find_with(cache.as_deref(), &FindOptions::default())
+    }
+
+    /// Find the system battery as described by `options`.
+    /// UPS devices are always considered when there is no system battery.
+    /// The battery is remembered in the `cache` file if given, skipping detection next time.
+    /// A system battery named by `BATMON_PRIMARY` is used without detection or the cache.
+    /// Devices `options` ignores are never detected, and an ignored cached device is redetected,
+    /// as is one when a device earlier in the priority list has appeared.
+    pub fn find_with(cache: Option<&Path>, options: &FindOptions) -> Option<Self> {
+        let primary = std::env::var(PRIMARY_ENV).ok();
+        Battery::find_with_in(cache, options, &options.roots(), primary.as_deref())
+    }
+
+    /// Find the battery like `find_with` in `roots`, with `primary` in place of the
+    /// `BATMON_PRIMARY` setting
+    fn find_with_in(
+        cache: Option<&Path>,
+        options: &FindOptions,
+        roots: &[PathBuf],
+        primary: Option<&str>,
+    ) -> Option<Self> {
+        let include_ups = options.include_ups;
+
+        if let Some(primary) = primary.filter(|p| !p.trim().is_empty()) {
+            match Battery::load_primary(primary, roots) {
+                Ok(bat) => {
+                    debug!("Using primary battery '{}' from {PRIMARY_ENV}", bat.name);
+                    return Some(bat);
+                }
+                Err(e) => warn!("Ignoring {PRIMARY_ENV}={primary}: {e}"),
+            }
+        }
+
+        if let Some(path) = cache.filter(|p| p.exists()) {
             debug!("Using cached battery");
-            if let Ok(bat) = Battery::load_cached_battery() {
-                return Some(bat);
+            match Battery::load_cached_battery(path, roots).and_then(|bat| {
+                if options.ignores(&bat.name) {
+                    return Err(format!("'{}' is ignored", bat.name).into());
+                }
+                match options.preferred_over(&bat.name, roots) {
+                    Some(name) => Err(format!("'{name}' is listed before it").into()),
+                    None => Ok(bat),
+                }
+            }) {
+                Ok(bat) => return Some(bat),
+                Err(e) => {
+                    debug!("Failed to create battery from cache ({e}), falling back to autodetect")
+                }
             }
-            debug!("Failed to create battery from cache, falling back to autodetect")
         }
 
-        let devices = std::fs::read_dir("/sys/class/power_supply").ok()?;
+        let devices: Vec<_> = roots
+            .iter()
+            .filter_map(|root| std::fs::read_dir(root).ok())
+            .flatten()
+            .filter_map(|d| d.ok().map(|d| Device::from(d.path())))
+            .filter(|d| options.not_ignored(d))
+            .collect();
+
+        let (bat, rating) = Battery::find_in(&devices, options, |d| {
+            d.is_system_battery() || (include_ups && d.is_ups())
+        })
+        .or_else(|| {
+            if include_ups {
+                return None;
+            }
+            debug!("No system battery found, looking for a UPS");
+            Battery::find_in(&devices, options, Device::is_ups)
+        })?;
+
+        if let Some(path) = cache {
+            match Cache::new(&bat.name, rating).save_if_changed(path) {
+                Ok(true) => debug!("Saved cache"),
+                Ok(false) => debug!("Cache already up to date"),
+                Err(e) => debug!("Failed to save cache: {e}"),
+            }
+        }
+        Some(bat)
+    }
 
+    /// The devices `accept` takes with how they are ranked, most preferred first
+    fn rank<'a>(
+        devices: &'a [Device],
+        options: &FindOptions,
+        accept: impl Fn(&Device) -> bool,
+    ) -> Vec<(&'a Device, Candidate)> {
         let mut devices: Vec<_> = devices
-            .filter_map(|d| d.ok().map(|d| Device::from(d.path())))
-            .filter_map(|d| {
-                if !d.is_system_battery() {
-                    None
-                } else {
-                    let rating = d.rating();
-                    Some((d, rating))
-                }
+            .iter()
+            .filter(|d| accept(d))
+            .map(|d| {
+                let mut candidate = d.candidate();
+                candidate.priority = options.priority_of(&candidate.name);
+                (d, candidate)
             })
             .collect();
+        devices.sort_by(|a, b| device::compare_candidates(&a.1, &b.1).0);
+        devices
+    }
 
-        devices.sort_by_key(|d| d.1);
-
-        for (d, r) in devices {
-            match Battery::try_from(&d) {
+    fn find_in(
+        devices: &[Device],
+        options: &FindOptions,
+        accept: impl Fn(&Device) -> bool,
+    ) -> Option<(Self, u8)> {
+        let devices = Battery::rank(devices, options, accept);
+        for (i, (d, c)) in devices.iter().enumerate() {
+            let r = c.rating.score;
+            match Battery::try_from(*d) {
                 Ok(bat) => {
                     debug!("found battery at device '{}' (rating {r})", bat.name);
-                    if r < 6 {
+                    if let Some((_, next)) = devices.get(i + 1) {
+                        if let (_, Some(rule)) = device::compare_candidates(c, next) {
+                            debug!("preferred '{}' over '{}' by {rule}", c.name, next.name);
+                        }
+                    }
+                    if r < DeviceRating::MAX && !d.is_ups() {
+                        let missing: Vec<_> = c.rating.missing().collect();
                         warn!(
-                            "device '{}' may be missing some features (expected 6, got {r})",
-                            bat.name
+                            "device '{}' may be missing some features (expected {}, got {r}; missing {})",
+                            bat.name,
+                            DeviceRating::MAX,
+                            missing.join(", ")
                         );
                     }
 
-                    if use_cache {
-                        let _ = std::fs::write("/tmp/batmon-battery", &bat.name);
-                    }
-
-                    return Some(bat);
+                    return Some((bat, r));
                 }
                 Err(e) => {
-                    let name = d
-                        .path
-                        .file_name()
-                        .unwrap_or_default()
-                        .to_string_lossy()
-                        .to_string();
-                    debug!("device {name} (rating {r}) failed to init: {e}",);
+                    debug!("device {} (rating {r}) failed to init: {e}", c.name);
                 }
             };
         }
@@ -83,112 +694,747 @@ impl Battery {
     }
 
     pub fn new(name: &str) -> Result<Battery, Box<dyn std::error::Error>> {
-        let mut path = std::path::PathBuf::from_str("/sys/class/power_supply")?;
-        path.push(name.trim());
+        Battery::new_in(Path::new(POWER_SUPPLY_ROOT), name)
+    }
+
+    /// Load the device called `name` in the directory `root`
+    fn new_in(root: &Path, name: &str) -> Result<Battery, Box<dyn std::error::Error>> {
+        let mut name = device::normalize_name(name).to_owned();
+        let valid = !name.is_empty() && !name.contains('/');
+        let mut case_matches = Vec::new();
+        if valid && std::fs::metadata(root.join(&name)).is_err() {
+            case_matches = device::matches_ignoring_case(root, &name);
+            // Only an unambiguous match is safe to use in place of the name given
+            if case_matches.len() == 1 {
+                let only = case_matches.remove(0);
+                info!("There is no device named '{name}', using '{only}'");
+                name = only;
+            }
+        }
+
+        let path = root.join(&name);
+        if !valid || std::fs::metadata(&path).is_err() {
+            let available = device::list(root);
+            let suggestion = device::closest_name(&name, available.iter().map(|(n, _)| n.as_str()))
+                .map(str::to_owned);
+            return Err(BatmonError::UnknownDevice {
+                name,
+                available,
+                suggestion,
+                case_matches,
+            }
+            .into());
+        }
+
+        let ty = std::fs::read_to_string(path.join("type")).unwrap_or_default();
+        if !matches!(ty.trim(), "Battery" | "UPS") {
+            return Err(BatmonError::InvalidDevice {
+                name: name.to_owned(),
+                reason: format!("it is a {} device", ty.trim()),
+            }
+            .into());
+        }
 
         let device = Device::from(path);
-        let rating = device.rating();
+        // Like a peripheral's battery, which powers something other than the system
+        if !device.is_selectable_battery() && !device.is_ups() {
+            return Err(BatmonError::InvalidDevice {
+                name: name.to_owned(),
+                reason: String::from("it is not a system battery"),
+            }
+            .into());
+        }
+        let rating = device.rating_detailed();
 
         let b = Battery::try_from(&device)?;
 
-        if rating < 6 {
+        if rating.score < DeviceRating::MAX && !device.is_ups() {
+            let missing: Vec<_> = rating.missing().collect();
             warn!(
-                "Cached device '{}' may be missing features (expected 6, got {rating})",
-                device
-                    .path
-                    .file_name()
-                    .unwrap_or_default()
-                    .to_string_lossy()
+                "Cached device '{}' may be missing features (expected {}, got {}; missing {})",
+                b.name,
+                DeviceRating::MAX,
+                rating.score,
+                missing.join(", ")
             );
         }
 
         Ok(b)
     }
 
-    fn load_cached_battery() -> Result<Battery, Box<dyn std::error::Error>> {
-        let bat = std::fs::read_to_string("/tmp/batmon-battery")?;
-        Battery::new(&bat)
+    /// Load the named device from the first root that has it, if it is a system battery
+    fn load_primary(name: &str, roots: &[PathBuf]) -> Result<Battery, Box<dyn std::error::Error>> {
+        let name = device::normalize_name(name);
+        let Some(root) = roots.iter().find(|r| r.join(name).exists()) else {
+            // Reports the devices that do exist
+            return Battery::new(name);
+        };
+        if !Device::from(root.join(name)).is_selectable_battery() {
+            return Err(BatmonError::InvalidDevice {
+                name: name.to_owned(),
+                reason: String::from("it is not a system battery"),
+            }
+            .into());
+        }
+        Battery::new_in(root, name)
+    }
+
+    /// Every system battery (and UPS, with `include_ups`) in the roots `options` scans, most
+    /// preferred first as ranked by detection. Devices `options` ignores are left out.
+    pub fn find_all(options: &FindOptions) -> Vec<Battery> {
+        Battery::find_all_in(&options.roots(), options)
+    }
+
+    fn find_all_in(roots: &[PathBuf], options: &FindOptions) -> Vec<Battery> {
+        let devices: Vec<_> = roots
+            .iter()
+            .flat_map(|root| {
+                device::list(root)
+                    .into_iter()
+                    .map(move |(name, _)| Device::from(root.join(name)))
+            })
+            .filter(|d| options.not_ignored(d))
+            .collect();
+        Battery::rank(&devices, options, |d| {
+            d.is_system_battery() || (options.include_ups && d.is_ups())
+        })
+        .into_iter()
+        .filter_map(|(d, _)| match Battery::try_from(d) {
+            Ok(bat) => Some(bat),
+            Err(e) => {
+                debug!("Skipping battery {}: {e}", d.path.to_string_lossy());
+                None
+            }
+        })
+        .collect()
+    }
+
+    /// The battery at `index` in the order of `find_all`, so scripts can pick the first or
+    /// second battery without knowing their names. Without any battery, this fails like
+    /// detection does.
+    pub fn find_index(options: &FindOptions, index: usize) -> Result<Battery, BatmonError> {
+        Battery::find_index_in(&options.roots(), options, index)
+    }
+
+    fn find_index_in(
+        roots: &[PathBuf],
+        options: &FindOptions,
+        index: usize,
+    ) -> Result<Battery, BatmonError> {
+        let mut batteries = Battery::find_all_in(roots, options);
+        match batteries.len() {
+            0 => Err(BatmonError::NoBattery),
+            found if index >= found => Err(BatmonError::NoSuchIndex { index, found }),
+            _ => Ok(batteries.swap_remove(index)),
+        }
+    }
+
+    /// Load the cached device from the first root that has a device with its name
+    fn load_cached_battery(
+        path: &Path,
+        roots: &[PathBuf],
+    ) -> Result<Battery, Box<dyn std::error::Error>> {
+        let name = Cache::load(path)?.name;
+        let root = roots
+            .iter()
+            .find(|r| r.join(device::normalize_name(&name)).exists())
+            .ok_or("Device does not exist")?;
+        Battery::new_in(root, &name)
+    }
+
+    /// Read the current draw from `source` instead of the automatically chosen attribute
+    pub fn set_current_source(&mut self, source: CurrentSource) {
+        self.current_source = match source {
+            CurrentSource::Auto => Battery::resolve_current_source(&self.path, self.family),
+            _ => source,
+        };
+        self.current = PolledValue::new(
+            0,
+            self.path.join(self.current_source.attribute(self.family)),
+        );
+        self.current.set_reader(self.reader.clone());
+        if self.unit_suffixes {
+            self.current
+                .set_unit(Some(self.current_source.unit(self.family)));
+        }
+
+        if let Err(e) = self.current.update() {
+            debug!("Failed to update current: {e}");
+        }
+        self.apply_unit_heuristics();
+    }
+
+    fn resolve_current_source(path: &std::path::Path, family: UnitFamily) -> CurrentSource {
+        let native = CurrentSource::Auto.attribute(family);
+        if std::fs::metadata(path.join(native)).is_ok() {
+            return CurrentSource::Auto;
+        }
+
+        match family {
+            UnitFamily::Charge => CurrentSource::Power,
+            UnitFamily::Energy => CurrentSource::Current,
+        }
+    }
+
+    /// The current draw in the unit of the battery's family
+    fn current(&self) -> u64 {
+        let raw = u128::from(self.current.unsigned_abs()) * u128::from(self.draw_scale);
+        let voltage = u128::from(self.voltage());
+        let converted = match (self.family, self.current_source) {
+            // uW -> uA
+            (UnitFamily::Charge, CurrentSource::Power) => {
+                (raw * 1_000_000).checked_div(voltage).unwrap_or_default()
+            }
+            // uA -> uW
+            (UnitFamily::Energy, CurrentSource::Current) => raw * voltage / 1_000_000,
+            _ => raw,
+        };
+        u64::try_from(converted).unwrap_or(u64::MAX)
+    }
+
+    /// The voltage in uV, scaled up for drivers that report it in V or mV
+    fn voltage(&self) -> u64 {
+        self.voltage.saturating_mul(self.voltage_scale)
+    }
+
+    /// Scale up the voltage and the draw where they are implausibly small for uV and uA (or uW),
+    /// logging whenever the scale changes
+    fn apply_unit_heuristics(&mut self) {
+        if !self.unit_heuristics {
+            return;
+        }
+
+        let voltage_scale = units::voltage_scale(*self.voltage);
+        if voltage_scale != self.voltage_scale {
+            debug!(
+                "Scaling voltage_now of {} by {voltage_scale}, as {} uV is implausible",
+                self.name, *self.voltage
+            );
+            self.voltage_scale = voltage_scale;
+        }
+
+        let previous = std::mem::replace(&mut self.draw_scale, 1);
+        let draw_scale = units::draw_scale(self.current(), *self.capacity);
+        if draw_scale != previous {
+            debug!(
+                "Scaling the draw of {} by {draw_scale}, as it would take over a month to drain",
+                self.name
+            );
+        }
+        self.draw_scale = draw_scale;
+    }
+
+    /// Turn the heuristics that scale up voltages and draws reported in V, mV, mA or mW
+    /// (by out-of-tree drivers) on or off. They are on by default, and leave values
+    /// mainline drivers can report alone.
+    pub fn set_unit_heuristics(&mut self, enabled: bool) {
+        self.unit_heuristics = enabled;
+        self.voltage_scale = 1;
+        self.draw_scale = 1;
+        self.apply_unit_heuristics();
+    }
+
+    /// The level as a percentage, computed from the charge and capacity for devices that
+    /// don't report it (or whose level can't be read), or `None` if it has never been read
+    fn known_level(&self) -> Option<u8> {
+        match &self.level {
+            Some(level) if level.is_read() => Some(**level),
+            _ if self.charge.is_read() => self
+                .charge
+                .saturating_mul(100)
+                .checked_div(*self.capacity)
+                .map(|l| l.min(100) as u8),
+            _ => None,
+        }
+    }
+
+    /// Fail unless every one of `values` has been read and parsed, naming the attributes of
+    /// those that haven't, rather than carrying on with their placeholders
+    pub fn require(&self, values: &[BatteryValue]) -> Result<(), BatmonError> {
+        let mut attributes: Vec<String> = Vec::new();
+        for value in values {
+            let unread = match value {
+                BatteryValue::Level if !self.level_known() => match &self.level {
+                    Some(level) => vec![level.attribute()],
+                    None => vec![self.charge.attribute(), self.capacity.attribute()],
+                },
+                BatteryValue::Capacity if !self.capacity.is_read() => {
+                    vec![self.capacity.attribute()]
+                }
+                BatteryValue::Charge if !self.charge.is_read() => vec![self.charge.attribute()],
+                BatteryValue::Current if !self.current.is_read() => {
+                    vec![self.current.attribute()]
+                }
+                BatteryValue::Cycles if !self.cycles.is_read() => vec![self.cycles.attribute()],
+                BatteryValue::Voltage if !self.voltage.is_read() => {
+                    vec![self.voltage.attribute()]
+                }
+                BatteryValue::Status if !self.status.is_read() => vec![self.status.attribute()],
+                _ => Vec::new(),
+            };
+            for attribute in unread {
+                if !attributes.contains(&attribute) {
+                    attributes.push(attribute);
+                }
+            }
+        }
+
+        if attributes.is_empty() {
+            Ok(())
+        } else {
+            Err(BatmonError::Unreadable {
+                name: self.name.clone(),
+                attributes,
+            })
+        }
+    }
+
+    /// The attribute files whose last read was denied for lack of permission
+    pub fn denied(&self) -> Vec<PathBuf> {
+        let values = [
+            self.level.as_ref().map(|l| (l.is_denied(), l.path())),
+            Some((self.capacity.is_denied(), self.capacity.path())),
+            Some((self.charge.is_denied(), self.charge.path())),
+            Some((self.current.is_denied(), self.current.path())),
+            Some((self.cycles.is_denied(), self.cycles.path())),
+            Some((self.voltage.is_denied(), self.voltage.path())),
+            Some((self.status.is_denied(), self.status.path())),
+            self.time_to_empty
+                .as_ref()
+                .map(|e| (e.is_denied(), e.path())),
+            self.time_to_full
+                .as_ref()
+                .map(|e| (e.is_denied(), e.path())),
+        ];
+        values
+            .into_iter()
+            .flatten()
+            .filter(|(denied, _)| *denied)
+            .map(|(_, path)| path.to_owned())
+            .collect()
+    }
+
+    /// Whether the level has been read, as opposed to the device not reporting it
+    /// or every read so far having failed
+    pub fn level_known(&self) -> bool {
+        self.known_level().is_some()
+    }
+
+    /// The level as a percentage, computed from the charge and capacity for devices that
+    /// don't report it. This is never rescaled to the end threshold,
+    /// and is 0 until the level has been read (see `level_known`).
+    pub fn raw_level(&self) -> u8 {
+        self.known_level().unwrap_or(0)
+    }
+
+    /// The level as displayed, which is the raw level rescaled so the end threshold reads
+    /// as 100% when scaling to the threshold
+    fn level(&self) -> u8 {
+        let level = self.raw_level();
+        if !self.scale_to_threshold {
+            return level;
+        }
+        match self.end_threshold().filter(|&end| end > 0 && end < 100) {
+            Some(end) => (u16::from(level) * 100 / u16::from(end)).min(100) as u8,
+            None => level,
+        }
+    }
+
+    /// Whether the device reports a numeric level, or enough to derive one
+    pub fn reports_level(&self) -> bool {
+        self.level.is_some() || *self.capacity > 0
+    }
+
+    /// Rescale the level so the charge control end threshold reads as 100%.
+    /// This is only a display transform: `raw_level` still reads the device's level,
+    /// and devices without an end threshold are unaffected.
+    pub fn set_scale_to_threshold(&mut self, scale: bool) {
+        self.scale_to_threshold = scale;
+    }
+
+    /// The status, inferred from the sign of the current when the device doesn't know it.
+    /// A positive current only means charging on devices known to report signed currents.
+    /// A status that was read is kept even when the level is unknown, which `unread` reports.
+    fn status(&self) -> ChargingStatus {
+        match *self.status {
+            ChargingStatus::Unknown if *self.current < 0 => ChargingStatus::Discharging,
+            ChargingStatus::Unknown if self.signed_current && *self.current > 0 => {
+                ChargingStatus::Charging
+            }
+            status => status,
+        }
     }
 
     pub fn state(&self) -> BatteryState {
+        self.state_at(&SystemClock)
+    }
+
+    /// The state, captured at the time `clock` gives
+    pub fn state_at(&self, clock: &impl Clock) -> BatteryState {
         BatteryState {
-            level: *self.level,
+            level: self.level(),
             capacity: *self.capacity,
             charge: *self.charge,
-            current: *self.current,
+            current: self.current(),
             cycles: *self.cycles,
-            status: *self.status,
+            status: self.status(),
+            family: self.family,
+            voltage: self.voltage(),
+            captured_at: clock.now(),
+            capacity_level: self.capacity_level.clone(),
+            unread: self.unread(),
         }
     }
 
-    pub fn update(&mut self) {
-        if let Err(e) = self.level.update() {
-            debug!("Failed to update charge level: {e}");
+    /// The values that have never been read
+    fn unread(&self) -> ValueSet {
+        [
+            (BatteryValue::Level, self.level_known()),
+            (BatteryValue::Capacity, self.capacity.is_read()),
+            (BatteryValue::Charge, self.charge.is_read()),
+            (BatteryValue::Current, self.current.is_read()),
+            (BatteryValue::Cycles, self.cycles.is_read()),
+            (BatteryValue::Voltage, self.voltage.is_read()),
+            (BatteryValue::Status, self.status.is_read()),
+        ]
+        .into_iter()
+        .filter_map(|(value, read)| (!read).then_some(value))
+        .collect()
+    }
+
+    /// Accept capacities, charges, draws and voltages with a unit suffix (e.g. `4200 mV`),
+    /// as written by some nonstandard drivers, converting them to the usual micro-units.
+    /// Off by default, where such values fail to parse.
+    pub fn set_unit_suffixes(&mut self, enabled: bool) {
+        self.unit_suffixes = enabled;
+        let unit = |unit| enabled.then_some(unit);
+        let charge_unit = self.family.charge_unit();
+        self.capacity.set_unit(unit(charge_unit));
+        self.charge.set_unit(unit(charge_unit));
+        self.current
+            .set_unit(unit(self.current_source.unit(self.family)));
+        self.voltage.set_unit(unit("uV"));
+
+        for (name, value) in [
+            ("capacity", &mut self.capacity),
+            ("charge", &mut self.charge),
+            ("voltage", &mut self.voltage),
+        ] {
+            if let Err(e) = value.update() {
+                debug!("Failed to update {name}: {e}");
+            }
+        }
+        if let Err(e) = self.current.update() {
+            debug!("Failed to update current: {e}");
         }
+        self.apply_unit_heuristics();
+    }
+
+    /// Choose how often each attribute is re-read by `update`
+    pub fn set_schedule(&mut self, schedule: PollSchedule) {
+        self.schedule = schedule;
+    }
 
-        if let Err(e) = self.capacity.update() {
-            debug!("Failed to update capacity: {e}");
+    /// Give up on reads by `update` that take longer than `timeout`, counting them as failed and
+    /// keeping the last value, so a hanging attribute can't stall the caller. Without a timeout,
+    /// as by default, reads wait for as long as they take.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.reader = timeout.and_then(|timeout| match ReadWorker::spawn(timeout) {
+            Ok(reader) => Some(reader),
+            Err(e) => {
+                warn!("Reading without a timeout, as the read thread couldn't start: {e}");
+                None
+            }
+        });
+        let reader = &self.reader;
+        if let Some(level) = &mut self.level {
+            level.set_reader(reader.clone());
+        }
+        self.capacity.set_reader(reader.clone());
+        self.charge.set_reader(reader.clone());
+        self.current.set_reader(reader.clone());
+        self.cycles.set_reader(reader.clone());
+        self.voltage.set_reader(reader.clone());
+        self.status.set_reader(reader.clone());
+        for estimate in [&mut self.time_to_empty, &mut self.time_to_full]
+            .into_iter()
+            .flatten()
+        {
+            estimate.set_reader(reader.clone());
+        }
+    }
+
+    /// Re-read the battery, returning the names of the values that couldn't be read.
+    /// Attributes the device doesn't provide at all aren't counted as failures.
+    pub fn update(&mut self) -> Vec<&'static str> {
+        self.update_at(&SystemClock)
+    }
+
+    /// Re-read the battery like `update`, taking the time it was read from `clock`
+    pub fn update_at(&mut self, clock: &impl Clock) -> Vec<&'static str> {
+        let slow = self.schedule.slow_due(self.ticks);
+        self.ticks = self.ticks.wrapping_add(1);
+        let mut failed = Vec::new();
+
+        if let Some(level) = &mut self.level {
+            if let Err(e) = level.update() {
+                read_failed(&mut failed, "level", e);
+            }
+        }
+
+        if slow {
+            if let Err(e) = self.capacity.update() {
+                read_failed(&mut failed, "capacity", e);
+            }
+
+            if let Err(e) = self.cycles.update() {
+                read_failed(&mut failed, "cycles", e);
+            }
         }
 
         if let Err(e) = self.charge.update() {
-            debug!("Failed to update charge: {e}");
+            read_failed(&mut failed, "charge", e);
         }
 
         if let Err(e) = self.current.update() {
-            debug!("Failed to update current: {e}");
+            read_failed(&mut failed, "current", e);
         }
+        self.signed_current |= *self.current < 0;
 
         if let Err(e) = self.status.update() {
-            debug!("Failed to update status: {e}");
+            read_failed(&mut failed, "status", e);
+        }
+
+        if let Err(e) = self.voltage.update() {
+            read_failed(&mut failed, "voltage", e);
+        }
+        self.apply_unit_heuristics();
+
+        // Reading the uevent makes the driver evaluate every property, so the coarse level
+        // is only read often on devices without a numeric one
+        if slow || !self.level_known() {
+            match self.read_capacity_level() {
+                Ok(level) => self.capacity_level = level,
+                Err(e) => debug!("Keeping the last capacity level: {e}"),
+            }
+        }
+
+        for estimate in [&mut self.time_to_empty, &mut self.time_to_full]
+            .into_iter()
+            .flatten()
+        {
+            if let Err(e) = estimate.update() {
+                read_failed(&mut failed, "kernel time estimate", e);
+            }
         }
 
-        if let Err(e) = self.cycles.update() {
-            debug!("Failed to update cycles: {e}");
+        if self.estimator == Estimator::Trend {
+            let state = self.state_at(clock);
+            self.trend.push(&state);
         }
+        failed
     }
 
-    pub fn remaining(&self) -> String {
-        let charge = *self.charge;
-        let capacity = *self.capacity;
-        let current = *self.current;
-        let total_seconds = match *self.status {
-            ChargingStatus::Full => return String::from("00:00:00"),
-            ChargingStatus::NotCharging => return String::from("00:00:00"),
-            ChargingStatus::Discharging => {
-                (charge * 60 * 60).checked_div(current).unwrap_or_default()
+    /// Read the attribute `name`, through the read worker when reads time out
+    fn read_attribute(&self, name: &str) -> std::io::Result<String> {
+        let path = self.path.join(name);
+        match &self.reader {
+            Some(reader) => reader.read(&path),
+            None => std::fs::read_to_string(path),
+        }
+    }
+
+    /// The coarse level from the uevent, or the capacity_level attribute.
+    /// Fails only when a read timed out, so the last level can be kept.
+    fn read_capacity_level(&self) -> std::io::Result<Option<String>> {
+        let timed_out = |e: &std::io::Error| e.kind() == std::io::ErrorKind::TimedOut;
+        let uevent = match self.read_attribute("uevent") {
+            Ok(data) => UeventData::parse(&data).unwrap_or_default(),
+            Err(e) if timed_out(&e) => return Err(e),
+            Err(_) => UeventData::default(),
+        };
+        let level = match uevent.capacity_level() {
+            Some(level) => Some(level.to_owned()),
+            None => match self.read_attribute("capacity_level") {
+                Ok(level) => Some(level),
+                Err(e) if timed_out(&e) => return Err(e),
+                Err(_) => None,
+            },
+        };
+        Ok(level.map(|l| l.trim().to_owned()).filter(|l| !l.is_empty()))
+    }
+
+    /// The family of units the battery reports in
+    pub fn family(&self) -> UnitFamily {
+        self.family
+    }
+
+    /// Read the capacity the battery was designed to hold, in the unit of its family
+    pub fn design_capacity(&self) -> Option<u64> {
+        let data = std::fs::read_to_string(self.path.join(self.design_attribute())).ok()?;
+        data.trim().parse().ok()
+    }
+
+    /// The attribute holding the design capacity
+    pub fn design_attribute(&self) -> &'static str {
+        match self.family {
+            UnitFamily::Charge => "charge_full_design",
+            UnitFamily::Energy => "energy_full_design",
+        }
+    }
+
+    /// The full capacity as a percentage of the design capacity
+    pub fn health(&self) -> Option<f64> {
+        self.state().percent_of_design(self.design_capacity()?)
+    }
+
+    /// Every entry of the device's directory by name, with what batmon makes of it
+    pub fn attributes(&self) -> Vec<(String, AttributeValue)> {
+        Device::from(self.path.clone()).attributes()
+    }
+
+    /// Read the exact contents of one of the device's attributes, without parsing or trimming
+    pub fn read_raw(&self, attribute: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        // Only allow plain file names so the device directory can't be escaped. Any `..` is
+        // refused, so names that are only decoded into a path later (`..%2F`) are caught too.
+        if attribute.is_empty()
+            || attribute == "."
+            || attribute.contains("..")
+            || attribute.contains(['/', '\0'])
+        {
+            return Err(format!("'{attribute}' is not a valid attribute name").into());
+        }
+        std::fs::read(self.path.join(attribute)).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => BatmonError::Unsupported {
+                attribute: attribute.to_owned(),
             }
-            ChargingStatus::Charging => ((capacity - charge) * 60 * 60)
-                .checked_div(current)
-                .unwrap_or_default(),
+            .into(),
+            std::io::ErrorKind::PermissionDenied => BatmonError::PermissionDenied {
+                attribute: attribute.to_owned(),
+            }
+            .into(),
+            _ => e.into(),
+        })
+    }
+
+    /// Read the charge level at which the firmware stops charging, if the device supports it
+    pub fn end_threshold(&self) -> Option<u8> {
+        let data = std::fs::read_to_string(self.path.join("charge_control_end_threshold")).ok()?;
+        data.trim().parse().ok()
+    }
+
+    /// Whether charging is deliberately held because the level reached the end threshold
+    fn held_by_threshold(&self) -> bool {
+        self.status() == ChargingStatus::NotCharging
+            && self
+                .end_threshold()
+                .is_some_and(|end| self.raw_level() >= end)
+    }
+
+    /// Choose how the remaining time is estimated
+    pub fn set_estimator(&mut self, estimator: Estimator) {
+        self.estimator = estimator;
+    }
+
+    /// Choose the capacity the time until full is estimated against
+    pub fn set_capacity_ref(&mut self, capacity_ref: CapacityRef) {
+        self.capacity_ref = capacity_ref;
+    }
+
+    /// The kernel's estimate for the current direction, if the device provides a sensible one.
+    /// The kernel estimates charging against the full capacity, so it isn't used for others.
+    fn kernel_remaining(&self) -> Option<Duration> {
+        let estimate = match self.status() {
+            ChargingStatus::Discharging => self.time_to_empty.as_ref()?,
+            ChargingStatus::Charging if self.capacity_ref == CapacityRef::Full => {
+                self.time_to_full.as_ref()?
+            }
+            _ => return None,
         };
+        match **estimate {
+            0 => None,
+            secs if secs > MAX_KERNEL_ESTIMATE => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    /// The estimated time until the battery is either charged or discharged,
+    /// or `None` if there is no current draw to estimate it from.
+    /// Prefers the kernel's estimate, which accounts for load history, unless told otherwise.
+    pub fn remaining_duration(&self) -> Option<Duration> {
+        self.remaining_duration_at(&SystemClock)
+    }
 
-        let s = total_seconds % 60;
-        let m = (total_seconds / 60) % 60;
-        let h = total_seconds / 60 / 60;
+    /// The estimated time like `remaining_duration`, as of the time `clock` gives
+    pub fn remaining_duration_at(&self, clock: &impl Clock) -> Option<Duration> {
+        if self.estimator == Estimator::Auto {
+            if let Some(remaining) = self.kernel_remaining() {
+                return Some(remaining);
+            }
+        }
 
-        format!("{h:0>2}:{m:0>2}:{s:0>2}")
+        let capacity = match self.capacity_ref {
+            CapacityRef::Full => *self.capacity,
+            CapacityRef::Design => self.design_capacity().unwrap_or(*self.capacity),
+        };
+        match self.estimator {
+            Estimator::Trend => self.trend.remaining_at(clock, capacity),
+            _ => self.state_at(clock).remaining_at_draw(capacity),
+        }
+    }
+
+    /// The remaining time as HH:MM:SS, or Unknown if it can't be estimated
+    /// (e.g. a UPS that doesn't report its charge or current)
+    pub fn remaining(&self) -> String {
+        match self.remaining_duration() {
+            Some(remaining) => self.time_format.format(remaining),
+            None => String::from("Unknown"),
+        }
+    }
+
+    /// Choose how `remaining` and the summary write the remaining time
+    pub fn set_time_format(&mut self, format: TimeFormat) {
+        self.time_format = format;
     }
 
     pub fn remaining_labelled(&self) -> String {
-        let label = match *self.status {
-            ChargingStatus::Full => return String::from("Full"),
-            ChargingStatus::NotCharging => return String::from("Not charging"),
-            ChargingStatus::Charging => "until full",
-            ChargingStatus::Discharging => "remaining",
-        };
-        format!("{} {}", self.remaining(), label)
+        crate::source::label_remaining(
+            self.status(),
+            self.held_by_threshold(),
+            self.remaining_duration(),
+            self.time_format,
+        )
+    }
+}
+
+/// Log a failed read of `value`, recording it unless the device doesn't provide the attribute
+fn read_failed(failed: &mut Vec<&'static str>, value: &'static str, e: Box<dyn std::error::Error>) {
+    debug!("Failed to update {value}: {e}");
+    let missing = e
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound);
+    if !missing {
+        failed.push(value);
     }
 }
 
 impl std::fmt::Display for Battery {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{} ({}) @ {}%, {}",
-            self.name, *self.cycles, *self.level, *self.status,
-        )?;
-        match *self.status {
-            ChargingStatus::Discharging | ChargingStatus::Charging => {
+        match self.cycles.is_read() {
+            true => write!(f, "{} ({}) @ ", self.name, *self.cycles)?,
+            false => write!(f, "{} (?) @ ", self.name)?,
+        }
+        match self.capacity_level.as_deref() {
+            _ if self.level_known() => write!(f, "{}%", self.level())?,
+            Some(level) => write!(f, "{level}")?,
+            None => write!(f, "?%")?,
+        }
+        write!(f, ", {}", self.status())?;
+        match self.status() {
+            ChargingStatus::Discharging | ChargingStatus::Charging | ChargingStatus::Unknown => {
+                write!(f, ", {}", self.remaining_labelled())
+            }
+            ChargingStatus::NotCharging if self.held_by_threshold() => {
                 write!(f, ", {}", self.remaining_labelled())
             }
             _ => Ok(()),
@@ -203,8 +1449,8 @@ impl TryFrom<&Device> for Battery {
             return Err("Device does not exist".into());
         }
 
-        if !device.is_system_battery() {
-            return Err("Device is not a system battery".into());
+        if !device.is_selectable_battery() && !device.is_ups() {
+            return Err("Device is not a system battery or UPS".into());
         }
 
         let name = device
@@ -213,17 +1459,712 @@ impl TryFrom<&Device> for Battery {
             .unwrap_or_default()
             .to_string_lossy()
             .to_string();
+        let family = device.family();
+        let (capacity, _, _) = family.attributes();
+        let charge = device.charge_attribute();
+        let current_source = Battery::resolve_current_source(&device.path, family);
+        // The instantaneous estimate is preferred over the average where both exist
+        let estimate = |attributes: [&str; 2]| {
+            attributes
+                .into_iter()
+                .map(|a| device.path.join(a))
+                .find(|p| p.exists())
+                .map(|p| PolledValue::new(0, p))
+        };
+
         let mut bat = Battery {
             name,
-            level: PolledValue::new(100, device.path.join("capacity")),
-            capacity: PolledValue::new(0, device.path.join("charge_full")),
-            charge: PolledValue::new(0, device.path.join("charge_now")),
-            current: PolledValue::new(0, device.path.join("current_now")),
+            path: device.path.clone(),
+            family,
+            current_source,
+            level: Some(device.path.join("capacity"))
+                .filter(|p| p.exists())
+                .map(|p| PolledValue::new(0, p)),
+            capacity: PolledValue::new(0, device.path.join(capacity)),
+            charge: PolledValue::new(0, device.path.join(charge)),
+            current: PolledValue::new(0, device.path.join(current_source.attribute(family))),
+            signed_current: false,
             cycles: PolledValue::new(0, device.path.join("cycle_count")),
-            status: PolledValue::new(ChargingStatus::Full, device.path.join("status")),
+            voltage: PolledValue::new(0, device.path.join("voltage_now")),
+            status: PolledValue::new(ChargingStatus::Unknown, device.path.join("status")),
+            estimator: Estimator::Auto,
+            trend: ChargeTrend::default(),
+            time_to_empty: estimate(["time_to_empty_now", "time_to_empty_avg"]),
+            time_to_full: estimate(["time_to_full_now", "time_to_full_avg"]),
+            schedule: PollSchedule::default(),
+            reader: None,
+            time_format: TimeFormat::default(),
+            ticks: 0,
+            scale_to_threshold: false,
+            capacity_ref: CapacityRef::Full,
+            capacity_level: None,
+            unit_suffixes: false,
+            unit_heuristics: true,
+            voltage_scale: 1,
+            draw_scale: 1,
         };
 
         bat.update();
         Ok(bat)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{make_fifo, FakeRoot};
+    use std::io::Write;
+    use std::time::Instant;
+
+    #[test]
+    fn status_is_kept_when_the_level_is_unreadable() {
+        let root = FakeRoot::new();
+        let dir = root.battery("BAT0", 55);
+        for attribute in ["capacity", "charge_now"] {
+            std::fs::write(dir.join(attribute), "garbage\n").unwrap();
+        }
+        std::fs::write(dir.join("status"), "Charging\n").unwrap();
+        let state = Battery::new_in(&root.path, "BAT0").unwrap().state();
+        assert_eq!(state.status, ChargingStatus::Charging);
+        assert!(!state.is_field_valid(BatteryValue::Level));
+    }
+
+    #[test]
+    fn failed_first_update_is_not_full() {
+        let root = FakeRoot::new();
+        let dir = root.battery("BAT0", 100);
+        for attribute in ["capacity", "charge_now", "charge_full", "status"] {
+            std::fs::write(dir.join(attribute), "garbage\n").unwrap();
+        }
+        let bat = Battery::new_in(&root.path, "BAT0").unwrap();
+        let state = bat.state();
+        assert!(!bat.level_known());
+        assert!(!state.is_field_valid(BatteryValue::Level));
+        assert_eq!(state.status, ChargingStatus::Unknown);
+        assert_ne!(state.level, 100);
+    }
+
+    fn names(batteries: &[Battery]) -> Vec<&str> {
+        batteries.iter().map(|bat| bat.name.as_str()).collect()
+    }
+
+    #[test]
+    fn preferred_device_comes_first_despite_a_lower_rating() {
+        let root = FakeRoot::new();
+        root.battery("BAT0", 55);
+        let small = root.battery("BAT1", 80);
+        std::fs::remove_file(small.join("cycle_count")).unwrap();
+        let roots = [root.path.clone()];
+
+        let options = FindOptions::default();
+        assert_eq!(
+            names(&Battery::find_all_in(&roots, &options)),
+            ["BAT0", "BAT1"]
+        );
+
+        let options = FindOptions {
+            priority: vec![String::from("BAT1")],
+            ..Default::default()
+        };
+        assert_eq!(
+            names(&Battery::find_all_in(&roots, &options)),
+            ["BAT1", "BAT0"]
+        );
+        let devices = [Device::from(root.path.join("BAT0")), Device::from(small)];
+        let (bat, _) = Battery::find_in(&devices, &options, Device::is_system_battery).unwrap();
+        assert_eq!(bat.name, "BAT1");
+    }
+
+    #[test]
+    fn unscoped_peripheral_loses_despite_a_higher_rating() {
+        let root = FakeRoot::new();
+        let bat = root.battery("BAT0", 55);
+        std::fs::remove_file(bat.join("cycle_count")).unwrap();
+        let gamepad = root.battery("sony_controller_battery_aa:bb:cc:dd:ee:ff", 80);
+        std::fs::remove_file(gamepad.join("scope")).unwrap();
+
+        let roots = [root.path.clone()];
+        let options = FindOptions::default();
+        assert_eq!(names(&Battery::find_all_in(&roots, &options)), ["BAT0"]);
+    }
+
+    #[test]
+    fn best_rated_device_is_detected() {
+        let root = FakeRoot::new();
+        // Listed first, and with only a level and a status
+        let weak = root.device(
+            "AAA0",
+            &[
+                ("type", "Battery"),
+                ("scope", "System"),
+                ("status", "Discharging"),
+                ("capacity", "40"),
+            ],
+        );
+        let full = root.battery("BAT0", 55);
+        assert_eq!(Device::from(weak.clone()).rating(), 2);
+        assert_eq!(Device::from(full.clone()).rating(), 6);
+
+        let options = FindOptions::default();
+        let devices = [Device::from(weak), Device::from(full)];
+        let (bat, rating) =
+            Battery::find_in(&devices, &options, Device::is_system_battery).unwrap();
+        assert_eq!((bat.name.as_str(), rating), ("BAT0", 6));
+        let roots = [root.path.clone()];
+        let bat = Battery::find_with_in(None, &options, &roots, None).unwrap();
+        assert_eq!(bat.name, "BAT0");
+    }
+
+    #[test]
+    fn equal_ratings_break_ties_by_name() {
+        // Created in an order unlike the expected one, which must not matter
+        let root = FakeRoot::new();
+        for name in ["CMB0", "BAT10", "BAT1", "BAT2"] {
+            let present = if name == "BAT1" { "0" } else { "1" };
+            let dir = root.battery(name, 55);
+            std::fs::write(dir.join("present"), format!("{present}\n")).unwrap();
+        }
+
+        let roots = [root.path.clone()];
+        let options = FindOptions::default();
+        assert_eq!(
+            names(&Battery::find_all_in(&roots, &options)),
+            ["BAT2", "BAT10", "CMB0", "BAT1"]
+        );
+        let bat = Battery::find_with_in(None, &options, &roots, None).unwrap();
+        assert_eq!(bat.name, "BAT2");
+    }
+
+    #[test]
+    fn primary_battery_skips_detection_and_the_cache() {
+        let root = FakeRoot::new();
+        root.battery("BAT0", 55);
+        let bat1 = root.battery("BAT1", 80);
+        std::fs::remove_file(bat1.join("cycle_count")).unwrap();
+        let gamepad = root.battery("sony_controller_battery_aa:bb:cc:dd:ee:ff", 80);
+        std::fs::write(gamepad.join("scope"), "Device\n").unwrap();
+        let roots = [root.path.clone()];
+        let options = FindOptions::default();
+        let cache = root.path.join("cache");
+        Cache::new("BAT0", 6).save(&cache).unwrap();
+
+        let find = |primary| {
+            Battery::find_with_in(Some(&cache), &options, &roots, primary)
+                .unwrap()
+                .name
+        };
+        assert_eq!(find(Some("BAT1")), "BAT1");
+        assert_eq!(find(Some(" BAT1\n")), "BAT1");
+        // The cache isn't replaced by the primary battery
+        assert_eq!(Cache::load(&cache).unwrap().name, "BAT0");
+
+        // Otherwise the cached battery is used as if it wasn't set
+        assert_eq!(find(Some("")), "BAT0");
+        assert_eq!(find(Some("BAT9")), "BAT0");
+        assert_eq!(
+            find(Some("sony_controller_battery_aa:bb:cc:dd:ee:ff")),
+            "BAT0"
+        );
+        // And so is detection without a cache
+        let bat = Battery::find_with_in(None, &options, &roots, Some("BAT9")).unwrap();
+        assert_eq!(bat.name, "BAT0");
+    }
+
+    #[test]
+    fn extra_roots_are_rated_together() {
+        let standard = FakeRoot::new();
+        let bat0 = standard.battery("BAT0", 55);
+        std::fs::remove_file(bat0.join("cycle_count")).unwrap();
+        let extra = FakeRoot::new();
+        extra.battery("CMB0", 80);
+
+        let missing = extra.path.join("missing");
+        let options = FindOptions {
+            extra_roots: vec![missing, extra.path.clone()],
+            ..Default::default()
+        };
+        // Unreadable roots are skipped
+        assert_eq!(
+            options.roots(),
+            [PathBuf::from(POWER_SUPPLY_ROOT), extra.path.clone()]
+        );
+
+        // The device with every feature wins whichever root it is in
+        let roots = [standard.path.clone(), extra.path.clone()];
+        assert_eq!(
+            names(&Battery::find_all_in(&roots, &options)),
+            ["CMB0", "BAT0"]
+        );
+        let bat = Battery::find_with_in(None, &options, &roots, None).unwrap();
+        assert_eq!(bat.name, "CMB0");
+    }
+
+    fn index(root: &FakeRoot, index: usize) -> Result<String, BatmonError> {
+        Battery::find_index_in(
+            std::slice::from_ref(&root.path),
+            &FindOptions::default(),
+            index,
+        )
+        .map(|bat| bat.name)
+    }
+
+    #[test]
+    fn device_index_without_batteries() {
+        let root = FakeRoot::new();
+        root.device("AC", &[("type", "Mains"), ("online", "1")]);
+        let e = index(&root, 0).unwrap_err();
+        assert!(matches!(e, BatmonError::NoBattery));
+        assert_eq!(e.exit_code(), crate::error::exit_code::NO_BATTERY);
+    }
+
+    #[test]
+    fn device_index_with_one_battery() {
+        let root = FakeRoot::new();
+        root.battery("BAT0", 55);
+        assert_eq!(index(&root, 0).unwrap(), "BAT0");
+        let e = index(&root, 1).unwrap_err();
+        assert!(matches!(e, BatmonError::NoSuchIndex { index: 1, found: 1 }));
+        assert_eq!(e.exit_code(), crate::error::exit_code::INVALID_DEVICE);
+    }
+
+    #[test]
+    fn device_index_with_two_batteries_follows_detection() {
+        let root = FakeRoot::new();
+        // BAT1 rates higher, so detection prefers it over the lower name
+        let bat0 = root.battery("BAT0", 55);
+        std::fs::remove_file(bat0.join("cycle_count")).unwrap();
+        let bat1 = root.battery("BAT1", 80);
+        let devices = [Device::from(bat0), Device::from(bat1)];
+        let (found, _) =
+            Battery::find_in(&devices, &FindOptions::default(), Device::is_system_battery).unwrap();
+
+        assert_eq!(index(&root, 0).unwrap(), found.name);
+        assert_eq!(index(&root, 0).unwrap(), "BAT1");
+        assert_eq!(index(&root, 1).unwrap(), "BAT0");
+        assert!(matches!(
+            index(&root, 2),
+            Err(BatmonError::NoSuchIndex { index: 2, found: 2 })
+        ));
+    }
+
+    fn set_capacity_level(dir: &Path, level: &str) {
+        let uevent = format!("POWER_SUPPLY_CAPACITY_LEVEL={level}\n");
+        std::fs::write(dir.join("uevent"), uevent).unwrap();
+    }
+
+    #[test]
+    fn capacity_level_follows_the_slow_schedule_with_a_numeric_level() {
+        let root = FakeRoot::new();
+        let dir = root.battery("BAT0", 55);
+        set_capacity_level(&dir, "Normal");
+        let mut bat = Battery::new_in(&root.path, "BAT0").unwrap();
+        bat.set_schedule(PollSchedule { slow_every: 3 });
+
+        set_capacity_level(&dir, "Low");
+        // The update numbered 0 was taken when the battery was created
+        for tick in 1..3 {
+            bat.update();
+            assert_eq!(
+                bat.state().capacity_level.as_deref(),
+                Some("Normal"),
+                "{tick}"
+            );
+        }
+        bat.update();
+        assert_eq!(bat.state().capacity_level.as_deref(), Some("Low"));
+    }
+
+    #[test]
+    fn capacity_level_is_read_every_update_without_a_numeric_level() {
+        let root = FakeRoot::new();
+        let dir = root.device(
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("scope", "System"),
+                ("status", "Discharging"),
+            ],
+        );
+        set_capacity_level(&dir, "Normal");
+        let mut bat = Battery::new_in(&root.path, "BAT0").unwrap();
+        bat.set_schedule(PollSchedule { slow_every: 3 });
+
+        set_capacity_level(&dir, "Low");
+        bat.update();
+        assert_eq!(bat.state().capacity_level.as_deref(), Some("Low"));
+    }
+
+    #[test]
+    fn capacity_level_attribute_without_a_uevent() {
+        let root = FakeRoot::new();
+        let dir = root.device(
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("scope", "System"),
+                ("status", "Discharging"),
+                ("capacity_level", "Low"),
+            ],
+        );
+        let bat = Battery::new_in(&root.path, "BAT0").unwrap();
+        assert_eq!(bat.to_string(), "BAT0 (?) @ Low, Discharging, Unknown");
+
+        let json = serde_json::to_value(bat.state()).unwrap();
+        assert_eq!(json["capacity_level"], "Low");
+        assert_eq!(json["level"], serde_json::Value::Null);
+
+        // The uevent wins over the attribute
+        set_capacity_level(&dir, "Critical");
+        let bat = Battery::new_in(&root.path, "BAT0").unwrap();
+        assert_eq!(bat.state().capacity_level.as_deref(), Some("Critical"));
+    }
+
+    #[test]
+    fn hanging_uevent_keeps_the_last_capacity_level() {
+        let root = FakeRoot::new();
+        let dir = root.battery("BAT0", 55);
+        set_capacity_level(&dir, "Normal");
+        let mut bat = Battery::new_in(&root.path, "BAT0").unwrap();
+        assert_eq!(bat.state().capacity_level.as_deref(), Some("Normal"));
+
+        let timeout = Duration::from_millis(100);
+        bat.set_read_timeout(Some(timeout));
+        make_fifo(&dir.join("uevent"));
+        let start = Instant::now();
+        bat.update();
+        assert!(start.elapsed() < timeout * 5);
+        assert_eq!(bat.state().capacity_level.as_deref(), Some("Normal"));
+
+        // Let the worker finish the hanging read
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(dir.join("uevent"))
+            .unwrap()
+            .write_all(b"POWER_SUPPLY_CAPACITY_LEVEL=Low\n")
+            .unwrap();
+    }
+
+    #[test]
+    fn device_names_are_normalized() {
+        let root = FakeRoot::new();
+        root.battery("BAT0", 50);
+        for name in ["BAT0", "BAT0/", "BAT0\r\n", " BAT0/\n", "bat0", "Bat0/"] {
+            let battery = Battery::new_in(&root.path, name).unwrap();
+            assert_eq!(battery.name, "BAT0", "{name:?}");
+        }
+    }
+
+    #[test]
+    fn invalid_device_names_are_rejected() {
+        let root = FakeRoot::new();
+        root.battery("BAT0", 50);
+        for name in ["", "/", "../BAT0", "power_supply/BAT0", "BAT1"] {
+            let err = Battery::new_in(&root.path, name).unwrap_err();
+            assert!(
+                matches!(
+                    err.downcast_ref::<BatmonError>(),
+                    Some(BatmonError::UnknownDevice { .. })
+                ),
+                "{name:?}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn ambiguous_case_match_is_an_error() {
+        let root = FakeRoot::new();
+        root.battery("BAT0", 50);
+        root.battery("Bat0", 50);
+        let err = Battery::new_in(&root.path, "bat0").unwrap_err();
+        match err.downcast_ref::<BatmonError>() {
+            Some(BatmonError::UnknownDevice { case_matches, .. }) => {
+                assert_eq!(case_matches, &["BAT0", "Bat0"]);
+            }
+            _ => panic!("unexpected error: {err}"),
+        }
+    }
+
+    #[test]
+    fn exact_case_wins_over_case_matches() {
+        let root = FakeRoot::new();
+        root.battery("BAT0", 50);
+        root.battery("Bat0", 50);
+        for name in ["BAT0", "Bat0", "Bat0/\r\n"] {
+            let battery = Battery::new_in(&root.path, name).unwrap();
+            assert_eq!(battery.name, name.trim_end_matches(['/', '\r', '\n']));
+        }
+
+        let err = Battery::new_in(&root.path, "bat0").unwrap_err();
+        assert!(
+            err.to_string().starts_with(
+                "there is no device named 'bat0', and it matches BAT0 and Bat0 ignoring case"
+            ),
+            "{err}"
+        );
+        // Without a case match, the closest name is suggested
+        let root = FakeRoot::new();
+        root.battery("BAT0", 50);
+        let err = Battery::new_in(&root.path, "BAT9").unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("there is no device named 'BAT9', did you mean 'BAT0'?"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn peripheral_device_is_invalid() {
+        let root = FakeRoot::new();
+        root.battery("BAT0", 50);
+        root.device(
+            "hid-mouse-battery",
+            &[("type", "Battery"), ("scope", "Device"), ("capacity", "80")],
+        );
+        root.device("AC", &[("type", "Mains"), ("online", "1")]);
+        for name in ["hid-mouse-battery", "AC"] {
+            let err = Battery::new_in(&root.path, name).unwrap_err();
+            let err = err.downcast_ref::<BatmonError>();
+            assert!(
+                matches!(err, Some(BatmonError::InvalidDevice { .. })),
+                "{name}: {err:?}"
+            );
+            assert_eq!(
+                err.map(BatmonError::exit_code),
+                Some(crate::error::exit_code::INVALID_DEVICE)
+            );
+        }
+    }
+
+    #[test]
+    fn charging_estimate_against_the_design_capacity() {
+        let root = FakeRoot::new();
+        let dir = root.battery("BAT0", 50);
+        std::fs::write(dir.join("status"), "Charging\n").unwrap();
+        std::fs::write(dir.join("charge_full_design"), "5000000\n").unwrap();
+        std::fs::write(dir.join("time_to_full_now"), "1800\n").unwrap();
+        let mut bat = Battery::new_in(&root.path, "BAT0").unwrap();
+        let hours = |h: u64| Some(Duration::from_secs(h * 60 * 60));
+
+        // The kernel's estimate is against the full capacity, so only used for it
+        assert_eq!(bat.remaining_duration(), Some(Duration::from_secs(1800)));
+        bat.set_capacity_ref(CapacityRef::Design);
+        assert_eq!(bat.remaining_duration(), hours(3));
+        bat.set_capacity_ref(CapacityRef::Full);
+        bat.set_estimator(Estimator::Computed);
+        assert_eq!(bat.remaining_duration(), hours(2));
+
+        // Discharging doesn't depend on the capacity
+        std::fs::write(dir.join("status"), "Discharging\n").unwrap();
+        std::fs::remove_file(dir.join("time_to_full_now")).unwrap();
+        bat.update();
+        bat.set_capacity_ref(CapacityRef::Design);
+        assert_eq!(bat.remaining_duration(), hours(2));
+    }
+
+    #[test]
+    fn design_capacity_falls_back_to_the_full_capacity() {
+        let root = FakeRoot::new();
+        let dir = root.battery("BAT0", 50);
+        std::fs::write(dir.join("status"), "Charging\n").unwrap();
+        let mut bat = Battery::new_in(&root.path, "BAT0").unwrap();
+        bat.set_capacity_ref(CapacityRef::Design);
+        assert_eq!(
+            bat.remaining_duration(),
+            Some(Duration::from_secs(2 * 60 * 60))
+        );
+    }
+
+    #[test]
+    fn trend_estimate_follows_the_charge() {
+        let root = FakeRoot::new();
+        let dir = root.battery("BAT0", 50);
+        let clock = crate::clock::FakeClock::new(std::time::UNIX_EPOCH + Duration::from_secs(1000));
+        let mut bat = Battery::new_in(&root.path, "BAT0").unwrap();
+        bat.set_estimator(Estimator::Trend);
+        // The draw says 2 hours, but the charge drops 10000 a minute
+        assert_eq!(
+            bat.state().remaining_at_draw(4_000_000),
+            Some(Duration::from_secs(7200))
+        );
+        for charge in [2_020_000, 2_010_000, 2_000_000] {
+            std::fs::write(dir.join("charge_now"), format!("{charge}\n")).unwrap();
+            bat.update_at(&clock);
+            clock.advance(Duration::from_secs(60));
+        }
+        // A minute after the last update, 1990000 is left
+        assert_eq!(
+            bat.remaining_duration_at(&clock),
+            Some(Duration::from_secs(199 * 60))
+        );
+
+        bat.set_estimator(Estimator::Computed);
+        assert_eq!(
+            bat.remaining_duration_at(&clock),
+            Some(Duration::from_secs(2_000_000 * 3600 / 1_000_000))
+        );
+    }
+
+    #[test]
+    fn summary_in_every_status() {
+        let root = FakeRoot::new();
+        let dir = root.battery("BAT0", 50);
+        for (status, current, expected) in [
+            (
+                "Discharging",
+                "1000000",
+                "BAT0 (120) @ 50%, Discharging, 02:00:00 remaining",
+            ),
+            (
+                "Charging",
+                "1000000",
+                "BAT0 (120) @ 50%, Charging, 02:00:00 until full",
+            ),
+            ("Full", "0", "BAT0 (120) @ 50%, Full"),
+            ("Not charging", "0", "BAT0 (120) @ 50%, NotCharging"),
+            ("Unknown", "0", "BAT0 (120) @ 50%, Unknown, Unknown"),
+            ("Unknown", "1000000", "BAT0 (120) @ 50%, Unknown, Unknown"),
+        ] {
+            std::fs::write(dir.join("status"), format!("{status}\n")).unwrap();
+            std::fs::write(dir.join("current_now"), format!("{current}\n")).unwrap();
+            let bat = Battery::new_in(&root.path, "BAT0").unwrap();
+            assert_eq!(bat.to_string(), expected);
+        }
+    }
+
+    fn discharging(level: u8, charge: u64, current: u64) -> BatteryState {
+        BatteryState::builder()
+            .level(level)
+            .capacity(4_000_000)
+            .charge(charge)
+            .current(current)
+            .status(ChargingStatus::Discharging)
+            .build()
+    }
+
+    #[test]
+    fn default_state_is_neutral() {
+        let state = BatteryState::default();
+        assert_eq!(state.level, 0);
+        assert_eq!(state.status, ChargingStatus::Unknown);
+        assert_eq!(state.capacity_level, None);
+        assert!(state.is_field_valid(BatteryValue::Level));
+        assert_eq!(state.remaining_at_draw(0), None);
+        assert_eq!(state.net_flow(), 0);
+    }
+
+    #[test]
+    fn builder_marks_unread_values() {
+        let state = BatteryState::builder()
+            .level(42)
+            .unread(BatteryValue::Charge)
+            .build();
+        assert_eq!(state.level, 42);
+        assert!(state.is_field_valid(BatteryValue::Level));
+        assert!(!state.is_field_valid(BatteryValue::Charge));
+    }
+
+    #[test]
+    fn remaining_at_draw() {
+        let state = discharging(50, 2_000_000, 1_000_000);
+        assert_eq!(
+            state.remaining_at_draw(4_000_000),
+            Some(Duration::from_secs(2 * 60 * 60))
+        );
+
+        let charging = BatteryState::builder()
+            .charge(3_000_000)
+            .current(2_000_000)
+            .status(ChargingStatus::Charging)
+            .build();
+        assert_eq!(
+            charging.remaining_at_draw(4_000_000),
+            Some(Duration::from_secs(30 * 60))
+        );
+
+        let full = BatteryState::builder().status(ChargingStatus::Full).build();
+        assert_eq!(full.remaining_at_draw(4_000_000), Some(Duration::ZERO));
+
+        assert_eq!(
+            discharging(50, 2_000_000, 0).remaining_at_draw(4_000_000),
+            None
+        );
+        let unread = BatteryState::builder()
+            .status(ChargingStatus::Discharging)
+            .unread(BatteryValue::Current)
+            .build();
+        assert_eq!(unread.remaining_at_draw(4_000_000), None);
+    }
+
+    fn assert_close(value: f64, expected: f64) {
+        assert!((value - expected).abs() < 1e-9, "{value} != {expected}");
+    }
+
+    #[test]
+    fn energy_helpers() {
+        // 3000 mAh and 1500 mA at 11.4 V
+        let charge = BatteryState::builder()
+            .family(UnitFamily::Charge)
+            .capacity(4_000_000)
+            .charge(3_000_000)
+            .current(1_500_000)
+            .voltage(11_400_000)
+            .build();
+        assert_close(charge.energy_wh(11_400_000), 34.2);
+        assert_close(charge.charge_rate_w(11_400_000), 17.1);
+        assert_close(charge.energy_now_wh().unwrap(), 34.2);
+        assert_close(charge.energy_full_wh().unwrap(), 45.6);
+        assert_close(charge.power_w().unwrap(), 17.1);
+        // Without a voltage a charge can't be converted
+        let unknown_voltage = BatteryState {
+            voltage: 0,
+            ..charge.clone()
+        };
+        assert_eq!(unknown_voltage.energy_now_wh(), None);
+        assert_eq!(unknown_voltage.power_w(), None);
+
+        // 42.5 Wh and 8.25 W, whatever the voltage
+        let energy = BatteryState::builder()
+            .family(UnitFamily::Energy)
+            .capacity(50_000_000)
+            .charge(42_500_000)
+            .current(8_250_000)
+            .build();
+        assert_close(energy.energy_wh(11_400_000), 42.5);
+        assert_close(energy.energy_wh(0), 42.5);
+        assert_close(energy.charge_rate_w(0), 8.25);
+        assert_close(energy.energy_now_wh().unwrap(), 42.5);
+    }
+
+    #[test]
+    fn percent_of_design() {
+        let state = BatteryState::builder().capacity(4_000_000).build();
+        assert_close(state.percent_of_design(5_000_000).unwrap(), 80.0);
+        assert_close(state.percent_of_design(3_200_000).unwrap(), 125.0);
+        assert_eq!(state.percent_of_design(0), None);
+    }
+
+    #[test]
+    fn thresholds_and_flow() {
+        let state = discharging(15, 600_000, 1_000_000);
+        assert!(state.is_low(15) && state.is_critical(15));
+        assert!(!state.is_low(14));
+        assert_eq!(state.net_flow(), -1_000_000);
+        assert_eq!(state.percent_f64(), 15.0);
+        assert!(state.will_last(Duration::from_secs(30 * 60)));
+        assert!(!state.will_last(Duration::from_secs(60 * 60)));
+
+        let charging = BatteryState::builder()
+            .level(15)
+            .current(500)
+            .status(ChargingStatus::Charging)
+            .build();
+        assert!(!charging.is_critical(15));
+        assert_eq!(charging.net_flow(), 500);
+        assert_eq!(charging.percent_f64(), 15.0);
+        assert!(charging.will_last(Duration::from_secs(24 * 60 * 60)));
+
+        // Without a draw there's nothing to go on
+        let idle = discharging(15, 600_000, 0);
+        assert!(idle.will_last(Duration::from_secs(24 * 60 * 60)));
+    }
+}
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The battery detected last time, so detection can be skipped on the next run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cache {
+    /// The device name under /sys/class/power_supply (or one of the extra roots)
+    pub name: String,
+    /// The device's rating when it was detected
+    pub rating: u8,
+    /// When the device was detected, in seconds since the unix epoch
+    pub timestamp: u64,
+}
+
+impl Cache {
+    pub fn new(name: &str, rating: u8) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Self {
+            name: name.to_owned(),
+            rating,
+            timestamp,
+        }
+    }
+
+    /// `$XDG_CACHE_HOME/batmon/battery.json`, or in `~/.cache` if it isn't set
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match std::env::var_os("XDG_CACHE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+        };
+        Some(dir.join("batmon").join("battery.json"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    /// Save the cache unless `path` already holds the same device, returning whether it was
+    /// written. The timestamp is ignored, so re-detecting the same device doesn't touch the file.
+    pub fn save_if_changed(&self, path: &Path) -> std::io::Result<bool> {
+        match Cache::load(path) {
+            Ok(existing) if existing.name == self.name && existing.rating == self.rating => {
+                Ok(false)
+            }
+            _ => self.save(path).map(|_| true),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_vec(self)?)
+    }
+}
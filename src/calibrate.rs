@@ -0,0 +1,290 @@
+use crate::Result;
+use batmon::{Battery, BatteryState, ChargingStatus, UnitFamily};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// The level a discharge has to reach to count as a full cycle
+pub const EMPTY_LEVEL: u8 = 5;
+
+/// The usable capacity observed over a discharge from full
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Measurement {
+    /// The charge drawn from the battery, counted from the current, in the family's units
+    pub drawn: u64,
+    pub start_level: u8,
+    pub end_level: u8,
+    /// The full capacity the battery reported at the end
+    pub reported: u64,
+    pub family: UnitFamily,
+    #[serde(with = "seconds")]
+    pub duration: Duration,
+    /// When the measurement finished
+    pub time: u64,
+}
+
+impl Measurement {
+    /// The capacity the drawn charge extrapolates to over the whole level range
+    pub fn usable(&self) -> u64 {
+        let levels = u64::from(self.start_level.saturating_sub(self.end_level).max(1));
+        self.drawn.saturating_mul(100) / levels
+    }
+
+    /// Where the last measurement is kept
+    pub fn default_path() -> Option<PathBuf> {
+        Some(crate::paths::state_dir()?.join("calibration.json"))
+    }
+
+    pub fn load(path: &PathBuf) -> Result<Self> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    pub fn save(&self, path: &PathBuf) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_vec(self)?)
+    }
+}
+
+impl std::fmt::Display for Measurement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unit = self.family.charge_unit();
+        let usable = self.usable();
+        writeln!(
+            f,
+            "Drew {} {unit} from {}% to {}% over {}",
+            self.drawn,
+            self.start_level,
+            self.end_level,
+            humantime::format_duration(Duration::from_secs(self.duration.as_secs()))
+        )?;
+        write!(f, "Measured capacity: {usable} {unit}")?;
+        if self.reported > 0 {
+            write!(
+                f,
+                " ({:.1}% of the reported {} {unit})",
+                usable as f64 * 100.0 / self.reported as f64,
+                self.reported
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Durations as whole seconds, which is all a discharge needs
+mod seconds {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_u64(d.as_secs())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+        Ok(Duration::from_secs(u64::deserialize(d)?))
+    }
+}
+
+enum Phase {
+    /// Waiting for the battery to be full
+    Idle,
+    Full,
+    /// Counting the charge drawn since the battery started discharging from full
+    Discharging {
+        start: BatteryState,
+        last: BatteryState,
+        /// In the family's units, kept fractional so short intervals aren't lost to rounding
+        drawn: f64,
+    },
+}
+
+/// Measures the usable capacity by counting the charge drawn from the current,
+/// over a discharge from full down to `EMPTY_LEVEL`. Charging part way resets it.
+pub struct Calibration {
+    phase: Phase,
+}
+
+impl Calibration {
+    pub fn new() -> Self {
+        Self { phase: Phase::Idle }
+    }
+
+    /// Whether a discharge from full is being measured
+    pub fn measuring(&self) -> bool {
+        matches!(self.phase, Phase::Discharging { .. })
+    }
+
+    /// Feed a new state, returning the measurement once the discharge reaches `EMPTY_LEVEL`
+    pub fn update(&mut self, state: &BatteryState) -> Option<Measurement> {
+        let full = state.status == ChargingStatus::Full
+            || (state.status == ChargingStatus::NotCharging && state.level >= 100);
+
+        self.phase = match std::mem::replace(&mut self.phase, Phase::Idle) {
+            _ if full => Phase::Full,
+            Phase::Full if state.is_discharging() => {
+                info!("Calibration started at {}%", state.level);
+                Phase::Discharging {
+                    start: state.clone(),
+                    last: state.clone(),
+                    drawn: 0.0,
+                }
+            }
+            Phase::Discharging {
+                start,
+                last,
+                mut drawn,
+            } if state.is_discharging() => {
+                let elapsed = state
+                    .captured_at
+                    .duration_since(last.captured_at)
+                    .unwrap_or_default();
+                // The average of both samples, per hour
+                drawn +=
+                    (last.current + state.current) as f64 / 2.0 * elapsed.as_secs_f64() / 3600.0;
+
+                if state.level <= EMPTY_LEVEL {
+                    let measurement = Measurement {
+                        drawn: drawn as u64,
+                        start_level: start.level,
+                        end_level: state.level,
+                        reported: state.capacity,
+                        family: state.family,
+                        duration: state
+                            .captured_at
+                            .duration_since(start.captured_at)
+                            .unwrap_or_default(),
+                        time: state
+                            .captured_at
+                            .duration_since(SystemTime::UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_secs(),
+                    };
+                    self.phase = Phase::Idle;
+                    return Some(measurement);
+                }
+
+                Phase::Discharging {
+                    start,
+                    last: state.clone(),
+                    drawn,
+                }
+            }
+            Phase::Discharging { .. } => {
+                info!("Calibration abandoned, the battery stopped discharging");
+                Phase::Idle
+            }
+            phase => phase,
+        };
+        None
+    }
+}
+
+/// Record a measurement, logging it and keeping it for `batmon calibrate`
+pub fn record(measurement: &Measurement) {
+    info!("Calibration finished: {measurement}");
+    if let Some(path) = Measurement::default_path() {
+        if let Err(e) = measurement.save(&path) {
+            warn!("Failed to save calibration: {e}");
+        }
+    }
+}
+
+/// Print the last measurement recorded by the daemon or `--watch`
+pub fn print_last() -> Result<()> {
+    let path = Measurement::default_path().ok_or("Failed to locate the calibration file")?;
+    match Measurement::load(&path) {
+        Ok(measurement) => println!("{measurement}"),
+        Err(_) => println!(
+            "No calibration recorded yet. Run the daemon or `batmon calibrate --watch` \
+             through a discharge from full to {EMPTY_LEVEL}%"
+        ),
+    }
+    Ok(())
+}
+
+/// Measure in the foreground until a discharge from full to `EMPTY_LEVEL` completes
+pub fn watch(bat: &mut Battery, interval: u64) -> Result<()> {
+    let mut calibration = Calibration::new();
+    println!("Waiting for the battery to be full, then discharge it to {EMPTY_LEVEL}%");
+    loop {
+        bat.update();
+        let state = bat.state();
+        let was_measuring = calibration.measuring();
+        if let Some(measurement) = calibration.update(&state) {
+            record(&measurement);
+            println!("{measurement}");
+            return Ok(());
+        }
+        match (was_measuring, calibration.measuring()) {
+            (false, true) => println!("Measuring from {}%", state.level),
+            (true, false) => println!("Stopped discharging, waiting for the battery to be full"),
+            _ => {}
+        }
+        std::thread::sleep(Duration::from_secs(interval));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    /// A state at `minute` with a steady draw of a tenth of the capacity per hour
+    fn state(minute: u64, level: u8, status: ChargingStatus) -> BatteryState {
+        BatteryState::builder()
+            .level(level)
+            .capacity(5_000_000)
+            .current(400_000)
+            .status(status)
+            .captured_at(UNIX_EPOCH + Duration::from_secs(minute * 60))
+            .build()
+    }
+
+    #[test]
+    fn discharge_from_full_is_measured() {
+        let mut calibration = Calibration::new();
+        assert!(calibration
+            .update(&state(0, 100, ChargingStatus::Full))
+            .is_none());
+        // 5% every half hour, down to the empty level
+        let mut measurement = None;
+        for step in 0..=19 {
+            let level = 100 - step as u8 * 5;
+            let sample = state(step * 30, level, ChargingStatus::Discharging);
+            assert!(measurement.is_none(), "measured early at {level}%");
+            measurement = calibration.update(&sample);
+            if measurement.is_none() {
+                assert!(calibration.measuring());
+            }
+        }
+
+        let measurement = measurement.unwrap();
+        assert!(!calibration.measuring());
+        assert_eq!(measurement.drawn, 3_800_000);
+        assert_eq!((measurement.start_level, measurement.end_level), (100, 5));
+        assert_eq!(measurement.duration, Duration::from_secs(570 * 60));
+        assert_eq!(measurement.usable(), 4_000_000);
+        let unit = measurement.family.charge_unit();
+        assert!(measurement.to_string().ends_with(&format!(
+            "Measured capacity: 4000000 {unit} (80.0% of the reported 5000000 {unit})"
+        )));
+    }
+
+    #[test]
+    fn charging_part_way_starts_over() {
+        let mut calibration = Calibration::new();
+        // Discharging without having been full doesn't start a measurement
+        calibration.update(&state(0, 80, ChargingStatus::Discharging));
+        assert!(!calibration.measuring());
+
+        calibration.update(&state(10, 100, ChargingStatus::Full));
+        calibration.update(&state(20, 100, ChargingStatus::Discharging));
+        assert!(calibration.measuring());
+        calibration.update(&state(30, 60, ChargingStatus::Charging));
+        assert!(!calibration.measuring());
+        assert!(calibration
+            .update(&state(40, 4, ChargingStatus::Discharging))
+            .is_none());
+    }
+}
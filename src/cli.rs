@@ -1,33 +1,487 @@
-use clap::{Args, Parser, Subcommand};
+use crate::limit::RateLimit;
+use crate::notify::{self, DndPolicy, Event, NotifySettings};
+use batmon::{BatteryState, BatteryValue, CapacityRef, CurrentSource, Estimator, TimeFormat};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use libnotify::Urgency;
+use std::time::Duration;
+
 #[derive(Subcommand)]
 pub enum Command {
-    /// Print out the capacity, in uAh
+    /// Print out the capacity, in uAh (uWh for batteries that report energy)
     Capacity,
-    /// Print out the current charge level, in uAh
-    Charge,
-    /// Print out the current draw, in uA
+    /// Print out the capacity the battery was designed to hold, in uAh (uWh for batteries that
+    /// report energy)
+    Design,
+    /// Print out the current charge level, in uAh (uWh for batteries that report energy)
+    Charge {
+        /// Print the charge out of the capacity in mAh (Wh) instead
+        #[arg(long)]
+        absolute: bool,
+    },
+    /// Print out the current draw, in uA (uW for batteries that report energy)
     Current,
     /// Print out the number of charge cycles
     Cycles,
     /// Print out the battery level as a percentage
-    Level,
+    Level {
+        /// Print the level reported by the device, even with --scale-to-threshold
+        #[arg(long)]
+        raw: bool,
+    },
     /// Print out the name of the battery
     Name,
     /// Print out the status of the battery
     Status,
     /// Print out the time remaining until the battery is either charged or discharged
-    Time,
+    Time {
+        /// Print the whole number of seconds instead, failing if it can't be estimated
+        #[arg(long)]
+        seconds: bool,
+    },
+    /// Print out the local time the battery will be empty (or full while charging),
+    /// or nothing if it can't be estimated
+    EtaClock {
+        /// Use a 12 hour clock
+        #[arg(long = "12h")]
+        twelve_hour: bool,
+    },
     /// [DEFAULT] Print out a summary of the battery
-    Summary,
+    Summary {
+        /// Also print the charge out of the capacity in mAh (Wh)
+        #[arg(long)]
+        absolute: bool,
+
+        /// Print the summary as JSON
+        #[arg(long, conflicts_with = "absolute")]
+        json: bool,
+    },
     /// Run batmon as a battery state notification daemon
-    Daemon(DaemonArgs),
+    Daemon(Box<DaemonArgs>),
+    /// Print out the recent battery states recorded by the daemon
+    History {
+        /// Print how much the battery drained over each suspend instead
+        #[arg(long)]
+        suspend_report: bool,
+    },
+    /// Print out statistics over the battery states recorded by the daemon
+    Stats {
+        /// Only cover this much of the most recent history (e.g. 7d)
+        #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+        since: Option<Duration>,
+
+        /// Print the statistics as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print out the usable capacity measured over the last discharge from full, as recorded
+    /// by the daemon
+    Calibrate {
+        /// Measure a discharge in the foreground instead, waiting for the battery to be full
+        #[arg(long)]
+        watch: bool,
+
+        /// How often to sample the battery while watching, in seconds
+        #[arg(short, long, default_value_t = 15)]
+        interval: u64,
+    },
+    /// Print out the power draw and how long the battery would last at that draw
+    Efficiency,
+    /// Send the notification the daemon would send for an event, using the current battery state
+    TestNotify(TestNotifyArgs),
+    /// Print out what changed since the last time this was run, for polling scripts
+    Delta,
+    /// Print out the summary as Pango markup for bars that support it, like waybar tooltips
+    Pango,
+    /// Print out the level and remaining time for xmobar, coloured by how low the battery is.
+    /// Prints "no battery" when there isn't one, so the bar doesn't show an error
+    Xmobar {
+        /// Set the colour of a tier, as TIER=COLOR. Tiers are charging, low and critical
+        #[arg(long, value_name = "TIER=COLOR", value_parser = crate::tier::parse_color_setting)]
+        color: Vec<(crate::tier::Tier, String)>,
+
+        /// An icon to show before the level, as a path to an xpm or xbm file
+        #[arg(long, value_name = "PATH")]
+        icon: Option<String>,
+
+        /// A command to run when the output is clicked, which can't contain a backtick
+        #[arg(long, value_name = "CMD", value_parser = crate::markup::parse_click_command)]
+        on_click: Option<String>,
+    },
+    /// Print out the level, direction and remaining time for conky, padded to a fixed width
+    Conky {
+        /// Set the colour of a tier, as TIER=COLOR. Tiers are charging, low and critical
+        #[arg(long, value_name = "TIER=COLOR", value_parser = crate::tier::parse_color_setting)]
+        color: Vec<(crate::tier::Tier, String)>,
+
+        /// Print only the level, for conky's execbar and execgauge
+        #[arg(long)]
+        bar: bool,
+    },
+    /// Print out the battery's values as shell variable assignments,
+    /// for use with eval "$(batmon env)"
+    Env,
+    /// Print out a short indicator for shell prompts, like ⚡83 while charging
+    Prompt {
+        /// Don't end the indicator with a newline
+        #[arg(long)]
+        no_newline: bool,
+    },
+    /// Print out a timestamped line every time a value changes, until interrupted
+    Trace {
+        #[arg(value_enum)]
+        field: TraceField,
+
+        /// How often to check the value, in seconds
+        #[arg(short, long, default_value_t = 1)]
+        interval: u64,
+    },
+    /// Check everything the daemon relies on before enabling it
+    Selftest {
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Collect every device's attributes, the detected battery, the settings and the cache
+    /// into one JSON file to attach to bug reports
+    DebugDump {
+        /// Write the dump to this file instead of printing it
+        #[arg(long, value_name = "FILE")]
+        out: Option<std::path::PathBuf>,
+        /// Keep serial numbers, which are left out by default
+        #[arg(long)]
+        include_identifiers: bool,
+    },
+    /// List the files in the device's directory, with the values of those batmon understands
+    Attrs,
+    /// Print out the exact contents of an attribute of the device, without parsing it
+    Raw {
+        /// The attribute to read (e.g. status)
+        attribute: String,
+    },
+    /// Clear the cached system battery
+    Forget,
+    /// Hold back all but critical notifications from the daemon for a while
+    Snooze {
+        /// How long to snooze for (e.g. 30m), `off` to stop snoozing or `status`
+        request: crate::snooze::Request,
+    },
+    /// Print out a shell completion script
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+/// The values `--strict` requires to have been read for the remaining time
+const REMAINING: &[BatteryValue] = &[
+    BatteryValue::Status,
+    BatteryValue::Charge,
+    BatteryValue::Capacity,
+    BatteryValue::Current,
+];
+/// The values `--strict` requires for the summary, which is also the default command
+pub const SUMMARY: &[BatteryValue] = &[
+    BatteryValue::Level,
+    BatteryValue::Status,
+    BatteryValue::Charge,
+    BatteryValue::Capacity,
+    BatteryValue::Current,
+];
+/// The values `--strict` requires for showing the level and status
+const LEVEL_AND_STATUS: &[BatteryValue] = &[BatteryValue::Level, BatteryValue::Status];
+
+impl Command {
+    /// The values `--strict` requires to have been read before running the command
+    pub fn required_values(&self) -> &'static [BatteryValue] {
+        match self {
+            Command::Capacity => &[BatteryValue::Capacity],
+            Command::Charge { .. } => &[BatteryValue::Charge],
+            Command::Current => &[BatteryValue::Current],
+            Command::Cycles => &[BatteryValue::Cycles],
+            Command::Level { .. } => &[BatteryValue::Level],
+            Command::Status => &[BatteryValue::Status],
+            Command::Time { .. } | Command::EtaClock { .. } => REMAINING,
+            Command::Summary { .. } => SUMMARY,
+            Command::Efficiency => &[
+                BatteryValue::Current,
+                BatteryValue::Voltage,
+                BatteryValue::Capacity,
+            ],
+            Command::Daemon(_)
+            | Command::Env
+            | Command::Pango
+            | Command::Xmobar { .. }
+            | Command::Conky { .. }
+            | Command::Prompt { .. } => LEVEL_AND_STATUS,
+            _ => &[],
+        }
+    }
+}
+
+/// A battery value that can be traced
+#[derive(Clone, Copy, ValueEnum)]
+pub enum TraceField {
+    Level,
+    Capacity,
+    Charge,
+    Current,
+    Cycles,
+    Status,
+    Voltage,
 }
 
 #[derive(Args)]
 pub struct DaemonArgs {
-    /// The refresh interval when running, in seconds
-    #[arg(short, long, default_value_t = 15)]
-    pub interval: u64,
+    /// The refresh interval when running, in seconds or as a duration (e.g. 1m)
+    #[arg(short, long, value_name = "DURATION", default_value = "15", value_parser = parse_interval)]
+    pub interval: Duration,
+
+    /// The refresh interval while charging, full or not charging [default: --interval]
+    #[arg(long, value_name = "DURATION", value_parser = parse_interval)]
+    pub interval_charging: Option<Duration>,
+
+    /// The refresh interval while discharging [default: --interval]
+    #[arg(long, value_name = "DURATION", value_parser = parse_interval)]
+    pub interval_discharging: Option<Duration>,
+
+    /// Only re-read the capacity and cycle count every this many refreshes,
+    /// as they change far more slowly than the rest
+    #[arg(long, value_name = "COUNT", default_value_t = 20, value_parser = clap::value_parser!(u32).range(1..))]
+    pub slow_poll_every: u32,
+
+    /// Give up on reading an attribute after this long, keeping its last value, so a read
+    /// that hangs in a buggy driver doesn't hold up notifications. 0 waits indefinitely
+    #[arg(long, value_name = "DURATION", default_value = "2s", value_parser = parse_timeout)]
+    pub read_timeout: Duration,
+
+    /// Notify when the estimated time remaining while discharging drops below this duration (e.g. 30m)
+    #[arg(long, alias = "time-warn", value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub warn_remaining: Option<Duration>,
+
+    /// Notify with critical urgency when the estimated time remaining while discharging
+    /// drops below this duration (e.g. 10m)
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub critical_remaining: Option<Duration>,
+
+    /// Remind to unplug once the battery has been at 100% on AC for this duration (e.g. 4h)
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub full_plugged_reminder: Option<Duration>,
+
+    /// Don't notify when the level drops to 50%, 25% and 15%,
+    /// e.g. to only warn about the time remaining instead
+    #[arg(long)]
+    pub no_level_warnings: bool,
+
+    /// Notify when the battery has been draining much faster than usual for a few minutes,
+    /// as with a runaway process. The usual draw is learned over every discharge
+    #[arg(long)]
+    pub drain_alert: bool,
+
+    /// How many times the usual draw counts as draining much faster than usual
+    #[arg(
+        long,
+        value_name = "FACTOR",
+        default_value_t = 2.0,
+        value_parser = parse_factor,
+        requires = "drain_alert"
+    )]
+    pub drain_factor: f64,
+
+    /// Switch power-profiles-daemon to power-saver when the level drops to this percentage
+    /// while discharging, and back to the previous profile once charging
+    #[arg(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub power_saver_below: Option<u8>,
+
+    /// The command run by the "Suspend now" button on critical notifications
+    #[arg(long, default_value = "systemctl suspend")]
+    pub emergency_command: String,
+
+    /// A script to run on every status change and level crossing.
+    /// It receives BATMON_EVENT, BATMON_LEVEL and BATMON_NAME in its environment
+    #[arg(long, value_name = "SCRIPT")]
+    pub on_transition: Option<std::path::PathBuf>,
+
+    /// Append status changes, threshold crossings and notifications to this file,
+    /// one line each
+    #[arg(long, value_name = "PATH")]
+    pub transitions_log: Option<std::path::PathBuf>,
+
+    /// The number of recent battery states to keep for `batmon history`, or 0 to keep none
+    #[arg(long, default_value_t = 240)]
+    pub history_size: usize,
+
+    /// Install a systemd user service running the daemon with these options
+    #[arg(long)]
+    pub install_service: bool,
+
+    /// Print the systemd user service for the daemon with these options instead of running it
+    #[arg(long, conflicts_with = "install_service")]
+    pub print_service: bool,
+
+    /// Overwrite an existing service when installing
+    #[arg(long, requires = "install_service")]
+    pub force: bool,
+
+    /// Fork into the background once the battery has been found, writing a pidfile
+    /// to the runtime directory
+    #[arg(long)]
+    pub daemonize: bool,
+
+    /// Where to write logs when running in the background, instead of the journal
+    #[arg(long, value_name = "FILE", requires = "daemonize")]
+    pub log_file: Option<std::path::PathBuf>,
+
+    /// Rotate the log file once it grows larger than this (e.g. 50M)
+    #[arg(long, value_name = "SIZE", value_parser = crate::rotate::parse_size, requires = "log_file")]
+    pub log_max_size: Option<u64>,
+
+    /// The number of rotated log files to keep
+    #[arg(long, value_name = "COUNT", default_value_t = 3)]
+    pub log_keep: usize,
+
+    /// Stop the daemon running in the background
+    #[arg(long)]
+    pub stop: bool,
+
+    /// Update as soon as the kernel announces a power supply change, rather than only every
+    /// interval. The interval still applies as a fallback, as not every change is announced
+    #[cfg(feature = "netlink")]
+    #[arg(long)]
+    pub netlink: bool,
+
+    #[command(flatten)]
+    pub notify: NotifyArgs,
+}
+
+impl DaemonArgs {
+    /// The interval to wait after reading `state`, picked from its status.
+    /// Unknown statuses count as charging, where there is nothing urgent to notice.
+    pub fn interval_for(&self, state: &BatteryState) -> Duration {
+        let specific = if state.is_discharging() {
+            self.interval_discharging
+        } else {
+            self.interval_charging
+        };
+        specific.unwrap_or(self.interval)
+    }
+}
+
+/// A duration as whole seconds (e.g. 15) or with units (e.g. 1m 30s)
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    let interval = match s.trim().parse() {
+        Ok(secs) => Duration::from_secs(secs),
+        Err(_) => humantime::parse_duration(s).map_err(|e| e.to_string())?,
+    };
+    if interval.is_zero() {
+        return Err(String::from("the interval can't be zero"));
+    }
+    Ok(interval)
+}
+
+/// A duration as whole seconds (e.g. 2) or with units (e.g. 500ms), where 0 means none
+fn parse_timeout(s: &str) -> Result<Duration, String> {
+    match s.trim().parse() {
+        Ok(secs) => Ok(Duration::from_secs(secs)),
+        Err(_) => humantime::parse_duration(s).map_err(|e| e.to_string()),
+    }
+}
+
+/// A factor over the usual draw, which must be more than 1
+fn parse_factor(s: &str) -> Result<f64, String> {
+    let factor: f64 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid factor '{s}'"))?;
+    if factor.is_nan() || factor <= 1.0 {
+        return Err(String::from("the factor must be more than 1"));
+    }
+    Ok(factor)
+}
+
+#[derive(Args)]
+pub struct NotifyArgs {
+    /// Set the urgency of a notification event, as EVENT=URGENCY.
+    /// Events are half, low, critical, charging, discharging, full, time and time-critical;
+    /// urgencies are low, normal and critical
+    #[arg(short, long, value_name = "EVENT=URGENCY", value_parser = parse_urgency_setting)]
+    pub urgency: Vec<(Event, Urgency)>,
+
+    /// Choose what happens to a notification event during do-not-disturb, as EVENT=POLICY.
+    /// Policies are bypass (the default), queue and drop
+    #[arg(long, value_name = "EVENT=POLICY", value_parser = parse_dnd_setting)]
+    pub dnd: Vec<(Event, DndPolicy)>,
+
+    /// Set the sound played for a notification event, as EVENT=SOUND.
+    /// Only critical notifications play a sound (battery-caution) by default; use none to disable one
+    #[arg(long, value_name = "EVENT=SOUND", value_parser = parse_sound_setting)]
+    pub sound: Vec<(Event, Option<String>)>,
+
+    /// Ask the notification server not to play any sounds
+    #[arg(long)]
+    pub silent: bool,
+
+    /// Don't set the value hint to the level, which some notification servers
+    /// render as a progress bar
+    #[arg(long)]
+    pub no_progress_hint: bool,
+
+    /// The most notifications to show in a period, as COUNT/PERIOD.
+    /// Notifications over the limit are summarized once the period allows it
+    #[arg(long, value_name = "COUNT/PERIOD", default_value = "6/1m")]
+    pub rate_limit: RateLimit,
+
+    /// The rate limit for notifications with critical urgency
+    #[arg(long, value_name = "COUNT/PERIOD", default_value = "20/1m")]
+    pub critical_rate_limit: RateLimit,
+
+    /// Print notifications to standard output instead of showing them
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+impl NotifyArgs {
+    pub fn settings(&self) -> NotifySettings {
+        let mut settings = NotifySettings {
+            silent: self.silent,
+            progress_hint: !self.no_progress_hint,
+            rate_limit: self.rate_limit,
+            critical_rate_limit: self.critical_rate_limit,
+            ..Default::default()
+        };
+        for (event, urgency) in &self.urgency {
+            settings.urgency.set(*event, *urgency);
+        }
+        for (event, policy) in &self.dnd {
+            settings.dnd.set(*event, *policy);
+        }
+        for (event, sound) in &self.sound {
+            settings.sound.set(*event, sound.clone());
+        }
+        settings
+    }
+}
+
+#[derive(Args)]
+pub struct TestNotifyArgs {
+    /// The event to send the notification for
+    #[arg(short, long, value_enum, default_value = "low")]
+    pub tier: Event,
+
+    #[command(flatten)]
+    pub notify: NotifyArgs,
+}
+
+fn parse_dnd_setting(s: &str) -> Result<(Event, DndPolicy), String> {
+    notify::parse_event_setting(s, notify::parse_dnd_policy)
+}
+
+fn parse_sound_setting(s: &str) -> Result<(Event, Option<String>), String> {
+    notify::parse_event_setting(s, notify::parse_sound)
+}
+
+fn parse_urgency_setting(s: &str) -> Result<(Event, Urgency), String> {
+    notify::parse_event_setting(s, notify::parse_urgency)
 }
 
 #[derive(Parser)]
@@ -38,11 +492,95 @@ pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Command>,
 
-    /// Use a specific device instead of trying to detect the system battery
+    /// Use a specific device instead of trying to detect the system battery.
+    /// BATMON_PRIMARY can name a system battery to use by default instead
     #[arg(short, long)]
     pub device: Option<String>,
 
+    /// Use the Nth system battery, counting from 0 in the order detection prefers them, so a
+    /// script can pick the first or second battery without knowing their names. Index 0 is
+    /// the battery batmon uses without --device
+    #[arg(long, value_name = "N", conflicts_with = "device")]
+    pub device_index: Option<usize>,
+
+    /// Log more details: -v for debug messages, -vv to also time every attribute read
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// The number of decimals in derived values like watts and watt-hours (0-3)
+    #[arg(long, value_name = "N", default_value_t = 2, value_parser = clap::value_parser!(u8).range(0..=3), global = true)]
+    pub precision: u8,
+
+    /// How remaining times are written: hms (02:13:45) or hm (2:14).
+    /// Defaults to hms, except in notifications where it defaults to hm
+    #[arg(long, value_name = "FORMAT", global = true)]
+    pub time_format: Option<TimeFormat>,
+
     /// Force a rescan of all batteries and ignore the cached system battery
     #[arg(short, long)]
     pub no_cache: bool,
+
+    /// Where to cache the detected system battery
+    /// [default: $XDG_CACHE_HOME/batmon/battery.json]
+    #[arg(long, value_name = "PATH")]
+    pub cache_file: Option<std::path::PathBuf>,
+
+    /// Also consider UPS devices when detecting the battery.
+    /// They are always considered when there is no system battery
+    #[arg(long)]
+    pub include_ups: bool,
+
+    /// Also look for devices in this directory, laid out like /sys/class/power_supply.
+    /// Can be given more than once
+    #[arg(long, value_name = "DIR")]
+    pub extra_root: Vec<std::path::PathBuf>,
+
+    /// Leave this device out of detection, as with a dock's bogus battery. `*` and `?` match
+    /// any characters and any single character. Can be given more than once, and is added to
+    /// the comma separated patterns in BATMON_IGNORE
+    #[arg(long, value_name = "PATTERN")]
+    pub ignore: Vec<String>,
+
+    /// Prefer this device over detection's own ranking, as with the larger of two batteries.
+    /// Given more than once, earlier devices are preferred. Patterns are matched like
+    /// --ignore, and BATMON_PRIORITY lists more after these, separated by commas
+    #[arg(long, value_name = "PATTERN")]
+    pub prefer: Vec<String>,
+
+    /// Read the current draw from current_now (current) or power_now (power)
+    /// instead of choosing automatically, converting with the battery voltage where needed
+    #[arg(long, default_value = "auto")]
+    pub current_source: CurrentSource,
+
+    /// Accept capacities, charges, draws and voltages written with a unit suffix
+    /// (e.g. `4200 mV`) by nonstandard drivers, converting them to micro-units
+    #[arg(long)]
+    pub lenient_parse: bool,
+
+    /// Fail if any value the command needs couldn't be read, instead of going on
+    /// with placeholders (like a charge of 0)
+    #[arg(long, global = true)]
+    pub strict: bool,
+
+    /// Don't scale up voltages and draws that look like they are in V, mV, mA or mW
+    /// instead of uV, uA or uW, for devices with genuinely tiny readings
+    #[arg(long)]
+    pub no_unit_heuristics: bool,
+
+    /// Display the level rescaled so the charge control end threshold reads as 100%
+    /// (e.g. 40% reads as 50% with an 80% threshold). Notifications and thresholds
+    /// use the rescaled level too; `batmon level --raw` still prints the real one
+    #[arg(long, global = true)]
+    pub scale_to_threshold: bool,
+
+    /// Estimate the remaining time from the device's own time_to_empty/time_to_full (auto)
+    /// when it provides them, always compute it from the charge and current draw (computed),
+    /// or from how fast the charge changed over the last minutes (trend, daemon only)
+    #[arg(long, default_value = "auto")]
+    pub estimator: Estimator,
+
+    /// Estimate the time until full against the full capacity (full) or the design capacity
+    /// (design), which stays the same as the battery ages
+    #[arg(long, value_name = "CAPACITY", default_value = "full")]
+    pub capacity_ref: CapacityRef,
 }
@@ -0,0 +1,57 @@
+//! Where the time battery states are captured at comes from.
+//!
+//! Estimates are computed from the times states were captured, so driving
+//! [`Battery::update_at`] and [`Battery::remaining_duration_at`] with a `FakeClock` (with the
+//! `test-util` feature) gives a controlled sequence of samples to check them against.
+//!
+//! [`Battery::update_at`]: crate::Battery::update_at
+//! [`Battery::remaining_duration_at`]: crate::Battery::remaining_duration_at
+
+use std::time::SystemTime;
+
+/// A source of the current time
+pub trait Clock {
+    fn now(&self) -> SystemTime;
+}
+
+/// The system's real-time clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[inline]
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug, Clone)]
+pub struct FakeClock {
+    now: std::cell::Cell<SystemTime>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl FakeClock {
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            now: std::cell::Cell::new(start),
+        }
+    }
+
+    pub fn advance(&self, by: std::time::Duration) {
+        self.now.set(self.now.get() + by);
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        self.now.set(now);
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Clock for FakeClock {
+    fn now(&self) -> SystemTime {
+        self.now.get()
+    }
+}
@@ -0,0 +1,65 @@
+use crate::cli::Cli;
+use clap::CommandFactory;
+use clap_complete::Shell;
+
+/// The hidden subcommand completion scripts call to list device names
+pub const DEVICES_HELPER: &str = "__complete-devices";
+
+/// Generate the completion script for `shell`, completing `--device` with the names of the
+/// power supply devices present when completing, for the shells that support it
+pub fn generate(shell: Shell) -> String {
+    let mut buf = Vec::new();
+    clap_complete::generate(shell, &mut Cli::command(), "batmon", &mut buf);
+    let script = String::from_utf8_lossy(&buf).into_owned();
+
+    let devices = format!("batmon {DEVICES_HELPER} 2>/dev/null");
+    match shell {
+        Shell::Bash => script.replace(
+            "                --device)\n                    COMPREPLY=($(compgen -f \"${cur}\"))",
+            &format!("                --device)\n                    COMPREPLY=($(compgen -W \"$({devices})\" -- \"${{cur}}\"))"),
+        )
+        .replace(
+            "                -d)\n                    COMPREPLY=($(compgen -f \"${cur}\"))",
+            &format!("                -d)\n                    COMPREPLY=($(compgen -W \"$({devices})\" -- \"${{cur}}\"))"),
+        ),
+        Shell::Zsh => {
+            let helper = format!(
+                "_batmon_devices() {{\n    local -a devices\n    devices=(${{(f)\"$({devices})\"}})\n    compadd -a devices\n}}\n\n"
+            );
+            let script = script.replace(":DEVICE:_default'", ":DEVICE:_batmon_devices'");
+            match script.split_once('\n') {
+                Some((compdef, rest)) => format!("{compdef}\n\n{helper}{rest}"),
+                None => script,
+            }
+        }
+        Shell::Fish => script
+            .lines()
+            .map(|line| {
+                if line.contains(" -l device ") {
+                    format!("{line} -f -a \"({devices})\"\n")
+                } else {
+                    format!("{line}\n")
+                }
+            })
+            .collect(),
+        _ => script,
+    }
+}
+
+/// Print the power supply device names, one per line.
+/// Prints nothing if sysfs can't be read so completion quietly offers no candidates.
+pub fn print_devices() {
+    let Ok(entries) = std::fs::read_dir("/sys/class/power_supply") else {
+        return;
+    };
+
+    let mut names: Vec<_> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+
+    for name in names {
+        println!("{name}");
+    }
+}
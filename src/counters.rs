@@ -0,0 +1,143 @@
+//! Runtime counters for checking that the daemon is healthy.
+//!
+//! The daemon writes them with the battery's state to the status file after every update,
+//! and prints them when it receives SIGUSR2.
+
+use batmon::{BatteryState, Envelope};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Counters maintained by the monitor. Every count only ever increases.
+#[derive(Debug, Serialize)]
+pub struct DaemonStats {
+    #[serde(skip)]
+    started: Instant,
+    /// Seconds since the daemon started, as of the last update
+    pub uptime: u64,
+    /// Update cycles completed
+    pub updates: u64,
+    /// Failed reads, by value
+    pub read_failures: BTreeMap<&'static str, u64>,
+    pub notifications_sent: u64,
+    pub notifications_failed: u64,
+    /// Notifications held back by the rate limit
+    pub rate_limited: u64,
+    /// Seconds since the unix epoch of the last update where every value was read
+    pub last_full_update: Option<u64>,
+}
+
+impl DaemonStats {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            uptime: 0,
+            updates: 0,
+            read_failures: BTreeMap::new(),
+            notifications_sent: 0,
+            notifications_failed: 0,
+            rate_limited: 0,
+            last_full_update: None,
+        }
+    }
+
+    /// Count a completed update captured at `time`, where the values in `failed` couldn't be read
+    pub fn record_update(&mut self, failed: &[&'static str], time: SystemTime) {
+        self.updates += 1;
+        self.uptime = self.started.elapsed().as_secs();
+        for &value in failed {
+            *self.read_failures.entry(value).or_default() += 1;
+        }
+        if failed.is_empty() {
+            let epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            self.last_full_update = Some(epoch.as_secs());
+        }
+    }
+}
+
+/// What the status file holds
+#[derive(Serialize)]
+pub struct Status<'a> {
+    pub battery: &'a BatteryState,
+    pub stats: &'a DaemonStats,
+}
+
+impl Status<'_> {
+    pub fn path() -> PathBuf {
+        // Named like the pidfile, for the same reason
+        let name = match std::env::var_os("XDG_RUNTIME_DIR") {
+            Some(_) => String::from("batmon.status.json"),
+            None => format!("batmon-{}.status.json", unsafe { libc::getuid() }),
+        };
+        crate::paths::runtime_dir().join(name)
+    }
+
+    pub fn save(&self, path: &PathBuf) -> std::io::Result<()> {
+        // Written through a temporary file so readers never see a partial status
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&Envelope::new(self))?)?;
+        std::fs::rename(tmp, path)
+    }
+
+    /// The status as printed on SIGUSR2, versioned like the status file
+    pub fn to_json_pretty(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&Envelope::new(self))
+    }
+}
+
+static DUMP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigusr2(_: libc::c_int) {
+    DUMP_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Have SIGUSR2 request a dump of the stats instead of terminating the daemon
+pub fn handle_sigusr2() {
+    unsafe {
+        libc::signal(libc::SIGUSR2, on_sigusr2 as *const () as libc::sighandler_t);
+    }
+}
+
+/// Whether SIGUSR2 was received and the dump is still to be printed
+pub fn dump_pending() -> bool {
+    DUMP_REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Whether SIGUSR2 was received since the last call
+pub fn take_dump_request() -> bool {
+    DUMP_REQUESTED.swap(false, Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_updates_and_failures() {
+        let mut stats = DaemonStats::new();
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(1000);
+        for i in 0..5 {
+            let failed: &[&str] = if i % 2 == 0 { &["current"] } else { &[] };
+            stats.record_update(failed, time);
+        }
+        assert_eq!(stats.updates, 5);
+        assert_eq!(stats.read_failures.get("current"), Some(&3));
+        assert_eq!(stats.last_full_update, Some(1000));
+    }
+
+    #[test]
+    fn status_is_versioned() {
+        let state = BatteryState::default();
+        let stats = DaemonStats::new();
+        let status = Status {
+            battery: &state,
+            stats: &stats,
+        };
+        let json: serde_json::Value =
+            serde_json::from_str(&status.to_json_pretty().unwrap()).unwrap();
+        assert_eq!(json["version"], batmon::OUTPUT_VERSION);
+        assert_eq!(json["battery"]["stats"]["updates"], 0);
+    }
+}
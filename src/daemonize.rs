@@ -0,0 +1,142 @@
+use crate::Result;
+use std::fs::File;
+use std::io::Write;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+pub fn pidfile_path() -> PathBuf {
+    // XDG_RUNTIME_DIR is per user, the temporary directory fallback isn't
+    let name = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(_) => String::from("batmon.pid"),
+        None => format!("batmon-{}.pid", unsafe { libc::getuid() }),
+    };
+    crate::paths::runtime_dir().join(name)
+}
+
+/// The pid of the running daemon, if there is one
+fn running_pid(pidfile: &Path) -> Option<libc::pid_t> {
+    let pid = std::fs::read_to_string(pidfile).ok()?.trim().parse().ok()?;
+    // Signal 0 only checks that the process exists
+    (unsafe { libc::kill(pid, 0) } == 0).then_some(pid)
+}
+
+fn fork() -> Result<libc::pid_t> {
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error())?,
+        pid => Ok(pid),
+    }
+}
+
+/// Spawn a process forwarding the daemon's output to the journal through systemd-cat,
+/// or to syslog through logger, returning the pipe to it
+fn spawn_journal() -> Option<OwnedFd> {
+    for program in ["systemd-cat", "logger"] {
+        let child = Command::new(program)
+            .args(["-t", "batmon"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        if let Some(stdin) = child.ok().and_then(|mut c| c.stdin.take()) {
+            return Some(stdin.into());
+        }
+    }
+    None
+}
+
+fn redirect(from: &impl AsRawFd, to: libc::c_int) -> Result<()> {
+    if unsafe { libc::dup2(from.as_raw_fd(), to) } == -1 {
+        Err(std::io::Error::last_os_error())?
+    }
+    Ok(())
+}
+
+/// Detach from the terminal and keep running in the background.
+///
+/// Anything that can fail is done before forking so errors still reach the terminal.
+/// Only the background process returns; libnotify must be initialized after this.
+pub fn daemonize(log_file: Option<&Path>) -> Result<()> {
+    let pidfile_path = pidfile_path();
+    if let Some(pid) = running_pid(&pidfile_path) {
+        return Err(format!("batmon is already running with pid {pid}").into());
+    }
+
+    let mut pidfile = File::create(&pidfile_path).map_err(|e| {
+        format!(
+            "Failed to create pidfile {}: {e}",
+            pidfile_path.to_string_lossy()
+        )
+    })?;
+    let null = File::open("/dev/null")?;
+    let log_file = match log_file {
+        Some(path) => Some(
+            File::options()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Failed to open log file {}: {e}", path.to_string_lossy()))?,
+        ),
+        None => None,
+    };
+
+    std::io::stdout().flush()?;
+    std::io::stderr().flush()?;
+
+    let pid = fork()?;
+    if pid > 0 {
+        // Wait for the intermediate process so the pidfile is written once we exit
+        let mut status = 0;
+        unsafe {
+            libc::waitpid(pid, &mut status, 0);
+            libc::_exit(libc::WEXITSTATUS(status));
+        }
+    }
+
+    // Leave the terminal's session, then fork again so the daemon isn't a session leader
+    // and can never reacquire a controlling terminal
+    if unsafe { libc::setsid() } == -1 {
+        unsafe { libc::_exit(1) };
+    }
+    let pid = fork()?;
+    if pid > 0 {
+        let status = match writeln!(pidfile, "{pid}") {
+            Ok(()) => 0,
+            Err(_) => 1,
+        };
+        unsafe { libc::_exit(status) };
+    }
+    drop(pidfile);
+
+    std::env::set_current_dir("/")?;
+    let log: OwnedFd = match log_file {
+        Some(file) => file.into(),
+        None => spawn_journal().unwrap_or(null.try_clone()?.into()),
+    };
+    redirect(&null, libc::STDIN_FILENO)?;
+    redirect(&log, libc::STDOUT_FILENO)?;
+    redirect(&log, libc::STDERR_FILENO)?;
+    Ok(())
+}
+
+/// Point the daemon's output at a fresh `log_file`, after the old one has been rotated away
+pub fn reopen_log(log_file: &Path) -> Result<()> {
+    let log = File::options().create(true).append(true).open(log_file)?;
+    redirect(&log, libc::STDOUT_FILENO)?;
+    redirect(&log, libc::STDERR_FILENO)?;
+    Ok(())
+}
+
+/// Stop the daemon recorded in the pidfile
+pub fn stop() -> Result<()> {
+    let pidfile_path = pidfile_path();
+    let pid = running_pid(&pidfile_path).ok_or("batmon is not running")?;
+
+    if unsafe { libc::kill(pid, libc::SIGTERM) } == -1 {
+        Err(std::io::Error::last_os_error())?
+    }
+    let _ = std::fs::remove_file(&pidfile_path);
+
+    println!("Stopped batmon (pid {pid})");
+    Ok(())
+}
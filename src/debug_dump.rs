@@ -0,0 +1,68 @@
+use crate::Result;
+use batmon::dump::{self, Contents, DeviceDump};
+use batmon::{Battery, FindOptions};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// The options that decide which battery is used and how it is read
+#[derive(Serialize)]
+pub struct Settings {
+    pub device: Option<String>,
+    pub device_index: Option<usize>,
+    pub primary: Option<String>,
+    pub include_ups: bool,
+    pub extra_roots: Vec<PathBuf>,
+    pub ignore: Vec<String>,
+    pub priority: Vec<String>,
+    pub no_cache: bool,
+    pub cache_file: Option<PathBuf>,
+    pub current_source: String,
+    pub lenient_parse: bool,
+    pub unit_heuristics: bool,
+}
+
+#[derive(Serialize)]
+struct DebugDump<'a> {
+    version: &'static str,
+    /// The battery detection chose, or why it chose none
+    chosen: Option<String>,
+    detection_error: Option<String>,
+    settings: &'a Settings,
+    /// The batteries in the order `--device-index` counts them
+    order: Vec<String>,
+    cache: Option<Contents>,
+    devices: Vec<DeviceDump>,
+}
+
+/// Collect everything needed to triage a report into one JSON document,
+/// written to `out` or printed
+pub fn run(
+    found: &Result<Battery>,
+    options: &FindOptions,
+    settings: &Settings,
+    include_identifiers: bool,
+    out: Option<&Path>,
+) -> Result<()> {
+    let dump = DebugDump {
+        version: env!("CARGO_PKG_VERSION"),
+        chosen: found.as_ref().ok().map(|bat| bat.name.clone()),
+        detection_error: found.as_ref().err().map(|e| e.to_string()),
+        cache: settings.cache_file.as_deref().map(Contents::read),
+        settings,
+        order: Battery::find_all(options)
+            .into_iter()
+            .map(|bat| bat.name)
+            .collect(),
+        devices: dump::devices(options, include_identifiers),
+    };
+
+    let json = serde_json::to_string_pretty(&dump)?;
+    match out {
+        Some(path) => {
+            std::fs::write(path, json + "\n")?;
+            eprintln!("Wrote debug dump to {}", path.display());
+        }
+        None => println!("{json}"),
+    }
+    Ok(())
+}
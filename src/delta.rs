@@ -0,0 +1,48 @@
+use crate::Result;
+use batmon::BatteryState;
+use std::path::PathBuf;
+
+fn default_path() -> Option<PathBuf> {
+    Some(crate::paths::state_dir()?.join("last-state.json"))
+}
+
+/// The differences worth reporting between two states, as `field: old -> new`
+fn changes(old: &BatteryState, new: &BatteryState) -> Vec<String> {
+    let mut changes = Vec::new();
+    if old.status != new.status {
+        changes.push(format!("status: {} -> {}", old.status, new.status));
+    }
+    if old.level != new.level {
+        changes.push(format!("level: {} -> {}", old.level, new.level));
+    }
+    if old.capacity != new.capacity {
+        changes.push(format!("capacity: {} -> {}", old.capacity, new.capacity));
+    }
+    if old.cycles != new.cycles {
+        changes.push(format!("cycles: {} -> {}", old.cycles, new.cycles));
+    }
+    changes
+}
+
+/// Print what changed since the state stored by the last run, then store the current state
+pub fn print_delta(state: &BatteryState) -> Result<()> {
+    let path = default_path().ok_or("Failed to locate the state file")?;
+
+    // A missing or unreadable state means this is the first run, with nothing to compare
+    let previous: Option<BatteryState> = std::fs::read(&path)
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok());
+    if let Some(previous) = previous {
+        for change in changes(&previous, state) {
+            println!("{change}");
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec(state)?)?;
+    std::fs::rename(tmp, &path)?;
+    Ok(())
+}
@@ -1,60 +1,301 @@
-use std::path::PathBuf;
+use crate::status::ChargingStatus;
+use crate::units::UnitFamily;
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use strum::{Display, EnumString};
 
 pub struct Device {
     pub path: PathBuf,
 }
 
+/// Which of the attributes batmon reads a device has, and the resulting score
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceRating {
+    /// Each attribute looked for, and whether the device has it
+    pub features: Vec<(&'static str, bool)>,
+    /// The number of attributes the device has
+    pub score: u8,
+}
+
+impl DeviceRating {
+    /// The score of a device with every feature
+    pub const MAX: u8 = 6;
+
+    pub fn new(features: Vec<(&'static str, bool)>) -> Self {
+        let score = features.iter().filter(|(_, present)| *present).count() as u8;
+        Self { features, score }
+    }
+
+    /// The attributes the device is missing
+    pub fn missing(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.features
+            .iter()
+            .filter(|(_, present)| !present)
+            .map(|(attribute, _)| *attribute)
+    }
+}
+
+/// A device considered during detection, with what it is ranked by
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub name: String,
+    pub rating: DeviceRating,
+    /// Whether `present` reads 1
+    pub present: bool,
+    /// The place of the device in the priority list, if it is listed
+    pub priority: Option<usize>,
+}
+
+/// The rule that decided between two candidates
+#[derive(Debug, Clone, Copy, Display, PartialEq, Eq)]
+pub enum Preference {
+    #[strum(serialize = "the priority list")]
+    Priority,
+    #[strum(serialize = "a higher rating")]
+    Rating,
+    #[strum(serialize = "being present")]
+    Present,
+    #[strum(serialize = "a lower BAT<N> number")]
+    BatteryNumber,
+    #[strum(serialize = "name order")]
+    Name,
+}
+
+/// The number of a conventionally named battery like `BAT1`
+fn battery_number(name: &str) -> Option<u64> {
+    name.strip_prefix("BAT")?.parse().ok()
+}
+
+/// Order two candidates with the preferred one first, along with the rule that decided.
+/// Devices in the priority list win in its order, then higher ratings, then present devices,
+/// then `BAT<N>` names by ascending number, then names in lexicographic order, so ties never
+/// depend on the directory order.
+pub fn compare_candidates(a: &Candidate, b: &Candidate) -> (Ordering, Option<Preference>) {
+    let rules = [
+        (
+            match (a.priority, b.priority) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+            Preference::Priority,
+        ),
+        (b.rating.score.cmp(&a.rating.score), Preference::Rating),
+        (b.present.cmp(&a.present), Preference::Present),
+        (
+            match (battery_number(&a.name), battery_number(&b.name)) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+            Preference::BatteryNumber,
+        ),
+        (a.name.cmp(&b.name), Preference::Name),
+    ];
+    rules
+        .into_iter()
+        .find(|(ordering, _)| ordering.is_ne())
+        .map_or((Ordering::Equal, None), |(ordering, rule)| {
+            (ordering, Some(rule))
+        })
+}
+
+/// The kind of power supply a device is, from its `type` attribute
+#[derive(Debug, Clone, Copy, Display, PartialEq, Eq)]
+pub enum DeviceKind {
+    Battery,
+    /// An AC adapter
+    Mains,
+    /// A USB power source, including the USB_* types older kernels report
+    #[strum(serialize = "USB")]
+    Usb,
+    /// An uninterruptible power supply, which has battery-like attributes
+    #[strum(serialize = "UPS")]
+    Ups,
+    /// A wireless charger
+    Wireless,
+    /// A type batmon doesn't know, or a device without one
+    Unknown,
+}
+
+impl DeviceKind {
+    /// Parse a `type` value, tolerating ones added to the kernel later
+    pub fn parse(ty: &str) -> Self {
+        match ty {
+            "Battery" => DeviceKind::Battery,
+            "Mains" => DeviceKind::Mains,
+            "UPS" => DeviceKind::Ups,
+            "Wireless" => DeviceKind::Wireless,
+            // Before 5.x the USB charger types were reported directly (USB_DCP, USB_PD, ...)
+            ty if ty == "USB" || ty.starts_with("USB_") || ty == "BrickID" => DeviceKind::Usb,
+            _ => DeviceKind::Unknown,
+        }
+    }
+}
+
+/// What a device powers, from its `scope` attribute
+#[derive(Debug, Clone, Copy, Display, EnumString, PartialEq, Eq)]
+pub enum Scope {
+    /// The system itself
+    System,
+    /// A peripheral
+    Device,
+}
+
+/// Why a battery without a scope was taken for a peripheral's
+#[derive(Debug, Clone, Copy, Display, PartialEq, Eq)]
+pub enum PeripheralRule {
+    #[strum(serialize = "peripheral driver name")]
+    PeripheralName,
+    #[strum(serialize = "no charge or energy attributes")]
+    NoChargeOrEnergy,
+    #[strum(serialize = "input device model name")]
+    InputModel,
+}
+
+/// Whether `name` is one HID and Bluetooth peripheral drivers give their batteries: `hid-*`,
+/// `wacom_*`, or `*_battery_<mac>` (like sony_controller_battery_aa:bb:cc:dd:ee:ff).
+/// System fuel gauges like `bq27xxx_battery` don't match.
+pub fn is_peripheral_name(name: &str) -> bool {
+    let is_mac = |s: &str| {
+        let parts: Vec<_> = s.split(':').collect();
+        parts.len() == 6
+            && parts
+                .iter()
+                .all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_hexdigit()))
+    };
+    name.starts_with("hid-")
+        || name.starts_with("wacom_")
+        || name
+            .rsplit_once("_battery_")
+            .is_some_and(|(_, mac)| is_mac(mac))
+}
+
+/// Words in the model name of input peripherals that report a battery
+const INPUT_MODEL_WORDS: [&str; 6] = [
+    "mouse",
+    "keyboard",
+    "controller",
+    "gamepad",
+    "headset",
+    "trackpad",
+];
+
 impl Device {
+    /// Whether the device is a battery powering the system, as opposed to a peripheral's.
+    /// Devices that don't report a scope are judged by `peripheral_rule`.
     pub fn is_system_battery(&self) -> bool {
-        let meta = match std::fs::metadata(&self.path) {
-            Ok(m) => m,
-            Err(_) => return false,
-        };
+        self.check_system_battery(true)
+    }
 
-        if !meta.is_dir() {
+    /// Whether the device can be used when named explicitly. Only the type and scope are
+    /// checked, the user knows better than the heuristics for devices without a scope.
+    pub fn is_selectable_battery(&self) -> bool {
+        self.check_system_battery(false)
+    }
+
+    fn check_system_battery(&self, heuristics: bool) -> bool {
+        if !std::fs::metadata(&self.path).is_ok_and(|m| m.is_dir()) {
             return false;
         }
 
-        // Type should contain "Battery" if the device is a battery
-        let type_file = self.path.join("type");
-
-        let ty = match std::fs::read_to_string(type_file) {
-            Ok(res) => res,
-            Err(_) => return false,
-        };
-
-        if ty.trim() != "Battery" {
-            debug!(
-                "Device '{}' ('{}') rejected: Bad type",
-                self.path.file_name().unwrap_or_default().to_string_lossy(),
-                ty.trim(),
-            );
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        let kind = self.kind();
+        if kind != DeviceKind::Battery {
+            debug!("Device '{name}' ('{kind}') rejected: Bad type");
             return false;
         }
 
         // Scope may or may not exist.
-        // It can be ignored if not present, but it should contain "System" if it exists.
-        let scope_file = self.path.join("scope");
-
-        match std::fs::read_to_string(scope_file) {
-            Ok(s) => {
-                let valid = s.trim() == "System";
-
-                if valid {
-                    debug!(
-                        "Device '{}' is (probably) a system battery",
-                        self.path.file_name().unwrap_or_default().to_string_lossy(),
-                    );
-                } else {
-                    debug!(
-                        "Device '{}' ('{}') rejected: Bad scope",
-                        self.path.file_name().unwrap_or_default().to_string_lossy(),
-                        s.trim(),
-                    );
-                }
-                valid
+        // It can be ignored if not present, but it should be System if it exists.
+        match self.scope() {
+            Some(Scope::System) => {
+                debug!("Device '{name}' is (probably) a system battery");
+                true
             }
-            Err(_) => true,
+            Some(scope) => {
+                debug!("Device '{name}' ('{scope}') rejected: Bad scope");
+                false
+            }
+            None if !heuristics => true,
+            None => match self.peripheral_rule() {
+                Some(rule) => {
+                    debug!("Device '{name}' rejected: No scope and {rule}");
+                    false
+                }
+                None => true,
+            },
+        }
+    }
+
+    /// What kind of power supply the device is, from its `type`
+    pub fn kind(&self) -> DeviceKind {
+        std::fs::read_to_string(self.path.join("type"))
+            .map_or(DeviceKind::Unknown, |ty| DeviceKind::parse(ty.trim()))
+    }
+
+    /// What the device powers, or `None` if it doesn't say
+    pub fn scope(&self) -> Option<Scope> {
+        std::fs::read_to_string(self.path.join("scope"))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    /// The first rule by which the device looks like a peripheral's battery, for drivers
+    /// that don't set a scope (like some Bluetooth gamepads)
+    pub fn peripheral_rule(&self) -> Option<PeripheralRule> {
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        if is_peripheral_name(&name) {
+            return Some(PeripheralRule::PeripheralName);
+        }
+
+        let families = [
+            "charge_now",
+            "charge_counter",
+            "charge_full",
+            "energy_now",
+            "energy_full",
+        ];
+        if !families.iter().any(|f| self.has_file_available(f)) {
+            return Some(PeripheralRule::NoChargeOrEnergy);
+        }
+
+        let model = std::fs::read_to_string(self.path.join("model_name"))
+            .unwrap_or_default()
+            .to_lowercase();
+        if INPUT_MODEL_WORDS.iter().any(|w| model.contains(w)) {
+            return Some(PeripheralRule::InputModel);
+        }
+        None
+    }
+
+    /// Whether the device is an uninterruptible power supply, which is only monitored when
+    /// asked to or when there is no system battery
+    pub fn is_ups(&self) -> bool {
+        self.kind() == DeviceKind::Ups
+    }
+
+    /// Whether the device reports itself present, which hot-swappable batteries may not be
+    pub fn is_present(&self) -> bool {
+        std::fs::read_to_string(self.path.join("present")).is_ok_and(|p| p.trim() == "1")
+    }
+
+    /// How the device is ranked during detection
+    pub fn candidate(&self) -> Candidate {
+        Candidate {
+            name: self
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            rating: self.rating_detailed(),
+            present: self.is_present(),
+            priority: None,
         }
     }
 
@@ -62,19 +303,277 @@ impl Device {
         std::fs::metadata(self.path.join(file)).is_ok()
     }
 
+    /// Energy reporting devices are only used when the device doesn't report its charge
+    pub fn family(&self) -> UnitFamily {
+        if !self.has_file_available("charge_now")
+            && !self.has_file_available("charge_counter")
+            && self.has_file_available("energy_now")
+        {
+            UnitFamily::Energy
+        } else {
+            UnitFamily::Charge
+        }
+    }
+
+    /// The attribute holding the current charge.
+    /// Android style devices report it as charge_counter instead of charge_now.
+    pub fn charge_attribute(&self) -> &'static str {
+        let (_, charge, _) = self.family().attributes();
+        if charge == "charge_now"
+            && !self.has_file_available(charge)
+            && self.has_file_available("charge_counter")
+        {
+            "charge_counter"
+        } else {
+            charge
+        }
+    }
+
+    /// Check which of the attributes batmon reads the device has
+    pub fn rating_detailed(&self) -> DeviceRating {
+        let (capacity, _, current) = self.family().attributes();
+        let charge = self.charge_attribute();
+
+        DeviceRating::new(
+            [
+                current,
+                "capacity",
+                capacity,
+                charge,
+                "cycle_count",
+                "status",
+            ]
+            .into_iter()
+            .map(|attribute| {
+                debug_assert!(
+                    KNOWN_ATTRIBUTES
+                        .iter()
+                        .any(|(known, _)| *known == attribute),
+                    "rated attribute {attribute} is missing from KNOWN_ATTRIBUTES"
+                );
+                (attribute, self.has_file_available(attribute))
+            })
+            .collect(),
+        )
+    }
+
+    /// The number of the attributes batmon reads the device has, out of `DeviceRating::MAX`
     pub fn rating(&self) -> u8 {
-        [
-            self.has_file_available("current_now"),
-            self.has_file_available("capacity"),
-            self.has_file_available("charge_full"),
-            self.has_file_available("charge_now"),
-            self.has_file_available("cycle_count"),
-            self.has_file_available("status"),
-        ]
+        self.rating_detailed().score
+    }
+}
+
+/// How batmon interprets an attribute it reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interpretation {
+    /// An unsigned number in the unit
+    Number(&'static str),
+    /// A number in the unit that some drivers report negative
+    Signed(&'static str),
+    /// A `ChargingStatus`
+    Status,
+    /// 0 or 1
+    Flag,
+    Text,
+}
+
+/// Every attribute batmon reads, including all those the rating looks for, and how
+const KNOWN_ATTRIBUTES: [(&str, Interpretation); 23] = [
+    ("capacity", Interpretation::Number("%")),
+    ("capacity_level", Interpretation::Text),
+    ("charge_full", Interpretation::Number("uAh")),
+    ("charge_full_design", Interpretation::Number("uAh")),
+    ("charge_now", Interpretation::Number("uAh")),
+    ("charge_counter", Interpretation::Number("uAh")),
+    ("energy_full", Interpretation::Number("uWh")),
+    ("energy_full_design", Interpretation::Number("uWh")),
+    ("energy_now", Interpretation::Number("uWh")),
+    ("current_now", Interpretation::Signed("uA")),
+    ("power_now", Interpretation::Signed("uW")),
+    ("voltage_now", Interpretation::Number("uV")),
+    ("cycle_count", Interpretation::Number("cycles")),
+    ("status", Interpretation::Status),
+    ("time_to_empty_now", Interpretation::Number("s")),
+    ("time_to_empty_avg", Interpretation::Number("s")),
+    ("time_to_full_now", Interpretation::Number("s")),
+    ("time_to_full_avg", Interpretation::Number("s")),
+    ("charge_control_end_threshold", Interpretation::Number("%")),
+    ("present", Interpretation::Flag),
+    ("online", Interpretation::Flag),
+    ("scope", Interpretation::Text),
+    ("type", Interpretation::Text),
+];
+
+/// What is behind one entry of a device's directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttributeValue {
+    /// An attribute batmon reads, with its value as batmon understands it
+    Known(String),
+    /// An attribute batmon reads, but whose contents it can't make sense of
+    Invalid(String),
+    /// An attribute batmon doesn't read, with its trimmed contents
+    Unrecognized(String),
+    Directory,
+    Unreadable(String),
+}
+
+impl std::fmt::Display for AttributeValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttributeValue::Known(value) => write!(f, "{value}"),
+            AttributeValue::Invalid(raw) => write!(f, "invalid: {raw:?}"),
+            AttributeValue::Unrecognized(raw) => write!(f, "unrecognized: {raw:?}"),
+            AttributeValue::Directory => write!(f, "directory"),
+            AttributeValue::Unreadable(e) => write!(f, "unreadable: {e}"),
+        }
+    }
+}
+
+/// Interpret the trimmed contents of an attribute, or `None` if they don't fit
+fn interpret(interpretation: Interpretation, raw: &str) -> Option<String> {
+    match interpretation {
+        Interpretation::Number(unit) => Some(format!("{} {unit}", raw.parse::<u64>().ok()?)),
+        Interpretation::Signed(unit) => Some(format!("{} {unit}", raw.parse::<i64>().ok()?)),
+        Interpretation::Status => Some(raw.parse::<ChargingStatus>().ok()?.to_string()),
+        Interpretation::Flag => match raw {
+            "0" => Some(String::from("no")),
+            "1" => Some(String::from("yes")),
+            _ => None,
+        },
+        Interpretation::Text => Some(raw.to_owned()),
+    }
+}
+
+impl Device {
+    /// Every entry of the device's directory by name, interpreted where batmon knows how
+    pub fn attributes(&self) -> Vec<(String, AttributeValue)> {
+        let Ok(entries) = std::fs::read_dir(&self.path) else {
+            return Vec::new();
+        };
+
+        let mut attributes: Vec<_> = entries
+            .filter_map(|e| e.ok())
+            .map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let value = if entry.path().is_dir() {
+                    AttributeValue::Directory
+                } else {
+                    match std::fs::read(entry.path()) {
+                        Err(e) => AttributeValue::Unreadable(e.to_string()),
+                        Ok(data) => {
+                            let raw = String::from_utf8_lossy(&data).trim().to_owned();
+                            match KNOWN_ATTRIBUTES.iter().find(|(known, _)| *known == name) {
+                                Some(&(_, interpretation)) => match interpret(interpretation, &raw)
+                                {
+                                    Some(value) => AttributeValue::Known(value),
+                                    None => AttributeValue::Invalid(raw),
+                                },
+                                None => AttributeValue::Unrecognized(raw),
+                            }
+                        }
+                    }
+                };
+                (name, value)
+            })
+            .collect();
+        attributes.sort_by(|a, b| a.0.cmp(&b.0));
+        attributes
+    }
+}
+
+/// The names of the devices in `root` with their types (e.g. `Battery` or `Mains`), by name
+pub fn list(root: &Path) -> Vec<(String, String)> {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return Vec::new();
+    };
+
+    let mut devices: Vec<_> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| {
+            let ty = std::fs::read_to_string(e.path().join("type"))
+                .map(|ty| ty.trim().to_owned())
+                .unwrap_or_else(|_| String::from("unknown type"));
+            (e.file_name().to_string_lossy().into_owned(), ty)
+        })
+        .collect();
+    devices.sort();
+    devices
+}
+
+/// Clean up a device name as typed or read back from a file, dropping surrounding whitespace
+/// (including the CR an editor may leave behind) and the trailing slash shells complete
+pub fn normalize_name(name: &str) -> &str {
+    name.trim().trim_end_matches('/').trim_end()
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any run of characters and `?` any
+/// single character
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // The last `*` seen and the position in the name it was tried at, to backtrack to
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    p = sp + 1;
+                    n = sn + 1;
+                    star = Some((sp, sn + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The devices in `root` named `name` ignoring case, for users who don't know sysfs is
+/// case sensitive
+pub fn matches_ignoring_case(root: &Path, name: &str) -> Vec<String> {
+    list(root)
         .into_iter()
-        .filter(|b| *b)
-        .count() as u8
+        .map(|(n, _)| n)
+        .filter(|n| n.eq_ignore_ascii_case(name))
+        .collect()
+}
+
+/// The number of single character edits between two names, ignoring case
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<_> = a.to_lowercase().chars().collect();
+    let b: Vec<_> = b.to_lowercase().chars().collect();
+
+    let mut previous: Vec<_> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
     }
+    previous[b.len()]
+}
+
+/// The name closest to `name`, if it is close enough to probably be what was meant.
+/// Roughly one edit in three characters is allowed, and differences in case are free.
+pub fn closest_name<'a>(name: &str, names: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    names
+        .into_iter()
+        .map(|n| (edit_distance(name, n), n))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, n)| n)
 }
 
 impl From<PathBuf> for Device {
@@ -82,3 +581,308 @@ impl From<PathBuf> for Device {
         Device { path: value }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::FakeRoot;
+
+    /// A candidate with a full rating, present and without a peripheral's name
+    fn candidate(name: &str) -> Candidate {
+        Candidate {
+            name: name.to_owned(),
+            rating: DeviceRating::new(vec![("capacity", true), ("status", true)]),
+            present: true,
+            priority: None,
+        }
+    }
+
+    fn preference(a: &Candidate, b: &Candidate) -> (Ordering, Option<Preference>) {
+        let (ordering, rule) = compare_candidates(a, b);
+        // The order must not depend on which candidate comes first
+        assert_eq!(compare_candidates(b, a), (ordering.reverse(), rule));
+        (ordering, rule)
+    }
+
+    #[test]
+    fn detailed_rating() {
+        let root = FakeRoot::new();
+        let full = Device::from(root.battery("BAT0", 55));
+        let rating = full.rating_detailed();
+        assert_eq!(rating.score, DeviceRating::MAX);
+        assert_eq!(rating.missing().count(), 0);
+        assert_eq!(full.rating(), rating.score);
+
+        let energy = Device::from(root.device(
+            "BAT1",
+            &[
+                ("type", "Battery"),
+                ("status", "Discharging"),
+                ("energy_full", "50000000"),
+                ("energy_now", "25000000"),
+            ],
+        ));
+        let rating = energy.rating_detailed();
+        assert_eq!(
+            rating.features,
+            [
+                ("power_now", false),
+                ("capacity", false),
+                ("energy_full", true),
+                ("energy_now", true),
+                ("cycle_count", false),
+                ("status", true),
+            ]
+        );
+        assert_eq!(rating.score, 3);
+        assert_eq!(
+            rating.missing().collect::<Vec<_>>(),
+            ["power_now", "capacity", "cycle_count"]
+        );
+
+        // A charge counter stands in for the charge
+        let counter = Device::from(root.device(
+            "BAT2",
+            &[("charge_full", "4000000"), ("charge_counter", "2000000")],
+        ));
+        let rating = counter.rating_detailed();
+        assert!(rating.features.contains(&("charge_counter", true)));
+        assert_eq!(rating.score, 2);
+    }
+
+    #[test]
+    fn candidate_order() {
+        let listed = Candidate {
+            priority: Some(0),
+            rating: DeviceRating::new(vec![("capacity", true)]),
+            ..candidate("BAT9")
+        };
+        let first = Candidate {
+            priority: Some(0),
+            ..candidate("BAT1")
+        };
+        let second = Candidate {
+            priority: Some(1),
+            ..candidate("BAT0")
+        };
+        assert_eq!(
+            preference(&first, &second),
+            (Ordering::Less, Some(Preference::Priority))
+        );
+        assert_eq!(
+            preference(&listed, &candidate("BAT0")),
+            (Ordering::Less, Some(Preference::Priority))
+        );
+
+        let weaker = Candidate {
+            rating: DeviceRating::new(vec![("capacity", true), ("status", false)]),
+            ..candidate("BAT0")
+        };
+        assert_eq!(
+            preference(&candidate("BAT1"), &weaker),
+            (Ordering::Less, Some(Preference::Rating))
+        );
+
+        let absent = Candidate {
+            present: false,
+            ..candidate("BAT0")
+        };
+        assert_eq!(
+            preference(&candidate("BAT1"), &absent),
+            (Ordering::Less, Some(Preference::Present))
+        );
+
+        assert_eq!(
+            preference(&candidate("BAT2"), &candidate("BAT10")),
+            (Ordering::Less, Some(Preference::BatteryNumber))
+        );
+        assert_eq!(
+            preference(&candidate("BAT10"), &candidate("CMB0")),
+            (Ordering::Less, Some(Preference::BatteryNumber))
+        );
+        assert_eq!(
+            preference(&candidate("CMB0"), &candidate("CMB1")),
+            (Ordering::Less, Some(Preference::Name))
+        );
+        assert_eq!(
+            preference(&candidate("BATX"), &candidate("CMB0")),
+            (Ordering::Less, Some(Preference::Name))
+        );
+        assert_eq!(
+            preference(&candidate("BAT0"), &candidate("BAT0")),
+            (Ordering::Equal, None)
+        );
+    }
+
+    #[test]
+    fn normalized_names() {
+        for (name, expected) in [
+            ("BAT0", "BAT0"),
+            ("  BAT0  ", "BAT0"),
+            ("BAT0\n", "BAT0"),
+            ("BAT0\r\n", "BAT0"),
+            ("BAT0/", "BAT0"),
+            ("BAT0//", "BAT0"),
+            ("BAT0/\r\n", "BAT0"),
+            ("BAT0 /", "BAT0"),
+            ("bat0", "bat0"),
+            ("/", ""),
+            ("", ""),
+            // Only trailing separators go, names with a '/' inside are rejected later
+            ("power_supply/BAT0", "power_supply/BAT0"),
+        ] {
+            assert_eq!(normalize_name(name), expected, "{name:?}");
+        }
+    }
+
+    #[test]
+    fn device_kinds() {
+        // Every type the kernel documents for power_supply, including the older USB types
+        for (ty, kind) in [
+            ("Battery", DeviceKind::Battery),
+            ("UPS", DeviceKind::Ups),
+            ("Mains", DeviceKind::Mains),
+            ("USB", DeviceKind::Usb),
+            ("Wireless", DeviceKind::Wireless),
+            ("USB_DCP", DeviceKind::Usb),
+            ("USB_CDP", DeviceKind::Usb),
+            ("USB_ACA", DeviceKind::Usb),
+            ("USB_C", DeviceKind::Usb),
+            ("USB_PD", DeviceKind::Usb),
+            ("USB_PD_DRP", DeviceKind::Usb),
+            ("BrickID", DeviceKind::Usb),
+            ("Unknown", DeviceKind::Unknown),
+            ("", DeviceKind::Unknown),
+            ("battery", DeviceKind::Unknown),
+            ("Solar", DeviceKind::Unknown),
+        ] {
+            assert_eq!(DeviceKind::parse(ty), kind, "{ty:?}");
+        }
+    }
+
+    #[test]
+    fn glob_patterns() {
+        for (pattern, name, matches) in [
+            ("dock_battery", "dock_battery", true),
+            ("dock_battery", "dock_battery2", false),
+            ("dock_battery", "Dock_battery", false),
+            ("hid-*", "hid-0005:046D:B01A.0001-battery", true),
+            ("hid-*", "hid-", true),
+            ("hid-*", "BAT0", false),
+            ("*", "", true),
+            ("*", "BAT0", true),
+            ("", "", true),
+            ("", "BAT0", false),
+            ("BAT?", "BAT1", true),
+            ("BAT?", "BAT", false),
+            ("BAT?", "BAT10", false),
+            ("*_battery", "max170xx_battery", true),
+            ("*_battery", "max170xx_battery_0", false),
+            ("*bat*", "dock_battery", true),
+            ("a*b*c", "aXbYbZc", true),
+            ("a*b*c", "aXbYbZ", false),
+            ("**", "BAT0", true),
+            ("?*", "", false),
+            // Only `*` and `?` are special
+            ("BAT[01]", "BAT0", false),
+            ("BAT[01]", "BAT[01]", true),
+        ] {
+            assert_eq!(glob_matches(pattern, name), matches, "{pattern} {name}");
+        }
+    }
+
+    /// The attributes of a battery without a scope
+    const UNSCOPED: [(&str, &str); 4] = [
+        ("type", "Battery"),
+        ("status", "Discharging"),
+        ("capacity", "55"),
+        ("charge_now", "2200000"),
+    ];
+
+    #[test]
+    fn peripheral_names() {
+        for name in [
+            "max170xx_battery",
+            "bq27xxx_battery",
+            "axp20x_battery",
+            "BAT0",
+        ] {
+            assert!(!is_peripheral_name(name), "{name}");
+        }
+        for name in [
+            "hid-0005:046D:B01A.0001-battery",
+            "sony_controller_battery_aa:bb:cc:dd:ee:ff",
+            "wacom_battery_0",
+        ] {
+            assert!(is_peripheral_name(name), "{name}");
+        }
+        // Only a MAC address makes a `_battery_` suffix a peripheral's
+        assert!(!is_peripheral_name("hidpp_battery_0"));
+        assert!(!is_peripheral_name("x_battery_aa:bb:cc"));
+    }
+
+    #[test]
+    fn unscoped_fuel_gauge_is_a_system_battery() {
+        let root = FakeRoot::new();
+        let device = Device::from(root.device("bq27xxx_battery", &UNSCOPED));
+        assert!(device.is_system_battery());
+        assert_eq!(device.peripheral_rule(), None);
+    }
+
+    #[test]
+    fn unscoped_peripheral_name_is_rejected() {
+        let root = FakeRoot::new();
+        for name in [
+            "hid-0005:054C:09CC.0001-battery",
+            "sony_controller_battery_aa:bb:cc:dd:ee:ff",
+            "wacom_battery_0",
+        ] {
+            let device = Device::from(root.device(name, &UNSCOPED));
+            assert_eq!(
+                device.peripheral_rule(),
+                Some(PeripheralRule::PeripheralName),
+                "{name}"
+            );
+            assert!(!device.is_system_battery(), "{name}");
+            // Named explicitly, the heuristics are bypassed
+            assert!(device.is_selectable_battery(), "{name}");
+        }
+    }
+
+    #[test]
+    fn scoped_peripheral_name_is_judged_by_scope() {
+        let root = FakeRoot::new();
+        let path = root.device("hid-gamepad-battery", &UNSCOPED);
+        std::fs::write(path.join("scope"), "System\n").unwrap();
+        assert!(Device::from(path).is_system_battery());
+    }
+
+    #[test]
+    fn peripheral_rules() {
+        let root = FakeRoot::new();
+        let path = root.device(
+            "gauge",
+            &[
+                ("type", "Battery"),
+                ("status", "Discharging"),
+                ("capacity", "55"),
+            ],
+        );
+        let device = Device::from(path.clone());
+        assert_eq!(
+            device.peripheral_rule(),
+            Some(PeripheralRule::NoChargeOrEnergy)
+        );
+        assert!(!device.is_system_battery());
+        // Named explicitly, the heuristics are bypassed
+        assert!(device.is_selectable_battery());
+
+        std::fs::write(path.join("charge_now"), "100\n").unwrap();
+        std::fs::write(path.join("model_name"), "Wireless Mouse M705\n").unwrap();
+        assert_eq!(device.peripheral_rule(), Some(PeripheralRule::InputModel));
+
+        std::fs::write(path.join("model_name"), "5B10W13930\n").unwrap();
+        assert_eq!(device.peripheral_rule(), None);
+        assert!(device.is_system_battery());
+    }
+}
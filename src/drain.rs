@@ -0,0 +1,188 @@
+//! Noticing the battery draining much faster than usual, as with a runaway process.
+
+use batmon::{BatteryState, BatteryValue, TimeFormat};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// The usual discharging draw, learned over every discharge and kept across runs
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Baseline {
+    /// The average draw in W
+    pub watts: f64,
+    /// How much discharging the average has seen, in seconds
+    pub learned: u64,
+}
+
+impl Baseline {
+    pub fn default_path() -> Option<PathBuf> {
+        Some(crate::paths::state_dir()?.join("drain-baseline.json"))
+    }
+
+    /// Load the baseline at `path`, starting from nothing if it is missing or corrupt
+    pub fn load(path: &PathBuf) -> Self {
+        let baseline = std::fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| serde_json::from_slice(&data).map_err(|e| e.to_string()));
+        match baseline {
+            Ok(baseline) => baseline,
+            Err(e) => {
+                if path.exists() {
+                    warn!("Resetting drain baseline at {}: {e}", path.display());
+                }
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &PathBuf) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_vec(self)?)
+    }
+}
+
+/// A draw well above the baseline, kept up for long enough to be worth a notification
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrainAlert {
+    pub watts: f64,
+    pub baseline: f64,
+    /// The time remaining at the current draw and at the baseline, where the energy is known
+    pub remaining: Option<(Duration, Duration)>,
+}
+
+impl DrainAlert {
+    pub fn body(&self, name: &str) -> String {
+        let mut body = format!(
+            "{name} is drawing {:.1} W, {:.1}x the usual {:.1} W",
+            self.watts,
+            self.watts / self.baseline,
+            self.baseline
+        );
+        if let Some((now, usual)) = self.remaining {
+            let format = TimeFormat::Hm;
+            body.push_str(&format!(
+                "\n{} left instead of {}",
+                format.format(now),
+                format.format(usual)
+            ));
+        }
+        body
+    }
+}
+
+/// Compares the smoothed discharging draw to the baseline, alerting once when it has been
+/// more than `factor` times the baseline for `HOLD`. The baseline only learns from draws
+/// that aren't above it that way, so an anomaly never becomes the new normal.
+pub struct DrainDetector {
+    baseline: Baseline,
+    path: Option<PathBuf>,
+    factor: f64,
+    /// The recent draw in W, smoothed like the time remaining is
+    smoothed: Option<f64>,
+    last_sample: Option<SystemTime>,
+    /// When the smoothed draw went above the threshold, while it still is
+    above_since: Option<SystemTime>,
+    fired: bool,
+}
+
+impl DrainDetector {
+    /// How long the smoothed draw must stay above the threshold to alert
+    const HOLD: Duration = Duration::from_secs(5 * 60);
+    /// The time over which the smoothed draw takes in 63% of a change
+    const SMOOTHING_PERIOD: f64 = 60.0;
+    /// The time over which the baseline takes in 63% of a change
+    const BASELINE_PERIOD: f64 = 10.0 * 3600.0;
+    /// How much discharging the baseline must have seen before it is trusted
+    const MIN_LEARNED: u64 = 60 * 60;
+    /// The longest time one sample counts for, so a suspend isn't taken for hours of draw
+    const MAX_STEP: f64 = 5.0 * 60.0;
+
+    pub fn new(baseline: Baseline, path: Option<PathBuf>, factor: f64) -> Self {
+        Self {
+            baseline,
+            path,
+            factor,
+            smoothed: None,
+            last_sample: None,
+            above_since: None,
+            fired: false,
+        }
+    }
+
+    /// Feed a state, returning an alert once when the draw has been abnormal for long enough
+    pub fn update(&mut self, state: &BatteryState) -> Option<DrainAlert> {
+        let watts = state
+            .power_w()
+            .filter(|_| state.is_discharging() && state.is_field_valid(BatteryValue::Current));
+        let Some(watts) = watts else {
+            self.smoothed = None;
+            self.last_sample = None;
+            self.above_since = None;
+            self.fired = false;
+            return None;
+        };
+
+        let time = state.captured_at;
+        let step = match self.last_sample.map(|last| time.duration_since(last)) {
+            Some(Ok(elapsed)) => elapsed.as_secs_f64().min(Self::MAX_STEP),
+            _ => 0.0,
+        };
+        self.last_sample = Some(time);
+        let smoothed = match self.smoothed {
+            Some(smoothed) => {
+                let weight = 1.0 - (-step / Self::SMOOTHING_PERIOD).exp();
+                smoothed + (watts - smoothed) * weight
+            }
+            None => watts,
+        };
+        self.smoothed = Some(smoothed);
+
+        let trusted = self.baseline.learned >= Self::MIN_LEARNED && self.baseline.watts > 0.0;
+        if !trusted || smoothed <= self.baseline.watts * self.factor {
+            self.above_since = None;
+            self.fired = false;
+            self.learn(watts, step);
+            return None;
+        }
+
+        let since = *self.above_since.get_or_insert(time);
+        if self.fired || time.duration_since(since).unwrap_or_default() < Self::HOLD {
+            return None;
+        }
+        self.fired = true;
+
+        let energy = state.energy_now_wh();
+        Some(DrainAlert {
+            watts: smoothed,
+            baseline: self.baseline.watts,
+            remaining: energy.map(|wh| {
+                let at = |w: f64| Duration::from_secs_f64(wh / w * 3600.0);
+                (at(smoothed), at(self.baseline.watts))
+            }),
+        })
+    }
+
+    /// Take a normal draw into the baseline, weighted by the time it was drawn for
+    fn learn(&mut self, watts: f64, step: f64) {
+        if step <= 0.0 {
+            return;
+        }
+        let baseline = &mut self.baseline;
+        baseline.watts = match baseline.learned {
+            0 => watts,
+            _ => {
+                let weight = 1.0 - (-step / Self::BASELINE_PERIOD).exp();
+                baseline.watts + (watts - baseline.watts) * weight
+            }
+        };
+        baseline.learned += step as u64;
+
+        if let Some(path) = &self.path {
+            if let Err(e) = baseline.save(path) {
+                debug!("Failed to save drain baseline: {e}");
+            }
+        }
+    }
+}
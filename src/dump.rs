@@ -0,0 +1,120 @@
+use crate::battery::FindOptions;
+use crate::device::{self, Device};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Attributes that identify the hardware, left out of dumps unless asked for
+const IDENTIFYING_ATTRIBUTES: [&str; 1] = ["serial_number"];
+
+/// The raw contents of a file in a dump
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Contents {
+    Value(String),
+    /// The file couldn't be read
+    Error(String),
+    /// The file identifies the hardware and was left out
+    Redacted,
+}
+
+impl Contents {
+    /// Read `path` whole, recording a failure instead of returning it
+    pub fn read(path: &Path) -> Self {
+        match std::fs::read(path) {
+            Ok(data) => Contents::Value(String::from_utf8_lossy(&data).into_owned()),
+            Err(e) => Contents::Error(e.to_string()),
+        }
+    }
+}
+
+/// One device as batmon sees it, for support requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceDump {
+    pub name: String,
+    pub root: PathBuf,
+    pub kind: String,
+    pub scope: Option<String>,
+    pub present: bool,
+    pub system_battery: bool,
+    /// Whether detection leaves the device out
+    pub ignored: bool,
+    /// Why the device was taken for a peripheral's battery, if it was
+    pub peripheral_rule: Option<String>,
+    pub rating: u8,
+    /// The attributes batmon reads that the device is missing
+    pub missing: Vec<String>,
+    /// Every file in the device's directory, by name
+    pub attributes: BTreeMap<String, Contents>,
+}
+
+/// Dump every device in the roots `options` scans, in name order within each root.
+/// Serial numbers are redacted unless `include_identifiers` is set.
+pub fn devices(options: &FindOptions, include_identifiers: bool) -> Vec<DeviceDump> {
+    options
+        .roots()
+        .into_iter()
+        .flat_map(|root| {
+            device::list(&root).into_iter().map(move |(name, _)| {
+                let ignored = options.ignores(&name);
+                dump_device(&root, name, ignored, include_identifiers)
+            })
+        })
+        .collect()
+}
+
+fn dump_device(root: &Path, name: String, ignored: bool, include_identifiers: bool) -> DeviceDump {
+    let device = Device::from(root.join(&name));
+    let rating = device.rating_detailed();
+
+    let mut attributes = BTreeMap::new();
+    let files = std::fs::read_dir(&device.path).into_iter().flatten();
+    for entry in files.filter_map(|e| e.ok()) {
+        // Subdirectories (and the symlinks to the parent device) are left out
+        if !entry.file_type().is_ok_and(|t| t.is_file()) {
+            continue;
+        }
+        let attribute = entry.file_name().to_string_lossy().into_owned();
+        let contents = match Contents::read(&entry.path()) {
+            _ if !include_identifiers && IDENTIFYING_ATTRIBUTES.contains(&attribute.as_str()) => {
+                Contents::Redacted
+            }
+            Contents::Value(data) if !include_identifiers && attribute == "uevent" => {
+                Contents::Value(redact_uevent(&data))
+            }
+            contents => contents,
+        };
+        attributes.insert(attribute, contents);
+    }
+
+    DeviceDump {
+        root: root.to_owned(),
+        kind: device.kind().to_string(),
+        scope: device.scope().map(|s| s.to_string()),
+        present: device.is_present(),
+        system_battery: device.is_system_battery(),
+        ignored,
+        peripheral_rule: device.peripheral_rule().map(|r| r.to_string()),
+        rating: rating.score,
+        missing: rating.missing().map(String::from).collect(),
+        attributes,
+        name,
+    }
+}
+
+/// Drop the identifying properties from the contents of a `uevent` file
+fn redact_uevent(data: &str) -> String {
+    data.lines()
+        .map(|line| {
+            let key = line
+                .strip_prefix("POWER_SUPPLY_")
+                .and_then(|l| l.split_once('='));
+            match key {
+                Some((key, _)) if IDENTIFYING_ATTRIBUTES.contains(&key.to_lowercase().as_str()) => {
+                    format!("POWER_SUPPLY_{key}=<redacted>\n")
+                }
+                _ => format!("{line}\n"),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,95 @@
+use batmon::PowerSource;
+
+/// Quote `value` for a POSIX shell, leaving it bare when it has nothing the shell would interpret
+pub fn shell_quote(value: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-.,:/+@%".contains(c);
+    if !value.is_empty() && value.chars().all(safe) {
+        return value.to_owned();
+    }
+    // Nothing is special inside single quotes, so only single quotes themselves need escaping
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Print the battery's values as `BATMON_*` assignments for `eval`.
+/// Values that aren't known are assigned empty, so every variable is always set.
+pub fn print_env(battery: &impl PowerSource) {
+    for line in assignments(battery) {
+        println!("{line}");
+    }
+}
+
+fn assignments(battery: &impl PowerSource) -> Vec<String> {
+    let s = battery.state();
+    let remaining = battery
+        .remaining_duration()
+        .map(|r| r.as_secs().to_string())
+        .unwrap_or_default();
+
+    let vars = [
+        ("NAME", battery.name().to_owned()),
+        ("LEVEL", s.level.to_string()),
+        ("STATUS", s.status.to_string()),
+        ("REMAINING_SECONDS", remaining),
+        ("CHARGING", u8::from(s.is_charging()).to_string()),
+        ("CAPACITY", s.capacity.to_string()),
+        ("CHARGE", s.charge.to_string()),
+        ("CURRENT", s.current.to_string()),
+        ("CYCLES", s.cycles.to_string()),
+    ];
+    vars.into_iter()
+        .map(|(name, value)| match value.is_empty() {
+            true => format!("BATMON_{name}="),
+            false => format!("BATMON_{name}={}", shell_quote(&value)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use batmon::{BatteryState, ChargingStatus, MockPowerSource};
+
+    #[test]
+    fn quoting() {
+        for (value, quoted) in [
+            ("BAT0", "BAT0"),
+            ("55", "55"),
+            ("hid-0005:046D.0001", "hid-0005:046D.0001"),
+            ("", "''"),
+            ("two words", "'two words'"),
+            ("it's", r"'it'\''s'"),
+            ("$HOME", "'$HOME'"),
+            ("a\nb", "'a\nb'"),
+            ("`id`", "'`id`'"),
+        ] {
+            assert_eq!(shell_quote(value), quoted, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn quoted_values_survive_eval() {
+        for value in ["", "it's", "two  words", "$HOME", "a\nb", "'\"\\", "`id`;x"] {
+            let script = format!("v={}; printf %s \"$v\"", shell_quote(value));
+            let output = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(&script)
+                .output()
+                .unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stdout), value, "{script}");
+        }
+    }
+
+    #[test]
+    fn unknown_values_are_empty() {
+        let state = BatteryState::builder()
+            .level(55)
+            .status(ChargingStatus::Unknown)
+            .build();
+        let battery = MockPowerSource::scripted("my battery", vec![state]);
+        let lines = assignments(&battery);
+        assert!(lines.contains(&String::from("BATMON_NAME='my battery'")));
+        assert!(lines.contains(&String::from("BATMON_LEVEL=55")));
+        assert!(lines.contains(&String::from("BATMON_REMAINING_SECONDS=")));
+        assert!(lines.contains(&String::from("BATMON_CHARGING=0")));
+    }
+}
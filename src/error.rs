@@ -0,0 +1,157 @@
+/// The ways the library can fail to produce battery information
+#[derive(Debug)]
+pub enum BatmonError {
+    /// No system battery could be found
+    NoBattery,
+    /// The requested device exists but couldn't be used as a battery
+    InvalidDevice { name: String, reason: String },
+    /// There are fewer system batteries than the requested index needs
+    NoSuchIndex { index: usize, found: usize },
+    /// The requested device doesn't exist
+    UnknownDevice {
+        name: String,
+        /// The devices that do exist, with their types
+        available: Vec<(String, String)>,
+        /// The available device the name is closest to, if any is close
+        suggestion: Option<String>,
+        /// The devices the name matches ignoring case, when it matches more than one
+        case_matches: Vec<String>,
+    },
+    /// The device doesn't have the attribute
+    Unsupported { attribute: String },
+    /// The attribute exists but can't be read by this user
+    PermissionDenied { attribute: String },
+    /// Attributes that were required couldn't be read or parsed, see `Battery::require`
+    Unreadable {
+        name: String,
+        attributes: Vec<String>,
+    },
+}
+
+/// The exit codes batmon promises scripts, which never change meaning once released
+pub mod exit_code {
+    pub const SUCCESS: i32 = 0;
+    /// Anything without a more specific code
+    pub const FAILURE: i32 = 1;
+    /// There is no battery on this machine, which is expected on desktops
+    pub const NO_BATTERY: i32 = 2;
+    /// The device given with --device (or --device-index) doesn't exist or isn't a battery
+    pub const INVALID_DEVICE: i32 = 3;
+    /// The device doesn't have the requested attribute
+    pub const UNSUPPORTED: i32 = 4;
+    pub const PERMISSION_DENIED: i32 = 5;
+}
+
+impl BatmonError {
+    /// The process exit code for the error, see `exit_code`
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            BatmonError::NoBattery => exit_code::NO_BATTERY,
+            BatmonError::InvalidDevice { .. }
+            | BatmonError::NoSuchIndex { .. }
+            | BatmonError::UnknownDevice { .. } => exit_code::INVALID_DEVICE,
+            BatmonError::Unsupported { .. } | BatmonError::Unreadable { .. } => {
+                exit_code::UNSUPPORTED
+            }
+            BatmonError::PermissionDenied { .. } => exit_code::PERMISSION_DENIED,
+        }
+    }
+}
+
+impl std::fmt::Display for BatmonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BatmonError::NoBattery => write!(f, "no system battery found"),
+            BatmonError::InvalidDevice { name, reason } => {
+                write!(f, "device '{name}' is not a usable battery: {reason}")
+            }
+            BatmonError::NoSuchIndex { index, found } => {
+                let batteries = if *found == 1 { "battery" } else { "batteries" };
+                write!(
+                    f,
+                    "there is no battery at index {index}: found {found} system {batteries}"
+                )
+            }
+            BatmonError::UnknownDevice {
+                name,
+                available,
+                suggestion,
+                case_matches,
+            } => {
+                write!(f, "there is no device named '{name}'")?;
+                if !case_matches.is_empty() {
+                    write!(
+                        f,
+                        ", and it matches {} ignoring case",
+                        case_matches.join(" and ")
+                    )?;
+                } else if let Some(suggestion) = suggestion {
+                    write!(f, ", did you mean '{suggestion}'?")?;
+                }
+                if available.is_empty() {
+                    return write!(f, " (no devices are available)");
+                }
+                let available: Vec<_> = available
+                    .iter()
+                    .map(|(name, ty)| format!("{name} ({ty})"))
+                    .collect();
+                write!(f, " (available: {})", available.join(", "))
+            }
+            BatmonError::Unsupported { attribute } => {
+                write!(f, "the device doesn't report {attribute}")
+            }
+            BatmonError::PermissionDenied { attribute } => {
+                write!(f, "permission denied reading {attribute}")
+            }
+            BatmonError::Unreadable { name, attributes } => {
+                write!(f, "device '{name}' has no usable {}", attributes.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for BatmonError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let unknown = BatmonError::UnknownDevice {
+            name: String::from("BAT9"),
+            available: Vec::new(),
+            suggestion: None,
+            case_matches: Vec::new(),
+        };
+        let invalid = BatmonError::InvalidDevice {
+            name: String::from("AC"),
+            reason: String::from("it is a Mains device"),
+        };
+        let unreadable = BatmonError::Unreadable {
+            name: String::from("BAT0"),
+            attributes: vec![String::from("capacity")],
+        };
+        for (error, code) in [
+            (BatmonError::NoBattery, 2),
+            (invalid, 3),
+            (BatmonError::NoSuchIndex { index: 1, found: 1 }, 3),
+            (unknown, 3),
+            (
+                BatmonError::Unsupported {
+                    attribute: String::from("cycle_count"),
+                },
+                4,
+            ),
+            (unreadable, 4),
+            (
+                BatmonError::PermissionDenied {
+                    attribute: String::from("charge_now"),
+                },
+                5,
+            ),
+        ] {
+            assert_eq!(error.exit_code(), code, "{error}");
+        }
+    }
+}
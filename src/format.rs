@@ -0,0 +1,73 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use strum::{Display, EnumString};
+
+/// How durations are written
+#[derive(Debug, Clone, Copy, Default, Display, EnumString, PartialEq, Eq)]
+#[strum(serialize_all = "lowercase")]
+pub enum TimeFormat {
+    /// Zero padded hours, minutes and seconds (`02:13:45`)
+    #[default]
+    Hms,
+    /// Hours and minutes rounded to the nearest minute (`2:14`), as the seconds are noise
+    /// for an estimate. Hours aren't wrapped at a day (`26:05`) and anything under a minute
+    /// but not zero shows as `0:01`.
+    Hm,
+}
+
+impl TimeFormat {
+    pub fn format(self, duration: Duration) -> String {
+        let total_seconds = duration.as_secs();
+        match self {
+            TimeFormat::Hms => {
+                let s = total_seconds % 60;
+                let m = (total_seconds / 60) % 60;
+                let h = total_seconds / 60 / 60;
+                format!("{h:0>2}:{m:0>2}:{s:0>2}")
+            }
+            TimeFormat::Hm => {
+                let mut minutes = total_seconds.saturating_add(30) / 60;
+                if minutes == 0 && !duration.is_zero() {
+                    minutes = 1;
+                }
+                format!("{}:{:0>2}", minutes / 60, minutes % 60)
+            }
+        }
+    }
+}
+
+static WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+/// The local wall clock time `remaining` after `now`, like `14:32` (or `2:32 PM`).
+/// Times more than a day away include the weekday (`Tue 09:10`).
+pub fn eta_clock(now: SystemTime, remaining: Duration, twelve_hour: bool) -> Option<String> {
+    let eta = now.checked_add(remaining)?;
+    let secs = eta.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    let time = libc::time_t::try_from(secs).ok()?;
+
+    // SAFETY: localtime_r only writes to the tm it is given
+    let tm = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            return None;
+        }
+        tm
+    };
+
+    let clock = if twelve_hour {
+        let hour = match tm.tm_hour % 12 {
+            0 => 12,
+            h => h,
+        };
+        let suffix = if tm.tm_hour < 12 { "AM" } else { "PM" };
+        format!("{hour}:{:0>2} {suffix}", tm.tm_min)
+    } else {
+        format!("{:0>2}:{:0>2}", tm.tm_hour, tm.tm_min)
+    };
+
+    if remaining > Duration::from_secs(24 * 60 * 60) {
+        let weekday = WEEKDAYS.get(tm.tm_wday as usize)?;
+        Some(format!("{weekday} {clock}"))
+    } else {
+        Some(clock)
+    }
+}
@@ -0,0 +1,390 @@
+use crate::battery::{BatteryState, BatteryValue};
+use crate::format::TimeFormat;
+use crate::status::ChargingStatus;
+use crate::units::UnitFamily;
+use std::process::Command;
+use std::time::Duration;
+
+/// Bits of `hw.acpi.battery.state`
+const STATE_DISCHARGING: i64 = 0x1;
+const STATE_CHARGING: i64 = 0x2;
+/// All bits are set when the battery isn't present
+const STATE_NOT_PRESENT: i64 = 0x7;
+
+/// What `acpiconf -i <unit>` reports about a battery
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AcpiInfo {
+    pub level: Option<u8>,
+    pub status: Option<ChargingStatus>,
+    pub remaining: Option<Duration>,
+    /// The last full capacity in mAh (mWh for batteries that report energy)
+    pub capacity: Option<u64>,
+    /// The present rate in mA (mW)
+    pub rate: Option<u64>,
+    /// The present voltage in mV
+    pub voltage: Option<u64>,
+    pub family: UnitFamily,
+}
+
+/// Parse an integer sysctl value as printed by `sysctl -n`
+pub fn parse_sysctl_int(s: &str) -> Option<i64> {
+    s.trim().parse().ok()
+}
+
+/// Map the `hw.acpi.battery.state` bit flags to a status
+pub fn parse_state(flags: i64, level: Option<u8>) -> ChargingStatus {
+    match flags {
+        STATE_NOT_PRESENT => ChargingStatus::Unknown,
+        f if f & STATE_CHARGING != 0 => ChargingStatus::Charging,
+        f if f & STATE_DISCHARGING != 0 => ChargingStatus::Discharging,
+        _ if level == Some(100) => ChargingStatus::Full,
+        _ => ChargingStatus::NotCharging,
+    }
+}
+
+/// Parse `hw.acpi.battery.time`, in minutes, where -1 means the time is still being estimated
+pub fn parse_time(minutes: i64) -> Option<Duration> {
+    u64::try_from(minutes)
+        .ok()
+        .map(|m| Duration::from_secs(m.saturating_mul(60)))
+}
+
+/// Parse a value like `4524 mAh`, returning the number and its unit
+fn parse_quantity(value: &str) -> Option<(u64, &str)> {
+    let (number, unit) = value.split_once(' ')?;
+    Some((number.parse().ok()?, unit.trim()))
+}
+
+/// Parse the output of `acpiconf -i <unit>`
+pub fn parse_acpiconf(output: &str) -> AcpiInfo {
+    let mut info = AcpiInfo::default();
+    let mut state = None;
+
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+
+        match key.trim() {
+            "Remaining capacity" => info.level = value.trim_end_matches('%').parse().ok(),
+            "Remaining time" => {
+                // h:mm, or "unknown" while estimating
+                info.remaining = value.split_once(':').and_then(|(h, m)| {
+                    let (h, m): (u64, u64) = (h.parse().ok()?, m.parse().ok()?);
+                    Some(Duration::from_secs((h * 60 + m) * 60))
+                })
+            }
+            "State" => state = Some(value.to_owned()),
+            "Last full capacity" => {
+                if let Some((capacity, unit)) = parse_quantity(value) {
+                    info.capacity = Some(capacity);
+                    if unit == "mWh" {
+                        info.family = UnitFamily::Energy;
+                    }
+                }
+            }
+            "Present rate" => info.rate = parse_quantity(value).map(|q| q.0),
+            "Present voltage" => info.voltage = parse_quantity(value).map(|q| q.0),
+            _ => {}
+        }
+    }
+
+    info.status = state.map(|s| {
+        let flags = s.split_whitespace().fold(0, |flags, word| match word {
+            "discharging" => flags | STATE_DISCHARGING,
+            "charging" => flags | STATE_CHARGING,
+            _ => flags,
+        });
+        if s == "not present" {
+            ChargingStatus::Unknown
+        } else {
+            parse_state(flags, info.level)
+        }
+    });
+    info
+}
+
+fn sysctl(name: &str) -> Option<i64> {
+    let output = Command::new("sysctl").arg("-n").arg(name).output().ok()?;
+    parse_sysctl_int(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Read a battery unit out of `units`. The level, state and time sysctls describe every
+/// battery combined, so they are only preferred when there is a single unit.
+fn read_unit(unit: u32, units: u32) -> Result<AcpiInfo, Box<dyn std::error::Error>> {
+    let output = Command::new("acpiconf")
+        .arg("-i")
+        .arg(unit.to_string())
+        .output()?;
+    if !output.status.success() {
+        return Err(format!("acpiconf failed for battery unit {unit}").into());
+    }
+    let mut info = parse_acpiconf(&String::from_utf8_lossy(&output.stdout));
+    if units != 1 {
+        return Ok(info);
+    }
+
+    if let Some(life) = sysctl("hw.acpi.battery.life").and_then(|l| u8::try_from(l).ok()) {
+        info.level = Some(life);
+    }
+    if let Some(state) = sysctl("hw.acpi.battery.state") {
+        info.status = Some(parse_state(state, info.level));
+    }
+    if let Some(time) = sysctl("hw.acpi.battery.time") {
+        info.remaining = parse_time(time);
+    }
+    Ok(info)
+}
+
+/// A battery unit on FreeBSD, which has no sysfs.
+/// Data comes from the `hw.acpi.battery` sysctls and `acpiconf -i <unit>`.
+#[derive(Debug)]
+pub struct Battery {
+    pub name: String,
+    unit: u32,
+    /// The number of battery units, from `hw.acpi.battery.units`
+    units: u32,
+    info: AcpiInfo,
+}
+
+impl Battery {
+    /// Find the first battery unit that is present
+    pub fn find() -> Option<Self> {
+        let units = u32::try_from(sysctl("hw.acpi.battery.units")?).ok()?;
+        (0..units).find_map(|unit| match Battery::new(unit, units) {
+            Ok(bat) if bat.info.status != Some(ChargingStatus::Unknown) => Some(bat),
+            Ok(_) => {
+                debug!("battery unit {unit} is not present");
+                None
+            }
+            Err(e) => {
+                debug!("battery unit {unit} failed to init: {e}");
+                None
+            }
+        })
+    }
+
+    /// Load battery `unit` out of the `units` the system has
+    pub fn new(unit: u32, units: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Battery {
+            name: format!("battery{unit}"),
+            unit,
+            units,
+            info: read_unit(unit, units)?,
+        })
+    }
+
+    pub fn update(&mut self) {
+        match read_unit(self.unit, self.units) {
+            Ok(info) => self.info = info,
+            Err(e) => debug!("Failed to update battery: {e}"),
+        }
+    }
+
+    pub fn state(&self) -> BatteryState {
+        let info = &self.info;
+        let level = info.level.unwrap_or(100);
+        // acpiconf reports milli-units, batteries are described in micro-units
+        let capacity = info.capacity.unwrap_or_default() * 1000;
+        // acpiconf has no cycle count, and the charge is derived from the level and capacity
+        let unread = [
+            (BatteryValue::Level, info.level.is_some()),
+            (BatteryValue::Capacity, info.capacity.is_some()),
+            (
+                BatteryValue::Charge,
+                info.level.is_some() && info.capacity.is_some(),
+            ),
+            (BatteryValue::Current, info.rate.is_some()),
+            (BatteryValue::Cycles, false),
+            (BatteryValue::Voltage, info.voltage.is_some()),
+            (BatteryValue::Status, info.status.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(value, read)| (!read).then_some(value))
+        .collect();
+        BatteryState {
+            level,
+            capacity,
+            charge: capacity * u64::from(level) / 100,
+            current: info.rate.unwrap_or_default() * 1000,
+            cycles: 0,
+            status: info.status.unwrap_or(ChargingStatus::Unknown),
+            family: info.family,
+            voltage: info.voltage.unwrap_or_default() * 1000,
+            captured_at: std::time::SystemTime::now(),
+            capacity_level: None,
+            unread,
+        }
+    }
+
+    /// The remaining time estimated by the firmware,
+    /// or `None` while it is still estimating
+    pub fn remaining_duration(&self) -> Option<Duration> {
+        match self.info.status? {
+            ChargingStatus::Full | ChargingStatus::NotCharging => Some(Duration::ZERO),
+            _ => self.info.remaining,
+        }
+    }
+
+    pub fn remaining(&self) -> String {
+        match self.remaining_duration() {
+            Some(remaining) => TimeFormat::Hms.format(remaining),
+            None => String::from("Unknown"),
+        }
+    }
+}
+
+impl std::fmt::Display for Battery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.state();
+        write!(f, "{} @ {}%, {}", self.name, state.level, state.status)?;
+        match state.status {
+            ChargingStatus::Discharging => write!(f, ", {} remaining", self.remaining()),
+            ChargingStatus::Charging => write!(f, ", {} until full", self.remaining()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `acpiconf -i 0` on a ThinkPad reporting energy, while discharging
+    const THINKPAD: &str = "\
+Design capacity:\t57000 mWh
+Last full capacity:\t51300 mWh
+Technology:\tsecondary (rechargeable)
+Design voltage:\t11400 mV
+Capacity (warn):\t2565 mWh
+Capacity (low):\t200 mWh
+Cycle Count:\t120
+Low/warn granularity:\t1 mWh
+Warn/full granularity:\t1 mWh
+Model number:\t01AV431
+Serial number:\t 1234
+Type:\tLiP
+OEM info:\tSMP
+State:\tdischarging
+Remaining capacity:\t87%
+Remaining time:\t2:51
+Present rate:\t8943 mW
+Present voltage:\t12210 mV
+";
+
+    /// `acpiconf -i 0` on a laptop reporting charge, while charging and still estimating
+    const CHARGING: &str = "\
+Design capacity:\t4000 mAh
+Last full capacity:\t3800 mAh
+Technology:\tsecondary (rechargeable)
+Design voltage:\t11100 mV
+State:\tcharging
+Remaining capacity:\t42%
+Remaining time:\tunknown
+Present rate:\t1500 mA
+Present voltage:\t12000 mV
+";
+
+    /// `acpiconf -i 1` for an empty second bay
+    const NOT_PRESENT: &str = "\
+Design capacity:\tunknown
+Last full capacity:\tunknown
+State:\tnot present
+Remaining capacity:\tunknown
+Remaining time:\tunknown
+Present rate:\tunknown
+Present voltage:\tunknown
+";
+
+    #[test]
+    fn sysctl_ints() {
+        assert_eq!(parse_sysctl_int("87\n"), Some(87));
+        assert_eq!(parse_sysctl_int("-1\n"), Some(-1));
+        assert_eq!(parse_sysctl_int(" 2 "), Some(2));
+        assert_eq!(parse_sysctl_int(""), None);
+        assert_eq!(parse_sysctl_int("unknown\n"), None);
+    }
+
+    #[test]
+    fn states() {
+        for (flags, level, status) in [
+            (0x1, Some(50), ChargingStatus::Discharging),
+            (0x2, Some(50), ChargingStatus::Charging),
+            // Charging while critical
+            (0x6, Some(5), ChargingStatus::Charging),
+            (0x5, Some(5), ChargingStatus::Discharging),
+            (0x0, Some(100), ChargingStatus::Full),
+            (0x0, Some(80), ChargingStatus::NotCharging),
+            (0x0, None, ChargingStatus::NotCharging),
+            (0x7, Some(100), ChargingStatus::Unknown),
+        ] {
+            assert_eq!(parse_state(flags, level), status, "{flags:#x}");
+        }
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(parse_time(-1), None);
+        assert_eq!(parse_time(0), Some(Duration::ZERO));
+        assert_eq!(parse_time(171), Some(Duration::from_secs(171 * 60)));
+    }
+
+    #[test]
+    fn acpiconf_energy() {
+        assert_eq!(
+            parse_acpiconf(THINKPAD),
+            AcpiInfo {
+                level: Some(87),
+                status: Some(ChargingStatus::Discharging),
+                remaining: Some(Duration::from_secs((2 * 60 + 51) * 60)),
+                capacity: Some(51300),
+                rate: Some(8943),
+                voltage: Some(12210),
+                family: UnitFamily::Energy,
+            }
+        );
+    }
+
+    #[test]
+    fn acpiconf_charge() {
+        assert_eq!(
+            parse_acpiconf(CHARGING),
+            AcpiInfo {
+                level: Some(42),
+                status: Some(ChargingStatus::Charging),
+                remaining: None,
+                capacity: Some(3800),
+                rate: Some(1500),
+                voltage: Some(12000),
+                family: UnitFamily::Charge,
+            }
+        );
+    }
+
+    #[test]
+    fn acpiconf_not_present() {
+        let info = parse_acpiconf(NOT_PRESENT);
+        assert_eq!(info.status, Some(ChargingStatus::Unknown));
+        assert_eq!(info.level, None);
+        assert_eq!(info.capacity, None);
+    }
+
+    #[test]
+    fn state_in_micro_units() {
+        let battery = Battery {
+            name: String::from("battery0"),
+            unit: 0,
+            units: 1,
+            info: parse_acpiconf(THINKPAD),
+        };
+        let state = battery.state();
+        assert_eq!(state.level, 87);
+        assert_eq!(state.capacity, 51_300_000);
+        assert_eq!(state.charge, 51_300_000 * 87 / 100);
+        assert_eq!(state.current, 8_943_000);
+        assert_eq!(state.voltage, 12_210_000);
+        assert!(!state.is_field_valid(BatteryValue::Cycles));
+        assert!(state.is_field_valid(BatteryValue::Level));
+    }
+}
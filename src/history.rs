@@ -0,0 +1,218 @@
+use batmon::BatteryState;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Files larger than this can't have been written by us and are treated as corrupt
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    /// Seconds since the unix epoch
+    pub time: u64,
+    #[serde(flatten)]
+    pub state: BatteryState,
+}
+
+impl Record {
+    pub fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.time)
+    }
+}
+
+/// A bounded window of recent battery states, kept on disk for diagnosing unexpected drains
+pub struct History {
+    path: PathBuf,
+    size: usize,
+    records: VecDeque<Record>,
+}
+
+impl History {
+    pub fn default_path() -> Option<PathBuf> {
+        Some(crate::paths::state_dir()?.join("history.json"))
+    }
+
+    /// Load the history at `path`, starting over if it is missing or corrupt
+    pub fn load(path: PathBuf, size: usize) -> Self {
+        let mut records = match History::read(&path) {
+            Ok(r) => r,
+            Err(e) => {
+                if path.exists() {
+                    warn!("Resetting history at {}: {e}", path.to_string_lossy());
+                }
+                VecDeque::new()
+            }
+        };
+
+        while records.len() > size {
+            records.pop_front();
+        }
+
+        Self {
+            path,
+            size,
+            records,
+        }
+    }
+
+    fn read(path: &PathBuf) -> Result<VecDeque<Record>, Box<dyn std::error::Error>> {
+        if std::fs::metadata(path)?.len() > MAX_FILE_SIZE {
+            return Err("file is too large".into());
+        }
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &Record> {
+        self.records.iter()
+    }
+
+    /// The energy used in Wh and the time spent since the battery last started discharging,
+    /// if it is still discharging
+    pub fn session_drain(&self) -> Option<(f64, Duration)> {
+        let session: Vec<_> = self
+            .records
+            .iter()
+            .rev()
+            .take_while(|r| r.state.is_discharging())
+            .collect();
+        let (last, first) = (session.first()?, session.last()?);
+
+        let mut used = 0.0;
+        for pair in session.windows(2) {
+            let (newer, older) = (
+                pair[0].state.energy_now_wh()?,
+                pair[1].state.energy_now_wh()?,
+            );
+            used += (older - newer).max(0.0);
+        }
+
+        Some((used, last.time().duration_since(first.time()).ok()?))
+    }
+
+    /// Record a state, dropping the oldest one if the history is full
+    pub fn push(&mut self, state: BatteryState) -> std::io::Result<()> {
+        let time = state
+            .captured_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        self.records.push_back(Record { time, state });
+        while self.records.len() > self.size {
+            self.records.pop_front();
+        }
+
+        self.save()
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        // Write to a temporary file first so a crash never leaves a truncated history behind
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&self.records)?)?;
+        std::fs::rename(tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use batmon::{ChargingStatus, UnitFamily};
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("batmon-history-{}-{name}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// A state at `minute` with `wh` stored, in an energy battery
+    fn state(minute: u64, wh: f64, status: ChargingStatus) -> BatteryState {
+        BatteryState::builder()
+            .family(UnitFamily::Energy)
+            .charge((wh * 1e6) as u64)
+            .status(status)
+            .captured_at(UNIX_EPOCH + Duration::from_secs(1_000_000 + minute * 60))
+            .build()
+    }
+
+    fn levels(history: &History) -> Vec<u8> {
+        history.records().map(|r| r.state.level).collect()
+    }
+
+    #[test]
+    fn corrupt_file_starts_over() {
+        let path = temp_path("corrupt");
+        std::fs::write(&path, "{\"time\": ").unwrap();
+        let mut history = History::load(path.clone(), 10);
+        assert_eq!(history.records().count(), 0);
+
+        history
+            .push(BatteryState::builder().level(40).build())
+            .unwrap();
+        let history = History::load(path.clone(), 10);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(levels(&history), [40]);
+    }
+
+    #[test]
+    fn oversized_file_starts_over() {
+        let path = temp_path("oversized");
+        // Valid JSON, but larger than we would ever write
+        let padding = " ".repeat(MAX_FILE_SIZE as usize);
+        std::fs::write(&path, format!("[]{padding}")).unwrap();
+        let history = History::load(path.clone(), 10);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(history.records().count(), 0);
+    }
+
+    #[test]
+    fn push_keeps_the_newest() {
+        let path = temp_path("trim");
+        let mut history = History::load(path.clone(), 3);
+        for level in 1..=5 {
+            history
+                .push(BatteryState::builder().level(level).build())
+                .unwrap();
+        }
+        assert_eq!(levels(&history), [3, 4, 5]);
+
+        assert_eq!(levels(&History::load(path.clone(), 3)), [3, 4, 5]);
+        // A smaller size trims what was saved
+        assert_eq!(levels(&History::load(path.clone(), 2)), [4, 5]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn drain_over_the_discharging_run() {
+        let path = temp_path("drain");
+        let mut history = History::load(path.clone(), 10);
+        let states = [
+            state(0, 40.0, ChargingStatus::Discharging),
+            state(10, 52.0, ChargingStatus::Charging),
+            state(20, 50.0, ChargingStatus::Discharging),
+            state(30, 49.0, ChargingStatus::Discharging),
+            // A reading that went up doesn't give energy back
+            state(40, 49.5, ChargingStatus::Discharging),
+            state(50, 47.5, ChargingStatus::Discharging),
+        ];
+        for state in states {
+            history.push(state).unwrap();
+        }
+        let _ = std::fs::remove_file(&path);
+
+        let (used, duration) = history.session_drain().unwrap();
+        assert!((used - 3.0).abs() < 1e-9, "{used}");
+        assert_eq!(duration, Duration::from_secs(30 * 60));
+
+        history
+            .push(state(60, 47.5, ChargingStatus::Charging))
+            .unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(history.session_drain(), None);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::notify::Event;
+use batmon::PowerSource;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+
+/// A user script run on every battery transition.
+/// Scripts are spawned in the background and reaped later so a slow hook never blocks the daemon.
+pub struct TransitionHook {
+    script: PathBuf,
+    running: Vec<Child>,
+}
+
+impl TransitionHook {
+    pub fn new(script: impl Into<PathBuf>) -> Self {
+        Self {
+            script: script.into(),
+            running: Vec::new(),
+        }
+    }
+
+    pub fn run(&mut self, event: Event, battery: &impl PowerSource) {
+        self.reap();
+
+        let res = Command::new(&self.script)
+            .env("BATMON_EVENT", event.to_string())
+            .env("BATMON_LEVEL", battery.state().level.to_string())
+            .env("BATMON_NAME", battery.name())
+            .stdin(Stdio::null())
+            .spawn();
+
+        match res {
+            Ok(child) => {
+                debug!("Started transition hook for {event} (pid {})", child.id());
+                self.running.push(child);
+            }
+            Err(e) => warn!(
+                "Failed to run transition hook '{}': {e}",
+                self.script.to_string_lossy()
+            ),
+        }
+    }
+
+    /// Collect any hooks that have finished
+    pub fn reap(&mut self) {
+        self.running.retain_mut(|child| match child.try_wait() {
+            Ok(Some(status)) => {
+                if !status.success() {
+                    debug!("Transition hook (pid {}) exited with {status}", child.id());
+                }
+                false
+            }
+            Ok(None) => true,
+            Err(_) => false,
+        });
+    }
+
+    #[cfg(test)]
+    pub(crate) fn running(&self) -> usize {
+        self.running.len()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use batmon::{BatteryState, ChargingStatus, MockPowerSource};
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    /// Write an executable script to a temp file named after `name`
+    pub(crate) fn script(name: &str, body: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("batmon-hook-{}-{name}.sh", std::process::id()));
+        std::fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+        let start = Instant::now();
+        while !done() {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "timed out waiting for {what}"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn hook_runs_in_the_background() {
+        let out = std::env::temp_dir().join(format!("batmon-hook-{}.env", std::process::id()));
+        let _ = std::fs::remove_file(&out);
+        let out_str = out.to_string_lossy();
+        // The environment is written under a temporary name so it's only seen complete
+        let script = script(
+            "env",
+            &format!(
+                "sleep 0.5\nenv | grep ^BATMON_ > '{out_str}.tmp'\nmv '{out_str}.tmp' '{out_str}'"
+            ),
+        );
+        let state = BatteryState::builder()
+            .level(9)
+            .status(ChargingStatus::Discharging)
+            .build();
+        let battery = MockPowerSource::scripted("BAT1", vec![state]);
+
+        let mut hook = TransitionHook::new(&script);
+        let start = Instant::now();
+        hook.run(Event::Critical, &battery);
+        assert!(start.elapsed() < Duration::from_millis(400));
+        assert_eq!(hook.running(), 1);
+        hook.reap();
+        assert_eq!(hook.running(), 1);
+
+        wait_for("the hook's output", || Path::new(&out).exists());
+        wait_for("the hook to be reaped", || {
+            hook.reap();
+            hook.running() == 0
+        });
+        let env = std::fs::read_to_string(&out).unwrap();
+        let _ = std::fs::remove_file(&out);
+        let _ = std::fs::remove_file(&script);
+        let mut lines: Vec<&str> = env.lines().collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            [
+                "BATMON_EVENT=critical",
+                "BATMON_LEVEL=9",
+                "BATMON_NAME=BAT1"
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_script_is_not_tracked() {
+        let mut hook = TransitionHook::new("/nonexistent/batmon-hook");
+        let battery = MockPowerSource::scripted("BAT0", vec![BatteryState::default()]);
+        hook.run(Event::Low, &battery);
+        assert_eq!(hook.running(), 0);
+    }
+}
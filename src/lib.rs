@@ -3,9 +3,42 @@ extern crate log;
 
 mod device;
 
+pub mod adapter;
 pub mod battery;
+pub mod cache;
+pub mod clock;
+pub mod dump;
+pub mod error;
+pub mod format;
+#[cfg(feature = "freebsd")]
+pub mod freebsd;
+pub mod power;
+pub mod snapshot;
+pub mod source;
 pub mod status;
-pub use battery::Battery;
+pub mod trend;
+pub mod uevent;
+pub mod units;
+pub use adapter::Adapter;
+pub use battery::{
+    Battery, BatteryState, BatteryStateBuilder, BatteryValue, CapacityRef, Estimator, FindOptions,
+};
+#[cfg(any(test, feature = "test-util"))]
+pub use clock::FakeClock;
+pub use clock::{Clock, SystemClock};
+pub use device::AttributeValue;
+pub use error::BatmonError;
+pub use format::TimeFormat;
+pub use power::SystemPower;
+pub use snapshot::{snapshot, Envelope, Snapshot, OUTPUT_VERSION};
+#[cfg(feature = "test-util")]
+pub use source::MockPowerSource;
+pub use source::{PowerSource, UpdateReport};
 pub use status::ChargingStatus;
+pub use units::{format_decimal, CurrentSource, UnitFamily};
 
 mod poll;
+#[cfg(test)]
+mod testutil;
+mod timestamp;
+pub use poll::{denied_hint, PollSchedule};
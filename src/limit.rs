@@ -0,0 +1,136 @@
+use std::time::{Duration, Instant};
+
+/// Allows `capacity` events per `period`, refilling continuously.
+/// Every method takes the current time so callers control the clock.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    per_second: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(limit: RateLimit, now: Instant) -> Self {
+        let capacity = limit.count as f64;
+        Self {
+            capacity,
+            tokens: capacity,
+            per_second: capacity / limit.period.as_secs_f64().max(f64::EPSILON),
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.last = now;
+    }
+
+    /// Whether an event would be allowed right now
+    pub fn available(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= 1.0
+    }
+
+    /// Take a token for an event, returning false if the event should be dropped
+    pub fn try_take(&mut self, now: Instant) -> bool {
+        if !self.available(now) {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// A limit of `count` events per `period`, written as COUNT/PERIOD (e.g. `6/1m`)
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub count: u32,
+    pub period: Duration,
+}
+
+impl std::str::FromStr for RateLimit {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (count, period) = s
+            .split_once('/')
+            .ok_or_else(|| format!("'{s}' is not of the form COUNT/PERIOD"))?;
+        let count = count
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid count '{count}' in '{s}'"))?;
+        let period = humantime::parse_duration(period.trim())
+            .map_err(|e| format!("invalid period '{period}' in '{s}': {e}"))?;
+
+        if period.is_zero() {
+            return Err(format!("period in '{s}' must not be zero"));
+        }
+        Ok(Self { count, period })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket(limit: &str) -> (TokenBucket, Instant) {
+        // The bucket takes the time on every call, so a fixed start plus offsets fakes the clock
+        let start = Instant::now();
+        (TokenBucket::new(limit.parse().unwrap(), start), start)
+    }
+
+    #[test]
+    fn burst_up_to_the_capacity() {
+        let (mut bucket, start) = bucket("6/1m");
+        for _ in 0..6 {
+            assert!(bucket.try_take(start));
+        }
+        assert!(!bucket.try_take(start));
+        assert!(!bucket.available(start));
+    }
+
+    #[test]
+    fn refills_continuously() {
+        let (mut bucket, start) = bucket("6/1m");
+        while bucket.try_take(start) {}
+
+        // One token every 10 seconds
+        assert!(!bucket.try_take(start + Duration::from_secs(9)));
+        assert!(bucket.try_take(start + Duration::from_secs(10)));
+        assert!(!bucket.try_take(start + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn refill_is_capped_at_the_capacity() {
+        let (mut bucket, start) = bucket("2/1m");
+        let later = start + Duration::from_secs(3600);
+        assert!(bucket.try_take(later));
+        assert!(bucket.try_take(later));
+        assert!(!bucket.try_take(later));
+    }
+
+    #[test]
+    fn empty_bucket_never_allows() {
+        let (mut bucket, start) = bucket("0/1m");
+        assert!(!bucket.try_take(start));
+        assert!(!bucket.try_take(start + Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn clock_going_backwards_adds_nothing() {
+        let (mut bucket, start) = bucket("1/1m");
+        let later = start + Duration::from_secs(60);
+        assert!(bucket.try_take(later));
+        assert!(!bucket.try_take(start));
+    }
+
+    #[test]
+    fn parse_rate_limit() {
+        let limit: RateLimit = "6/1m".parse().unwrap();
+        assert_eq!((limit.count, limit.period), (6, Duration::from_secs(60)));
+        for invalid in ["6", "x/1m", "6/soon", "6/0s"] {
+            assert!(invalid.parse::<RateLimit>().is_err(), "{invalid}");
+        }
+    }
+}
@@ -1,12 +1,79 @@
 #[macro_use]
 extern crate log;
 
-use batmon::{Battery, ChargingStatus};
+use batmon::cache::Cache;
+use batmon::error::exit_code;
+use batmon::{
+    Adapter, BatmonError, Battery, BatteryState, ChargingStatus, CurrentSource, Envelope,
+    FindOptions, PollSchedule, PowerSource, TimeFormat,
+};
 use clap::Parser;
-use libnotify::{Notification, Urgency};
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+mod actions;
+use actions::Action;
+
+mod calibrate;
 
 mod cli;
-use cli::{Cli, Command};
+
+mod completions;
+
+mod counters;
+use cli::{Cli, Command, DaemonArgs, TraceField};
+use counters::Status;
+
+mod daemonize;
+
+mod debug_dump;
+
+mod delta;
+
+mod drain;
+use drain::{Baseline, DrainDetector};
+
+mod env;
+
+mod history;
+use history::History;
+
+mod hook;
+use hook::TransitionHook;
+
+mod limit;
+
+mod markup;
+
+mod monitor;
+use monitor::{Monitor, LEVELS};
+
+mod notify;
+use notify::{Event, Notifier};
+
+mod paths;
+
+mod profiles;
+use profiles::{PowerProfiles, ProfileSwitcher};
+
+mod rotate;
+
+mod selftest;
+
+mod service;
+
+mod snooze;
+
+mod stats;
+
+mod suspend;
+use suspend::SuspendDrain;
+
+mod tier;
+use tier::Tier;
+
+mod transitions;
+use transitions::TransitionLog;
 
 type Result<T> = std::result::Result<T, std::boxed::Box<dyn std::error::Error>>;
 
@@ -24,142 +91,621 @@ fn start_libnotify() -> Result<()> {
 }
 
 fn main() {
-    if std::env::var("RUST_LOG").is_err() {
-        std::env::set_var(
-            "RUST_LOG",
-            if cfg!(debug_assertions) {
-                "trace"
-            } else {
-                "warn"
-            },
-        );
+    // Called by completion scripts, so kept out of the regular (and completed) subcommands
+    if std::env::args().nth(1).as_deref() == Some(completions::DEVICES_HELPER) {
+        completions::print_devices();
+        return;
+    }
+
+    // Usage errors would otherwise exit with clap's 2, which means no battery to scripts
+    let args = Cli::try_parse().unwrap_or_else(|e| {
+        let _ = e.print();
+        std::process::exit(if e.use_stderr() {
+            exit_code::FAILURE
+        } else {
+            exit_code::SUCCESS
+        });
+    });
+
+    // The log style is decided once the logger is set up, so colours would otherwise end up
+    // in the log file when the terminal is left behind
+    let daemonize = matches!(args.command, Some(Command::Daemon(ref d)) if d.daemonize);
+    if daemonize && std::env::var("RUST_LOG_STYLE").is_err() {
+        std::env::set_var("RUST_LOG_STYLE", "never");
+    }
+    match args.verbose {
+        0 if std::env::var("RUST_LOG").is_ok() => {}
+        0 if cfg!(debug_assertions) => std::env::set_var("RUST_LOG", "trace"),
+        0 => std::env::set_var("RUST_LOG", "warn"),
+        1 => std::env::set_var("RUST_LOG", "debug"),
+        _ => std::env::set_var("RUST_LOG", "trace"),
     }
     pretty_env_logger::init();
 
-    let res = run();
+    let res = run(args);
     if libnotify::is_initted() {
         libnotify::uninit();
     }
 
     if let Err(ref e) = res {
         error!("Fatal error: {}", e.to_string());
-        std::process::exit(1);
+        std::process::exit(exit_code(e.as_ref()));
+    }
+}
+
+/// The exit code for an error, following the contract in `batmon::error::exit_code`
+fn exit_code(e: &(dyn std::error::Error + 'static)) -> i32 {
+    if let Some(e) = e.downcast_ref::<BatmonError>() {
+        return e.exit_code();
+    }
+    match e.downcast_ref::<std::io::Error>() {
+        Some(e) if e.kind() == std::io::ErrorKind::PermissionDenied => exit_code::PERMISSION_DENIED,
+        _ => exit_code::FAILURE,
     }
 }
 
-fn run() -> Result<()> {
-    let args = Cli::parse();
-    let mut bat = match args.device {
-        Some(d) => {
-            Battery::new(&d).map_err(|e| format!("Failed to load specified battery: {e}"))?
+fn run(args: Cli) -> Result<()> {
+    let cache_file = args.cache_file.clone().or_else(Cache::default_path);
+
+    // Commands that don't need a battery
+    match args.command {
+        Some(Command::Forget) => return forget(cache_file),
+        Some(Command::Snooze { request }) => return snooze::run(request),
+        Some(Command::History { suspend_report }) => return print_history(suspend_report),
+        Some(Command::Stats { since, json }) => return stats::run(since, json),
+        Some(Command::Calibrate { watch: false, .. }) => return calibrate::print_last(),
+        Some(Command::Completions { shell }) => {
+            print!("{}", completions::generate(shell));
+            return Ok(());
+        }
+        Some(Command::Daemon(ref d)) if d.print_service => {
+            print!("{}", service::current_unit()?);
+            return Ok(());
         }
-        None => Battery::find(!args.no_cache).ok_or("Failed to detect a valid battery")?,
+        Some(Command::Daemon(ref d)) if d.install_service => return service::install(d.force),
+        Some(Command::Daemon(ref d)) if d.stop => return daemonize::stop(),
+        _ => {}
+    }
+
+    // FreeBSD has no sysfs, its batteries are read through ACPI instead
+    #[cfg(feature = "freebsd")]
+    if !std::path::Path::new(batmon::battery::POWER_SUPPLY_ROOT).exists() {
+        return run_freebsd(args);
+    }
+
+    let options = FindOptions {
+        include_ups: args.include_ups,
+        extra_roots: args.extra_root.clone(),
+        ignore: args
+            .ignore
+            .iter()
+            .cloned()
+            .chain(batmon::battery::env_list(batmon::battery::IGNORE_ENV))
+            .collect(),
+        priority: args
+            .prefer
+            .iter()
+            .cloned()
+            .chain(batmon::battery::env_list(batmon::battery::PRIORITY_ENV))
+            .collect(),
+    };
+    if let Some(d) = args.device.as_deref().filter(|d| options.ignores(d)) {
+        warn!("Using '{d}' as given, although it is ignored");
+    }
+    let found = match (&args.device, args.device_index) {
+        (Some(d), _) => Battery::new(d).map_err(|e| -> Box<dyn std::error::Error> {
+            if e.is::<BatmonError>() {
+                e
+            } else {
+                format!("Failed to load specified battery: {e}").into()
+            }
+        }),
+        (None, Some(index)) => Battery::find_index(&options, index).map_err(Into::into),
+        (None, None) => {
+            let cache = cache_file.as_deref().filter(|_| !args.no_cache);
+            Battery::find_with(cache, &options).ok_or_else(|| BatmonError::NoBattery.into())
+        }
+    };
+    // The dump records the battery detection chose, or why there is none
+    if let Some(Command::DebugDump {
+        out,
+        include_identifiers,
+    }) = &args.command
+    {
+        let settings = debug_dump::Settings {
+            device: args.device.clone(),
+            device_index: args.device_index,
+            primary: std::env::var(batmon::battery::PRIMARY_ENV).ok(),
+            include_ups: args.include_ups,
+            extra_roots: args.extra_root.clone(),
+            ignore: options.ignore.clone(),
+            priority: options.priority.clone(),
+            no_cache: args.no_cache,
+            cache_file: cache_file.clone(),
+            current_source: args.current_source.to_string(),
+            lenient_parse: args.lenient_parse,
+            unit_heuristics: !args.no_unit_heuristics,
+        };
+        return debug_dump::run(
+            &found,
+            &options,
+            &settings,
+            *include_identifiers,
+            out.as_deref(),
+        );
+    }
+    let mut bat = match found {
+        // Bars show whatever is printed, so an error is better replaced with a static string
+        Err(e) if matches!(args.command, Some(Command::Xmobar { .. })) => {
+            debug!("{e}");
+            println!("no battery");
+            return Ok(());
+        }
+        // A missing battery is one of the things checked
+        found if matches!(args.command, Some(Command::Selftest { .. })) => {
+            let json = matches!(args.command, Some(Command::Selftest { json: true }));
+            return selftest::run(found, cache_file.as_deref(), json);
+        }
+        found => found?,
     };
+    if args.current_source != CurrentSource::Auto {
+        bat.set_current_source(args.current_source);
+    }
+    if args.strict {
+        let required = args
+            .command
+            .as_ref()
+            .map_or(cli::SUMMARY, Command::required_values);
+        bat.require(required)?;
+    }
+    if args.lenient_parse {
+        bat.set_unit_suffixes(true);
+    }
+    if args.no_unit_heuristics {
+        bat.set_unit_heuristics(false);
+    }
+    bat.set_estimator(args.estimator);
+    bat.set_capacity_ref(args.capacity_ref);
+    bat.set_scale_to_threshold(args.scale_to_threshold);
+    bat.set_time_format(match args.command {
+        Some(Command::Daemon(_) | Command::TestNotify(_)) => {
+            args.time_format.unwrap_or(TimeFormat::Hm)
+        }
+        _ => args.time_format.unwrap_or_default(),
+    });
     let s = bat.state();
+    let precision = usize::from(args.precision);
     match args.command {
         Some(Command::Capacity) => println!("{}", s.capacity),
-        Some(Command::Charge) => println!("{}", s.charge),
+        Some(Command::Design) => {
+            let design = bat.design_capacity().ok_or(BatmonError::Unsupported {
+                attribute: bat.design_attribute().to_owned(),
+            })?;
+            println!("{design}");
+        }
+        Some(Command::Charge { absolute: false }) => println!("{}", s.charge),
+        Some(Command::Charge { absolute: true }) => println!("{}", s.absolute(precision)),
         Some(Command::Current) => println!("{}", s.current),
         Some(Command::Cycles) => println!("{}", s.cycles),
-        Some(Command::Level) => println!("{}", s.level),
+        Some(Command::Level { raw: false }) => println!("{}", s.level),
+        Some(Command::Level { raw: true }) => println!("{}", bat.raw_level()),
         Some(Command::Name) => println!("{}", bat.name),
         Some(Command::Status) => println!("{}", s.status),
-        Some(Command::Time) => println!("{}", bat.remaining()),
-        Some(Command::Summary) | None => println!("{bat}"),
-        Some(Command::Daemon(d)) => {
-            start_libnotify()?;
-            loop {
-                update_battery_and_notify(&mut bat)?;
-                info!("{bat}");
-                std::thread::sleep(std::time::Duration::from_secs(d.interval));
+        Some(Command::Time { seconds: false }) => println!("{}", bat.remaining()),
+        Some(Command::Time { seconds: true }) => {
+            let remaining = bat
+                .remaining_duration()
+                .ok_or("The remaining time can't be estimated")?;
+            println!("{}", remaining.as_secs());
+        }
+        Some(Command::Summary { json: true, .. }) => {
+            println!("{}", serde_json::to_string(&Envelope::new(bat.snapshot()))?)
+        }
+        Some(Command::Summary { absolute: true, .. }) => {
+            println!("{bat}, {}", s.absolute(precision))
+        }
+        Some(Command::Summary { .. }) | None => println!("{bat}"),
+        Some(Command::Daemon(d)) => run_daemon(&mut bat, *d)?,
+        Some(
+            Command::History { .. }
+            | Command::Stats { .. }
+            | Command::Completions { .. }
+            | Command::Forget
+            | Command::Snooze { .. }
+            | Command::Selftest { .. }
+            | Command::DebugDump { .. },
+        ) => unreachable!(),
+        Some(Command::Efficiency) => print_efficiency(&s, precision),
+        Some(Command::EtaClock { twelve_hour }) => {
+            let remaining = bat
+                .remaining_duration()
+                .filter(|_| s.is_charging() || s.is_discharging());
+            if let Some(eta) =
+                remaining.and_then(|r| batmon::format::eta_clock(s.captured_at, r, twelve_hour))
+            {
+                println!("{eta}");
+            }
+        }
+        Some(Command::Trace { field, interval }) => trace(&mut bat, field, interval),
+        Some(Command::Calibrate { interval, .. }) => calibrate::watch(&mut bat, interval)?,
+        Some(Command::Xmobar {
+            ref color,
+            ref icon,
+            ref on_click,
+        }) => {
+            let style = markup::XmobarStyle {
+                colors: color,
+                icon: icon.as_deref(),
+                on_click: on_click.as_deref(),
+            };
+            println!("{}", markup::xmobar(&bat, &style));
+        }
+        Some(Command::Conky { bar: true, .. }) => println!("{}", s.level),
+        Some(Command::Conky { ref color, .. }) => println!("{}", markup::conky(&bat, color)),
+        Some(Command::Pango) => println!("{}", markup::pango(&bat)),
+        Some(Command::Env) => env::print_env(&bat),
+        Some(Command::Delta) => delta::print_delta(&s)?,
+        Some(Command::Prompt { no_newline }) => {
+            print!("{}", prompt(&s));
+            if !no_newline {
+                println!();
+            }
+        }
+        Some(Command::Attrs) => {
+            let attributes = bat.attributes();
+            let width = attributes.iter().map(|(name, _)| name.len()).max();
+            for (name, value) in &attributes {
+                println!("{name:<width$}  {value}", width = width.unwrap_or(0));
+            }
+        }
+        Some(Command::Raw { attribute }) => {
+            let data = bat.read_raw(&attribute)?;
+            std::io::stdout().write_all(&data)?;
+        }
+        Some(Command::TestNotify(t)) => {
+            let mut notifier = Notifier::new(t.notify.settings(), t.notify.dry_run);
+            notifier.set_echo(true);
+            if !t.notify.dry_run {
+                start_libnotify()?;
             }
+            Monitor::new(notifier, None).send_event(&bat, t.tier)?;
         }
     }
     Ok(())
 }
 
-struct BatteryLevelSettings {
-    level: u8,
-    label: &'static str,
-    urgency: Urgency,
+fn forget(cache_file: Option<std::path::PathBuf>) -> Result<()> {
+    let path = cache_file.ok_or("Failed to locate the cache file")?;
+    match std::fs::remove_file(&path) {
+        Ok(()) => println!("Removed {}", path.to_string_lossy()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => Err(format!("Failed to remove {}: {e}", path.to_string_lossy()))?,
+    }
+    Ok(())
 }
 
-static LEVELS: [BatteryLevelSettings; 3] = [
-    BatteryLevelSettings {
-        level: 50,
-        label: "at half",
-        urgency: Urgency::Low,
-    },
-    BatteryLevelSettings {
-        level: 25,
-        label: "low",
-        urgency: Urgency::Normal,
-    },
-    BatteryLevelSettings {
-        level: 15,
-        label: "critical",
-        urgency: Urgency::Critical,
-    },
-];
-
-fn update_battery_and_notify(battery: &mut Battery) -> Result<()> {
-    let old_state = battery.state();
-    battery.update();
-    let new_state = battery.state();
-
-    match new_state.status.edge(old_state.status) {
-        Some(ChargingStatus::Discharging) => {
-            info!("Battery started discharging");
-            let body = format!(
-                "{} is discharging\n{}",
-                battery.name,
-                battery.remaining_labelled()
-            );
-            let n = Notification::new("Discharging", Some(body.as_str()), None);
-            n.set_urgency(Urgency::Normal);
-            n.show()?;
-        }
-        Some(ChargingStatus::Charging) => {
-            info!("Battery started charging");
-            let body = format!(
-                "{} is charging\n{}",
-                battery.name,
-                battery.remaining_labelled()
+/// A short indicator of the battery state, coloured by level unless NO_COLOR is set
+fn prompt(s: &BatteryState) -> String {
+    let glyph = match s.status {
+        ChargingStatus::Charging => "⚡",
+        ChargingStatus::Discharging => "",
+        ChargingStatus::Full | ChargingStatus::NotCharging => "=",
+        ChargingStatus::Unknown => "?",
+    };
+    let indicator = format!("{glyph}{}", s.level);
+
+    let Some(tier) = Tier::of(s) else {
+        return indicator;
+    };
+
+    if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        indicator
+    } else {
+        format!("\x1b[{}m{indicator}\x1b[0m", tier.ansi())
+    }
+}
+
+/// Print the value of `field` with a timestamp whenever it changes, forever
+fn trace(bat: &mut Battery, field: TraceField, interval: u64) -> ! {
+    let mut last = None;
+    loop {
+        let s = bat.state();
+        let value = match field {
+            TraceField::Level => s.level.to_string(),
+            TraceField::Capacity => s.capacity.to_string(),
+            TraceField::Charge => s.charge.to_string(),
+            TraceField::Current => s.current.to_string(),
+            TraceField::Cycles => s.cycles.to_string(),
+            TraceField::Status => s.status.to_string(),
+            TraceField::Voltage => s.voltage.to_string(),
+        };
+
+        if last.as_ref() != Some(&value) {
+            println!(
+                "{} {value}",
+                humantime::format_rfc3339_millis(s.captured_at)
             );
-            let n = Notification::new("Charging", Some(body.as_str()), None);
-            n.set_urgency(Urgency::Low);
-            n.show()?;
-        }
-        // Assume not charging means full (at least on my system)
-        Some(ChargingStatus::Full | ChargingStatus::NotCharging) => {
-            info!("Battery full");
-            let body = format!("{} @ 100%", battery.name);
-            let n = Notification::new("Battery full", Some(body.as_str()), None);
-            n.set_urgency(Urgency::Low);
-            n.show()?;
-        }
-        None => {}
-    }
-
-    for level in &LEVELS {
-        if old_state.level > level.level {
-            if new_state.level <= level.level {
-                info!("Battery at {}%", new_state.level);
-                let title = format!("Battery {}", level.label);
-                let body = format!(
-                    "{} @ {}%\n{}",
-                    battery.name,
-                    new_state.level,
-                    battery.remaining_labelled()
-                );
-                let n = Notification::new(title.as_str(), Some(body.as_str()), None);
-                n.set_urgency(level.urgency);
-                n.show()?;
-            }
+            last = Some(value);
+        }
+
+        std::thread::sleep(Duration::from_secs(interval));
+        bat.update();
+    }
+}
 
-            break;
+fn print_history(suspend_report: bool) -> Result<()> {
+    let path = History::default_path().ok_or("Failed to locate the history file")?;
+    let history = History::load(path, usize::MAX);
+    if suspend_report {
+        let samples: Vec<_> = history
+            .records()
+            .map(|r| (r.time(), r.state.clone()))
+            .collect();
+        let suspends = suspend::suspends(&samples);
+        if suspends.is_empty() {
+            println!("No suspends while discharging in the recorded history");
+        }
+        for drain in suspends {
+            println!("{} {drain}", humantime::format_rfc3339_seconds(drain.start));
         }
+        return Ok(());
+    }
+
+    for r in history.records() {
+        let s = &r.state;
+        println!(
+            "{} {:>3}% {:<12} {} {} {}",
+            humantime::format_rfc3339_seconds(r.time()),
+            s.level,
+            s.status.to_string(),
+            s.charge,
+            s.capacity,
+            s.current,
+        );
     }
+    Ok(())
+}
+
+fn print_efficiency(s: &BatteryState, precision: usize) {
+    let na = || String::from("N/A");
+    let fmt = |v: f64| batmon::format_decimal(v, precision);
+    let power = s.power_w();
+    let full = s.energy_full_wh();
+
+    println!(
+        "Power: {}",
+        power.map_or_else(na, |p| format!("{} W", fmt(p)))
+    );
+    println!(
+        "Full energy: {}",
+        full.map_or_else(na, |e| format!("{} Wh", fmt(e)))
+    );
+
+    let life = match (full, power) {
+        (Some(e), Some(p)) if p > 0.0 => format!("{} h", fmt(e / p)),
+        _ => na(),
+    };
+    println!("Life at current draw: {life}");
+
+    let drain = History::default_path()
+        .map(|path| History::load(path, usize::MAX))
+        .and_then(|h| h.session_drain());
+    let session = match drain {
+        Some((used, time)) => {
+            let secs = time.as_secs();
+            format!(
+                "{} Wh over {:0>2}:{:0>2}:{:0>2}",
+                fmt(used),
+                secs / 3600,
+                (secs / 60) % 60,
+                secs % 60
+            )
+        }
+        None => na(),
+    };
+    println!("Used this session: {session}");
+}
+
+static SNOOZE_DURATION: Duration = Duration::from_secs(10 * 60);
 
+/// Run a command on the first FreeBSD battery unit. Only the commands that need nothing but
+/// a `PowerSource` are available, the rest read sysfs attributes FreeBSD doesn't have.
+#[cfg(feature = "freebsd")]
+fn run_freebsd(args: Cli) -> Result<()> {
+    let mut bat = batmon::freebsd::Battery::find().ok_or(BatmonError::NoBattery)?;
+    let s = bat.state();
+    match args.command {
+        Some(Command::Level { .. }) => println!("{}", s.level),
+        Some(Command::Name) => println!("{}", bat.name),
+        Some(Command::Status) => println!("{}", s.status),
+        Some(Command::Time { seconds: false }) => println!("{}", bat.remaining()),
+        Some(Command::Time { seconds: true }) => {
+            let remaining = bat
+                .remaining_duration()
+                .ok_or("The remaining time can't be estimated")?;
+            println!("{}", remaining.as_secs());
+        }
+        Some(Command::Summary {
+            absolute: false,
+            json: false,
+        })
+        | None => println!("{bat}"),
+        Some(Command::Daemon(d)) => {
+            if d.daemonize {
+                daemonize::daemonize(d.log_file.as_deref())?;
+            }
+            watch(&mut bat, *d)?;
+        }
+        Some(_) => return Err("This command isn't supported on FreeBSD".into()),
+    }
     Ok(())
 }
+
+fn run_daemon(bat: &mut Battery, args: DaemonArgs) -> Result<()> {
+    if args.daemonize {
+        daemonize::daemonize(args.log_file.as_deref())?;
+    }
+
+    bat.set_schedule(PollSchedule {
+        slow_every: args.slow_poll_every,
+    });
+    bat.set_read_timeout(Some(args.read_timeout).filter(|t| !t.is_zero()));
+    watch(bat, args)
+}
+
+/// Monitor `bat` until the daemon is stopped
+fn watch(bat: &mut (impl PowerSource + std::fmt::Display), args: DaemonArgs) -> Result<()> {
+    let notifier = Notifier::new(args.notify.settings(), args.notify.dry_run);
+    let hook = args.on_transition.as_ref().map(TransitionHook::new);
+    let mut monitor = Monitor::new(notifier, hook);
+    if let Some(threshold) = args.warn_remaining {
+        monitor.add_time_warning(Event::Time, threshold);
+    }
+    if let Some(threshold) = args.critical_remaining {
+        monitor.add_time_warning(Event::TimeCritical, threshold);
+    }
+    monitor.set_level_warnings(!args.no_level_warnings);
+    if let Some(after) = args.full_plugged_reminder {
+        monitor.set_full_reminder(after, Adapter::find());
+    }
+    if let Some(path) = &args.transitions_log {
+        monitor.set_transition_log(TransitionLog::new(path));
+    }
+    if args.drain_alert {
+        let path = Baseline::default_path();
+        let baseline = path.as_ref().map(Baseline::load).unwrap_or_default();
+        monitor.set_drain_detector(DrainDetector::new(baseline, path, args.drain_factor));
+    }
+    if let Some(threshold) = args.power_saver_below {
+        monitor.set_profile_switcher(ProfileSwitcher::new(Box::new(PowerProfiles), threshold));
+    }
+
+    if !args.notify.dry_run {
+        start_libnotify()?;
+    }
+
+    let mut history = match History::default_path() {
+        Some(path) if args.history_size > 0 => Some(History::load(path, args.history_size)),
+        _ => None,
+    };
+
+    #[cfg(feature = "netlink")]
+    let mut uevents = if args.netlink {
+        match batmon::uevent::UeventListener::open() {
+            Ok(listener) => Some(listener),
+            Err(e) => {
+                warn!("Failed to listen for uevents, falling back to polling: {e}");
+                None
+            }
+        }
+    } else {
+        None
+    };
+    // SIGUSR2 wakes the daemon to dump its stats
+    counters::handle_sigusr2();
+    #[cfg(feature = "netlink")]
+    let mut woken =
+        || counters::dump_pending() || uevents.as_mut().is_some_and(|u| u.power_supply_changed());
+    #[cfg(not(feature = "netlink"))]
+    let mut woken = counters::dump_pending;
+    let status_path = Status::path();
+
+    let mut calibration = calibrate::Calibration::new();
+    let mut reminder: Option<Instant> = None;
+    // The state read by the previous update, to notice a suspend in between
+    let mut last: Option<BatteryState> = None;
+    loop {
+        if let (Some(path), Some(max_size)) = (&args.log_file, args.log_max_size) {
+            match rotate::rotate_if_larger(path, max_size, args.log_keep) {
+                Ok(true) => daemonize::reopen_log(path)?,
+                Ok(false) => {}
+                Err(e) => warn!("Failed to rotate log file: {e}"),
+            }
+        }
+
+        monitor.set_snooze(snooze::until(std::time::SystemTime::now()));
+        monitor.update(bat)?;
+        info!("{bat}");
+
+        let state = bat.state();
+        if let Some(before) = &last {
+            let (start, end) = (before.captured_at, state.captured_at);
+            let drain = suspend::is_gap(start, end, args.interval_for(before))
+                .then(|| SuspendDrain::between((start, before), (end, &state)))
+                .flatten();
+            if let Some(drain) = drain {
+                info!("{drain}");
+                monitor.record_suspend(bat, &drain);
+            }
+        }
+        last = Some(state.clone());
+
+        let status = Status {
+            battery: &state,
+            stats: monitor.stats(),
+        };
+        if let Err(e) = status.save(&status_path) {
+            debug!("Failed to save status: {e}");
+        }
+        if counters::take_dump_request() {
+            println!("{}", status.to_json_pretty()?);
+        }
+
+        if let Some(measurement) = calibration.update(&bat.state()) {
+            calibrate::record(&measurement);
+        }
+
+        if let Some(h) = &mut history {
+            if let Err(e) = h.push(bat.state()) {
+                debug!("Failed to save history: {e}");
+            }
+        }
+
+        let deadline = Instant::now() + args.interval_for(&bat.state());
+        while let Some(action) = actions::wait_until(deadline, &mut woken) {
+            match action {
+                Action::Suspend => {
+                    info!("Running emergency command '{}'", args.emergency_command);
+                    if let Err(e) = std::process::Command::new("sh")
+                        .arg("-c")
+                        .arg(&args.emergency_command)
+                        .spawn()
+                    {
+                        error!("Failed to run emergency command: {e}");
+                    }
+                }
+                Action::Snooze => {
+                    info!("Critical notification snoozed");
+                    reminder = Some(Instant::now() + SNOOZE_DURATION);
+                }
+            }
+        }
+
+        if reminder.is_some_and(|r| r <= Instant::now()) {
+            reminder = None;
+            let critical = &LEVELS[LEVELS.len() - 1];
+            if bat.state().is_critical(critical.level) {
+                monitor.remind(bat, critical)?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_of_errors() {
+        let error: Box<dyn std::error::Error> = Box::new(BatmonError::NoBattery);
+        assert_eq!(exit_code(error.as_ref()), exit_code::NO_BATTERY);
+
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        assert_eq!(exit_code(&denied), exit_code::PERMISSION_DENIED);
+        let missing = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert_eq!(exit_code(&missing), exit_code::FAILURE);
+
+        let error: Box<dyn std::error::Error> = "Failed to locate the state directory".into();
+        assert_eq!(exit_code(error.as_ref()), exit_code::FAILURE);
+    }
+}
@@ -0,0 +1,299 @@
+use crate::tier::Tier;
+use batmon::{ChargingStatus, PowerSource, TimeFormat};
+
+/// Escape the characters that are significant in Pango markup
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\'' => escaped.push_str("&apos;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The summary as Pango markup, with the level coloured by tier and the status in bold
+pub fn pango(battery: &impl PowerSource) -> String {
+    let s = battery.state();
+    let level = match Tier::of(&s) {
+        Some(tier) => format!(
+            "<span foreground=\"{}\">{}%</span>",
+            tier.color_name(),
+            s.level
+        ),
+        None => format!("{}%", s.level),
+    };
+    format!(
+        "{} @ {level}, <b>{}</b>, {}",
+        escape(battery.name()),
+        escape(&s.status.to_string()),
+        escape(&battery.remaining_labelled())
+    )
+}
+
+/// The colour set for `tier` in `colors`, if any, with later settings taking precedence
+fn color_override(colors: &[(Tier, String)], tier: Tier) -> Option<&str> {
+    colors
+        .iter()
+        .rev()
+        .find(|(t, _)| *t == tier)
+        .map(|(_, c)| c.as_str())
+}
+
+/// What xmobar output looks like
+pub struct XmobarStyle<'a> {
+    /// Colours overriding the defaults for some tiers
+    pub colors: &'a [(Tier, String)],
+    /// An icon (an xpm/xbm path) shown before the level
+    pub icon: Option<&'a str>,
+    /// A command run when the output is clicked
+    pub on_click: Option<&'a str>,
+}
+
+/// The level and remaining time in xmobar's markup, e.g. `<fc=#ff0000>12%</fc> 0:25`
+pub fn xmobar(battery: &impl PowerSource, style: &XmobarStyle) -> String {
+    let s = battery.state();
+    let mut out = String::new();
+    if let Some(icon) = style.icon {
+        out.push_str(&format!("<icon={icon}/>"));
+    }
+
+    match Tier::of(&s) {
+        Some(tier) => {
+            let color = color_override(style.colors, tier).unwrap_or(tier.xmobar());
+            out.push_str(&format!("<fc={color}>{}%</fc>", s.level));
+        }
+        None => out.push_str(&format!("{}%", s.level)),
+    }
+
+    if s.is_charging() || s.is_discharging() {
+        if let Some(remaining) = battery.remaining_duration() {
+            out.push_str(&format!(" {}", TimeFormat::Hm.format(remaining)));
+        }
+    }
+
+    match style.on_click {
+        Some(command) => format!("<action=`{command}`>{out}</action>"),
+        None => out,
+    }
+}
+
+/// Parse the command run when xmobar output is clicked. xmobar runs it as written, up to the
+/// backtick closing it, so a backtick is the one character it can't contain.
+pub fn parse_click_command(s: &str) -> Result<String, String> {
+    if s.contains('`') {
+        return Err(String::from(
+            "xmobar ends the command at a backtick, use $(...) instead",
+        ));
+    }
+    Ok(s.to_owned())
+}
+
+/// The level and remaining time for conky, e.g. `${color red} 12%${color} ↓  0:25`.
+/// Every part is padded to a fixed width so the layout doesn't shift as the values change.
+pub fn conky(battery: &impl PowerSource, colors: &[(Tier, String)]) -> String {
+    let s = battery.state();
+    let level = format!("{:>3}%", s.level);
+    let level = match Tier::of(&s) {
+        Some(tier) => {
+            let color = color_override(colors, tier).unwrap_or(tier.color_name());
+            format!("${{color {color}}}{level}${{color}}")
+        }
+        None => level,
+    };
+
+    let arrow = match s.status {
+        ChargingStatus::Charging => "↑",
+        ChargingStatus::Discharging => "↓",
+        ChargingStatus::Full | ChargingStatus::NotCharging => "=",
+        ChargingStatus::Unknown => "?",
+    };
+    let remaining = match battery.remaining_duration() {
+        Some(remaining) if s.is_charging() || s.is_discharging() => {
+            TimeFormat::Hm.format(remaining)
+        }
+        _ => String::from("--:--"),
+    };
+    format!("{level} {arrow} {remaining:>5}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use batmon::{BatteryState, MockPowerSource};
+
+    /// A battery at `level` with an hour to go at its draw
+    fn battery(level: u8, status: ChargingStatus) -> MockPowerSource {
+        let capacity = 4_000_000;
+        let charge = capacity * u64::from(level) / 100;
+        // An hour until empty or until full, whichever way the battery is going
+        let current = match status {
+            ChargingStatus::Charging => capacity - charge,
+            _ => charge,
+        };
+        let state = BatteryState::builder()
+            .level(level)
+            .capacity(capacity)
+            .charge(charge)
+            .current(current)
+            .status(status)
+            .build();
+        MockPowerSource::scripted("BAT0", vec![state])
+    }
+
+    const PLAIN: XmobarStyle = XmobarStyle {
+        colors: &[],
+        icon: None,
+        on_click: None,
+    };
+
+    #[test]
+    fn xmobar_tiers() {
+        for (level, status, expected) in [
+            (80, ChargingStatus::Discharging, "80% 1:00"),
+            (20, ChargingStatus::Discharging, "<fc=#ffff00>20%</fc> 1:00"),
+            (10, ChargingStatus::Discharging, "<fc=#ff0000>10%</fc> 1:00"),
+            (10, ChargingStatus::Charging, "<fc=#00ff00>10%</fc> 1:00"),
+            (100, ChargingStatus::Full, "100%"),
+            (80, ChargingStatus::NotCharging, "80%"),
+            (10, ChargingStatus::Unknown, "10%"),
+        ] {
+            assert_eq!(
+                xmobar(&battery(level, status), &PLAIN),
+                expected,
+                "{status:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn xmobar_style() {
+        let colors = [(Tier::Critical, String::from("#aa0000"))];
+        let style = XmobarStyle {
+            colors: &colors,
+            icon: Some("/usr/share/icons/battery.xpm"),
+            on_click: Some("notify-send 'Battery' \"$(batmon)\" && true"),
+        };
+        assert_eq!(
+            xmobar(&battery(10, ChargingStatus::Discharging), &style),
+            "<action=`notify-send 'Battery' \"$(batmon)\" && true`>\
+             <icon=/usr/share/icons/battery.xpm/><fc=#aa0000>10%</fc> 1:00</action>"
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(
+            escape(r#"<b>"Tom's" & co</b>"#),
+            "&lt;b&gt;&quot;Tom&apos;s&quot; &amp; co&lt;/b&gt;"
+        );
+        assert_eq!(escape("BAT0"), "BAT0");
+    }
+
+    #[test]
+    fn pango_markup() {
+        let state = battery(10, ChargingStatus::Discharging).state();
+        let battery = MockPowerSource::scripted("A&B<", vec![state]);
+        assert_eq!(
+            pango(&battery),
+            "A&amp;B&lt; @ <span foreground=\"red\">10%</span>, <b>Discharging</b>, \
+             01:00:00 remaining"
+        );
+    }
+
+    #[test]
+    fn pango_tiers() {
+        for (level, status, expected) in [
+            (
+                80,
+                ChargingStatus::Discharging,
+                "BAT0 @ 80%, <b>Discharging</b>",
+            ),
+            (
+                20,
+                ChargingStatus::Discharging,
+                "BAT0 @ <span foreground=\"yellow\">20%</span>, <b>Discharging</b>",
+            ),
+            (
+                60,
+                ChargingStatus::Charging,
+                "BAT0 @ <span foreground=\"green\">60%</span>, <b>Charging</b>",
+            ),
+            (100, ChargingStatus::Full, "BAT0 @ 100%, <b>Full</b>"),
+        ] {
+            let markup = pango(&battery(level, status));
+            assert!(markup.starts_with(expected), "{markup}");
+        }
+    }
+
+    #[test]
+    fn conky_columns() {
+        for (level, status, expected) in [
+            (80, ChargingStatus::Discharging, " 80% ↓  1:00"),
+            (
+                20,
+                ChargingStatus::Discharging,
+                "${color yellow} 20%${color} ↓  1:00",
+            ),
+            (
+                5,
+                ChargingStatus::Discharging,
+                "${color red}  5%${color} ↓  1:00",
+            ),
+            (
+                60,
+                ChargingStatus::Charging,
+                "${color green} 60%${color} ↑  1:00",
+            ),
+            (100, ChargingStatus::Full, "100% = --:--"),
+            (80, ChargingStatus::NotCharging, " 80% = --:--"),
+            (80, ChargingStatus::Unknown, " 80% ? --:--"),
+        ] {
+            assert_eq!(conky(&battery(level, status), &[]), expected, "{status:?}");
+        }
+    }
+
+    #[test]
+    fn conky_long_remaining_time() {
+        // 12.5 hours at the draw still fits the column
+        let state = BatteryState::builder()
+            .level(60)
+            .capacity(4_000_000)
+            .charge(2_500_000)
+            .current(200_000)
+            .status(ChargingStatus::Discharging)
+            .build();
+        let battery = MockPowerSource::scripted("BAT0", vec![state]);
+        assert_eq!(conky(&battery, &[]), " 60% ↓ 12:30");
+    }
+
+    #[test]
+    fn conky_colors() {
+        let colors = [
+            (Tier::Critical, String::from("#aa0000")),
+            (Tier::Critical, String::from("#cc0000")),
+        ];
+        assert_eq!(
+            conky(&battery(5, ChargingStatus::Discharging), &colors),
+            "${color #cc0000}  5%${color} ↓  1:00"
+        );
+        assert_eq!(
+            conky(&battery(20, ChargingStatus::Discharging), &colors),
+            "${color yellow} 20%${color} ↓  1:00"
+        );
+    }
+
+    #[test]
+    fn click_command_without_backticks() {
+        assert_eq!(
+            parse_click_command("a && b 'c'").as_deref(),
+            Ok("a && b 'c'")
+        );
+        assert!(parse_click_command("notify-send `batmon`").is_err());
+    }
+}
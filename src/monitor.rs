@@ -0,0 +1,825 @@
+use crate::counters::DaemonStats;
+use crate::drain::DrainDetector;
+use crate::hook::TransitionHook;
+use crate::notify::{DndPolicy, Event, Notifier, Progress};
+use crate::profiles::ProfileSwitcher;
+use crate::suspend::SuspendDrain;
+use crate::transitions::TransitionLog;
+use crate::Result;
+use batmon::{Adapter, BatteryState, BatteryValue, ChargingStatus, PowerSource};
+use std::time::{Duration, SystemTime};
+
+pub struct BatteryLevelSettings {
+    pub level: u8,
+    label: &'static str,
+    event: Event,
+}
+
+pub static LEVELS: [BatteryLevelSettings; 3] = [
+    BatteryLevelSettings {
+        level: 50,
+        label: "at half",
+        event: Event::Half,
+    },
+    BatteryLevelSettings {
+        level: 25,
+        label: "low",
+        event: Event::Low,
+    },
+    BatteryLevelSettings {
+        level: 15,
+        label: "critical",
+        event: Event::Critical,
+    },
+];
+
+/// Detects the estimated time remaining dropping below a threshold while discharging.
+/// Estimates are smoothed since they jump around with the load, the warning only fires once
+/// the smoothed estimate has been below the threshold for consecutive samples, and it is only
+/// re-armed once the smoothed estimate recovers comfortably above the threshold.
+pub struct TimeWarning {
+    threshold: Duration,
+    smoothed: Option<f64>,
+    armed: bool,
+    /// The number of consecutive samples below the threshold while armed
+    below_count: u8,
+    /// When the last estimate was captured, so samples are weighted by the time between them
+    last_sample: Option<SystemTime>,
+}
+
+impl TimeWarning {
+    /// The weight of a new estimate captured `SMOOTHING_PERIOD` after the previous one
+    const SMOOTHING: f64 = 0.3;
+    const SMOOTHING_PERIOD: f64 = 15.0;
+    const REARM_MARGIN: f64 = 1.1;
+    /// The number of consecutive samples that must be below the threshold to fire
+    const CONFIRM_SAMPLES: u8 = 2;
+
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            smoothed: None,
+            armed: false,
+            below_count: 0,
+            last_sample: None,
+        }
+    }
+
+    /// The weight of an estimate captured at `time`. Estimates after a long gap (like a
+    /// suspend) outweigh the history, rather than being smoothed as if no time had passed.
+    fn weight(&self, time: SystemTime) -> f64 {
+        let elapsed = match self.last_sample.map(|last| time.duration_since(last)) {
+            Some(Ok(elapsed)) => elapsed.as_secs_f64(),
+            _ => Self::SMOOTHING_PERIOD,
+        };
+        1.0 - (1.0 - Self::SMOOTHING).powf(elapsed / Self::SMOOTHING_PERIOD)
+    }
+
+    /// Whether the smoothed estimate is currently below the threshold
+    pub fn below(&self) -> bool {
+        self.smoothed
+            .is_some_and(|s| s <= self.threshold.as_secs_f64())
+    }
+
+    /// Feed a new estimate, returning true when the warning should fire
+    pub fn update(&mut self, state: &BatteryState, estimate: Option<Duration>) -> bool {
+        let estimate = match (state.status, estimate) {
+            (ChargingStatus::Discharging, Some(e)) => e.as_secs_f64(),
+            _ => {
+                self.smoothed = None;
+                self.armed = false;
+                self.below_count = 0;
+                self.last_sample = None;
+                return false;
+            }
+        };
+
+        let smoothed = match self.smoothed {
+            Some(s) => s + self.weight(state.captured_at) * (estimate - s),
+            None => estimate,
+        };
+        self.smoothed = Some(smoothed);
+        self.last_sample = Some(state.captured_at);
+
+        let threshold = self.threshold.as_secs_f64();
+        if smoothed > threshold {
+            self.below_count = 0;
+            if smoothed > threshold * Self::REARM_MARGIN {
+                self.armed = true;
+            }
+        } else if self.armed {
+            self.below_count += 1;
+            if self.below_count >= Self::CONFIRM_SAMPLES {
+                self.armed = false;
+                self.below_count = 0;
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Detects the battery being kept at 100% on AC, which wears it faster than a charge limit.
+/// Time is measured on the wall clock, so time spent suspended while full counts too.
+pub struct FullReminder {
+    after: Duration,
+    /// When the battery was first seen full on AC, if it still is
+    since: Option<SystemTime>,
+    fired: bool,
+}
+
+impl FullReminder {
+    pub fn new(after: Duration) -> Self {
+        Self {
+            after,
+            since: None,
+            fired: false,
+        }
+    }
+
+    /// Feed whether the battery is full (really at 100%, not held at a charge limit) on AC
+    /// at `time`, returning true once when it has been for the whole duration
+    pub fn update(&mut self, full_on_ac: bool, time: SystemTime) -> bool {
+        if !full_on_ac {
+            self.since = None;
+            self.fired = false;
+            return false;
+        }
+
+        let since = *self.since.get_or_insert(time);
+        let elapsed = time.duration_since(since).unwrap_or_default();
+        if self.fired || elapsed < self.after {
+            return false;
+        }
+        self.fired = true;
+        true
+    }
+}
+
+/// Watches a battery for transitions and reports them through notifications and hooks
+pub struct Monitor {
+    notifier: Notifier,
+    hook: Option<TransitionHook>,
+    /// Events held back while the notification server was in do-not-disturb mode
+    queued: Vec<Event>,
+    /// Warnings about the time remaining and the events they send, least severe first
+    time_warnings: Vec<(Event, TimeWarning)>,
+    /// Whether to notify when the level crosses one of `LEVELS`
+    level_warnings: bool,
+    /// The last status other than unknown, so a transiently unknown status isn't a transition
+    known_status: Option<ChargingStatus>,
+    transitions: Option<TransitionLog>,
+    stats: DaemonStats,
+    full_reminder: Option<FullReminder>,
+    /// The adapter the full reminder checks, if the system has one
+    adapter: Option<Adapter>,
+    /// Notifications produced by the current update, sent together as one when it ends
+    pending: Vec<(Event, String, String)>,
+    /// The end of the snooze set with `batmon snooze`, while notifications are snoozed
+    snoozed_until: Option<SystemTime>,
+    profiles: Option<ProfileSwitcher>,
+    drain: Option<DrainDetector>,
+}
+
+impl Monitor {
+    pub fn new(notifier: Notifier, hook: Option<TransitionHook>) -> Self {
+        Self {
+            notifier,
+            hook,
+            queued: Vec::new(),
+            time_warnings: Vec::new(),
+            level_warnings: true,
+            known_status: None,
+            transitions: None,
+            stats: DaemonStats::new(),
+            full_reminder: None,
+            adapter: None,
+            pending: Vec::new(),
+            snoozed_until: None,
+            profiles: None,
+            drain: None,
+        }
+    }
+
+    /// Hold back everything but critical warnings until `until`, or stop snoozing with `None`.
+    /// Thresholds crossed in the meantime are delivered once the snooze ends.
+    pub fn set_snooze(&mut self, until: Option<SystemTime>) {
+        if self.snoozed_until.is_some() && until.is_none() {
+            info!("Snooze ended");
+        }
+        self.snoozed_until = until;
+    }
+
+    fn snoozed(&self) -> bool {
+        self.snoozed_until
+            .is_some_and(|until| until > SystemTime::now())
+    }
+
+    /// Remind the user to unplug after the battery has been at 100% on AC for `after`.
+    /// Without an adapter to check, a full status is taken to mean it is plugged in.
+    pub fn set_full_reminder(&mut self, after: Duration, adapter: Option<Adapter>) {
+        self.full_reminder = Some(FullReminder::new(after));
+        self.adapter = adapter;
+    }
+
+    /// Switch to the power-saver profile while the battery is low, see `ProfileSwitcher`
+    pub fn set_profile_switcher(&mut self, profiles: ProfileSwitcher) {
+        self.profiles = Some(profiles);
+    }
+
+    /// Notify when the battery drains much faster than usual, see `DrainDetector`
+    pub fn set_drain_detector(&mut self, drain: DrainDetector) {
+        self.drain = Some(drain);
+    }
+
+    pub fn stats(&self) -> &DaemonStats {
+        &self.stats
+    }
+
+    /// Append status changes, threshold crossings and notifications to `log`
+    pub fn set_transition_log(&mut self, log: TransitionLog) {
+        self.transitions = Some(log);
+    }
+
+    fn record(&mut self, battery: &impl PowerSource, kind: &str, fields: &[(&str, &str)]) {
+        if let Some(log) = &mut self.transitions {
+            log.record(battery, kind, fields);
+        }
+    }
+
+    /// Record the drain over a suspend in the transitions log
+    pub fn record_suspend(&mut self, battery: &impl PowerSource, drain: &SuspendDrain) {
+        let average = drain
+            .average_w
+            .map_or(String::from("-"), |w| format!("{w:.2}"));
+        let fields = [
+            ("duration", drain.duration.as_secs().to_string()),
+            ("lost", drain.lost.to_string()),
+            ("average_w", average),
+        ];
+        let fields: Vec<_> = fields.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        self.record(battery, "suspend", &fields);
+    }
+
+    /// Send `event` when the time remaining drops below `threshold`.
+    /// Warnings must be added from least to most severe.
+    pub fn add_time_warning(&mut self, event: Event, threshold: Duration) {
+        self.time_warnings
+            .push((event, TimeWarning::new(threshold)));
+    }
+
+    pub fn set_level_warnings(&mut self, enabled: bool) {
+        self.level_warnings = enabled;
+    }
+
+    /// Update the battery and notify about what changed. Everything an update produces
+    /// (like a status change and a threshold after a resume) is sent as one notification.
+    pub fn update(&mut self, battery: &mut impl PowerSource) -> Result<()> {
+        let res = self.check(battery);
+        let pending = std::mem::take(&mut self.pending);
+        self.send_all(battery, &pending)?;
+        res
+    }
+
+    fn check(&mut self, battery: &mut impl PowerSource) -> Result<()> {
+        let old_state = battery.state();
+        let old_level_known = battery.level_known();
+        let failed = battery.update().failed;
+        let new_state = battery.state();
+        self.stats.record_update(&failed, new_state.captured_at);
+
+        if let Some(hook) = &mut self.hook {
+            hook.reap();
+        }
+
+        // Do-not-disturb may have changed since the last update
+        self.notifier.forget_inhibited();
+        self.deliver_queued(battery)?;
+        self.notifier.report_suppressed()?;
+
+        // Placeholders can't be compared, so nothing is notified until the status (and the
+        // level, for devices that report one) have been read, and an unread status that
+        // becomes known isn't a transition
+        let reports_level = battery.reports_level();
+        let ready = |s: &BatteryState| {
+            s.is_field_valid(BatteryValue::Status)
+                && (!reports_level || s.is_field_valid(BatteryValue::Level))
+        };
+        if !ready(&new_state) {
+            debug!("Waiting for the status and level to be read before notifying");
+            return Ok(());
+        }
+        let baseline = !old_state.is_field_valid(BatteryValue::Status);
+
+        if let Some(profiles) = &mut self.profiles {
+            profiles.update(&new_state);
+        }
+
+        if let Some(alert) = self.drain.as_mut().and_then(|d| d.update(&new_state)) {
+            info!(
+                "Battery drawing {:.1} W, above the usual {:.1} W",
+                alert.watts, alert.baseline
+            );
+            self.record(
+                battery,
+                "threshold",
+                &[("event", &Event::Drain.to_string())],
+            );
+            let body = alert.body(battery.name());
+            self.emit(battery, Event::Drain, "Battery draining fast", &body)?;
+        }
+
+        let previous = match baseline {
+            true => new_state.status,
+            false => self.known_status.unwrap_or(old_state.status),
+        };
+        if new_state.status != ChargingStatus::Unknown {
+            self.known_status = Some(new_state.status);
+        }
+
+        if !baseline && new_state.status != old_state.status {
+            let (from, to) = (
+                format!("{:?}", old_state.status),
+                format!("{:?}", new_state.status),
+            );
+            self.record(battery, "status", &[("from", &from), ("to", &to)]);
+        }
+
+        let event = match new_state.status.edge(previous) {
+            Some(ChargingStatus::Discharging) => {
+                info!("Battery started discharging");
+                Some(Event::Discharging)
+            }
+            Some(ChargingStatus::Charging) => {
+                info!("Battery started charging");
+                Some(Event::Charging)
+            }
+            // Assume not charging means full (at least on my system)
+            Some(ChargingStatus::Full | ChargingStatus::NotCharging) => {
+                info!("Battery full");
+                Some(Event::Full)
+            }
+            Some(ChargingStatus::Unknown) => {
+                debug!("Battery status unknown");
+                None
+            }
+            None => None,
+        };
+
+        if let Some(event) = event {
+            let (title, body) = status_message(battery, event);
+            self.emit(battery, event, title, &body)?;
+        }
+
+        // Every warning sees every estimate, but only the most severe one that fired is sent
+        let estimate = battery.remaining_duration();
+        let fired = self
+            .time_warnings
+            .iter_mut()
+            .filter_map(|(event, warning)| warning.update(&new_state, estimate).then_some(*event))
+            .last();
+        if let Some(event) = fired {
+            info!("Battery has {} remaining", battery.remaining());
+            self.record(battery, "threshold", &[("event", &event.to_string())]);
+            let (title, body) = status_message(battery, event);
+            self.emit(battery, event, title, &body)?;
+        }
+
+        if let Some(reminder) = &mut self.full_reminder {
+            if let Some(adapter) = &mut self.adapter {
+                adapter.update();
+            }
+            let on_ac = self.adapter.as_ref().is_none_or(Adapter::is_online);
+            let full = new_state.status == ChargingStatus::Full && battery.raw_level() >= 100;
+            if reminder.update(full && on_ac, new_state.captured_at) {
+                info!(
+                    "Battery has been full on AC for {}",
+                    humantime::format_duration(reminder.after)
+                );
+                let body = format!(
+                    "{} has been at 100% for {}\nConsider enabling a charge limit",
+                    battery.name(),
+                    humantime::format_duration(reminder.after)
+                );
+                self.emit(battery, Event::FullPlugged, "Battery kept full", &body)?;
+            }
+        }
+
+        if !self.level_warnings {
+            return Ok(());
+        }
+
+        // Devices without a numeric level may still report a coarse one
+        if !battery.reports_level() {
+            let rank = |s: &BatteryState| match s.capacity_level.as_deref() {
+                Some("Critical") => 2,
+                Some("Low") => 1,
+                _ => 0,
+            };
+            let (old, new) = (rank(&old_state), rank(&new_state));
+            if new > old && new_state.is_discharging() {
+                let level = &LEVELS[new];
+                info!("Battery capacity level {}", level.label);
+                self.record(battery, "threshold", &[("event", &level.event.to_string())]);
+                let title = format!("Battery {}", level.label);
+                let body = format!("{} is {}", battery.name(), level.label);
+                self.emit(battery, level.event, &title, &body)?;
+            }
+            return Ok(());
+        }
+
+        if !battery.level_known() {
+            return Ok(());
+        }
+
+        // A level that was never read can't have crossed a threshold yet
+        for level in &LEVELS {
+            if !old_level_known || !old_state.is_low(level.level) {
+                if new_state.is_low(level.level) {
+                    info!("Battery at {}%", new_state.level);
+                    self.record(battery, "threshold", &[("event", &level.event.to_string())]);
+                    let (title, body) = level_message(battery, level);
+                    self.emit(battery, level.event, &title, &body)?;
+                }
+
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Repeat a level notification without treating it as a new transition
+    pub fn remind(
+        &mut self,
+        battery: &impl PowerSource,
+        level: &BatteryLevelSettings,
+    ) -> Result<()> {
+        let (title, body) = level_message(battery, level);
+        self.send(battery, level.event, &title, &body)
+    }
+
+    /// Send the notification for `event` as it would be sent in the battery's current state
+    pub fn send_event(&mut self, battery: &impl PowerSource, event: Event) -> Result<()> {
+        match LEVELS.iter().find(|l| l.event == event) {
+            Some(level) => self.remind(battery, level),
+            None => {
+                let (title, body) = status_message(battery, event);
+                self.send(battery, event, title, &body)
+            }
+        }
+    }
+
+    fn emit(
+        &mut self,
+        battery: &impl PowerSource,
+        event: Event,
+        title: &str,
+        body: &str,
+    ) -> Result<()> {
+        if let Some(hook) = &mut self.hook {
+            hook.run(event, battery);
+        }
+
+        if self.snoozed() && !matches!(event, Event::Critical | Event::TimeCritical) {
+            self.record_notification(battery, event, "snoozed");
+            if matches!(event, Event::Half | Event::Low | Event::Time) {
+                info!("Queued {event} notification until the snooze ends");
+                if !self.queued.contains(&event) {
+                    self.queued.push(event);
+                }
+            } else {
+                info!("Dropped {event} notification (snoozed)");
+            }
+            return Ok(());
+        }
+
+        match self.notifier.dnd_policy(event) {
+            DndPolicy::Bypass => {}
+            DndPolicy::Drop if self.notifier.inhibited() => {
+                info!("Dropped {event} notification (do not disturb)");
+                self.record_notification(battery, event, "dropped");
+                return Ok(());
+            }
+            DndPolicy::Queue if self.notifier.inhibited() => {
+                info!("Queued {event} notification until do not disturb ends");
+                self.record_notification(battery, event, "queued");
+                if !self.queued.contains(&event) {
+                    self.queued.push(event);
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        self.pending
+            .push((event, title.to_owned(), body.to_owned()));
+        Ok(())
+    }
+
+    fn send(
+        &mut self,
+        battery: &impl PowerSource,
+        event: Event,
+        title: &str,
+        body: &str,
+    ) -> Result<()> {
+        self.send_all(battery, &[(event, title.to_owned(), body.to_owned())])
+    }
+
+    /// Send notifications as one, recording for each whether it was delivered
+    fn send_all(
+        &mut self,
+        battery: &impl PowerSource,
+        notifications: &[(Event, String, String)],
+    ) -> Result<()> {
+        if notifications.is_empty() {
+            return Ok(());
+        }
+
+        let res = self
+            .notifier
+            .send(notifications, Progress::of(&battery.state()));
+        let (result, counter) = match res {
+            Ok(true) => ("sent", &mut self.stats.notifications_sent),
+            Ok(false) => ("rate-limited", &mut self.stats.rate_limited),
+            Err(_) => ("failed", &mut self.stats.notifications_failed),
+        };
+        *counter += notifications.len() as u64;
+        for (event, ..) in notifications {
+            self.record_notification(battery, *event, result);
+        }
+        res.map(|_| ())
+    }
+
+    fn record_notification(&mut self, battery: &impl PowerSource, event: Event, result: &str) {
+        let event = event.to_string();
+        self.record(battery, "notify", &[("event", &event), ("result", result)]);
+    }
+
+    /// Deliver events queued during do-not-disturb or a snooze with the rest of the update,
+    /// skipping any that no longer apply
+    fn deliver_queued(&mut self, battery: &impl PowerSource) -> Result<()> {
+        if self.queued.is_empty() || self.snoozed() || self.notifier.inhibited() {
+            return Ok(());
+        }
+
+        let queued = std::mem::take(&mut self.queued);
+        let state = battery.state();
+
+        for &event in &queued {
+            let current = match event {
+                Event::Charging => state.is_charging(),
+                Event::Discharging => state.is_discharging(),
+                Event::Full => matches!(
+                    state.status,
+                    ChargingStatus::Full | ChargingStatus::NotCharging
+                ),
+                Event::Time | Event::TimeCritical => self
+                    .time_warnings
+                    .iter()
+                    .any(|(e, w)| *e == event && w.below()),
+                _ => continue,
+            };
+
+            if current {
+                let (title, body) = status_message(battery, event);
+                self.pending.push((event, title.to_owned(), body));
+            } else {
+                debug!("Discarded queued {event} notification");
+            }
+        }
+
+        // Only the most severe level that still applies is worth reporting
+        let level = LEVELS
+            .iter()
+            .rev()
+            .find(|l| queued.contains(&l.event) && state.is_discharging() && state.is_low(l.level));
+        if let Some(level) = level {
+            let (title, body) = level_message(battery, level);
+            self.pending.push((level.event, title, body));
+        }
+
+        Ok(())
+    }
+}
+
+fn status_message(battery: &impl PowerSource, event: Event) -> (&'static str, String) {
+    match event {
+        Event::Discharging => (
+            "Discharging",
+            format!(
+                "{} is discharging\n{}",
+                battery.name(),
+                battery.remaining_labelled()
+            ),
+        ),
+        Event::Charging => (
+            "Charging",
+            format!(
+                "{} is charging\n{}",
+                battery.name(),
+                battery.remaining_labelled()
+            ),
+        ),
+        Event::Time => ("Battery running out", remaining_message(battery)),
+        Event::TimeCritical => ("Battery nearly empty", remaining_message(battery)),
+        Event::Drain => {
+            let state = battery.state();
+            let power = state.power_w().map_or_else(
+                || String::from("faster than usual"),
+                |w| format!("{w:.1} W"),
+            );
+            (
+                "Battery draining fast",
+                format!("{} is drawing {power}", battery.name()),
+            )
+        }
+        _ => ("Battery full", format!("{} @ 100%", battery.name())),
+    }
+}
+
+fn remaining_message(battery: &impl PowerSource) -> String {
+    format!(
+        "{} @ {}%\n{}",
+        battery.name(),
+        battery.state().level,
+        battery.remaining_labelled()
+    )
+}
+
+fn level_message(battery: &impl PowerSource, level: &BatteryLevelSettings) -> (String, String) {
+    let title = format!("Battery {}", level.label);
+    let body = format!(
+        "{} @ {}%\n{}",
+        battery.name(),
+        battery.state().level,
+        battery.remaining_labelled()
+    );
+    (title, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::NotifySettings;
+    use batmon::MockPowerSource;
+
+    fn state(level: u8, status: ChargingStatus) -> BatteryState {
+        BatteryState::builder().level(level).status(status).build()
+    }
+
+    fn discharging(level: u8) -> BatteryState {
+        state(level, ChargingStatus::Discharging)
+    }
+
+    /// The threshold events recorded while the monitor follows `states`, which start with
+    /// the state the source has before the first update
+    fn thresholds(name: &str, states: Vec<BatteryState>) -> Vec<String> {
+        let path =
+            std::env::temp_dir().join(format!("batmon-monitor-{}-{name}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let updates = states.len() - 1;
+        let mut source = MockPowerSource::scripted("BAT0", states);
+        let mut monitor = Monitor::new(Notifier::new(NotifySettings::default(), true), None);
+        monitor.set_transition_log(TransitionLog::new(&path));
+        for _ in 0..updates {
+            monitor.update(&mut source).unwrap();
+        }
+        logged_thresholds(&path)
+    }
+
+    /// The threshold events in the transitions log at `path`, which is removed
+    fn logged_thresholds(path: &std::path::Path) -> Vec<String> {
+        let log = std::fs::read_to_string(path).unwrap_or_default();
+        let _ = std::fs::remove_file(path);
+        log.lines()
+            .filter(|line| line.split(' ').nth(1) == Some("threshold"))
+            .filter_map(|line| line.split(' ').find_map(|f| f.strip_prefix("event=")))
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn threshold_is_delivered_once_after_the_snooze() {
+        let path = std::env::temp_dir().join(format!("batmon-snooze-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let states = [55, 45, 44, 43].into_iter().map(discharging).collect();
+        let mut source = MockPowerSource::scripted("BAT0", states);
+        let mut monitor = Monitor::new(Notifier::new(NotifySettings::default(), true), None);
+        monitor.set_transition_log(TransitionLog::new(&path));
+
+        let snooze = Duration::from_millis(300);
+        monitor.set_snooze(Some(SystemTime::now() + snooze));
+        monitor.update(&mut source).unwrap();
+        std::thread::sleep(snooze);
+        monitor.update(&mut source).unwrap();
+        monitor.update(&mut source).unwrap();
+
+        let log = std::fs::read_to_string(&path).unwrap_or_default();
+        let _ = std::fs::remove_file(&path);
+        let notifications: Vec<String> = log
+            .lines()
+            .filter(|line| line.split(' ').nth(1) == Some("notify"))
+            .map(|line| {
+                let field = |name| line.split(' ').find_map(|f| f.strip_prefix(name));
+                format!("{} {}", field("event=").unwrap(), field("result=").unwrap())
+            })
+            .collect();
+        assert_eq!(notifications, ["half snoozed", "half sent"]);
+    }
+
+    #[test]
+    fn coarse_level_warns_without_a_numeric_level() {
+        let path = std::env::temp_dir().join(format!("batmon-coarse-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let states = ["Normal", "Normal", "Low", "Low", "Critical", "Critical"]
+            .into_iter()
+            .map(|level| {
+                BatteryState::builder()
+                    .status(ChargingStatus::Discharging)
+                    .capacity_level(level)
+                    .unread(BatteryValue::Level)
+                    .build()
+            })
+            .collect();
+        let mut source = MockPowerSource::scripted("BAT0", states).without_numeric_level();
+        let mut monitor = Monitor::new(Notifier::new(NotifySettings::default(), true), None);
+        monitor.set_transition_log(TransitionLog::new(&path));
+        for _ in 0..5 {
+            monitor.update(&mut source).unwrap();
+        }
+        assert_eq!(logged_thresholds(&path), ["low", "critical"]);
+    }
+
+    #[test]
+    fn each_threshold_notifies_once() {
+        let states = [60, 55, 50, 45, 30, 25, 20, 15, 10, 5]
+            .into_iter()
+            .map(discharging)
+            .collect();
+        assert_eq!(thresholds("once", states), ["half", "low", "critical"]);
+    }
+
+    #[test]
+    fn finished_hooks_are_reaped_on_every_update() {
+        let script = crate::hook::tests::script("reap", "exit 0");
+        let states = vec![discharging(55), discharging(45), discharging(45)];
+        let mut source = MockPowerSource::scripted("BAT0", states);
+        let hook = TransitionHook::new(&script);
+        let mut monitor = Monitor::new(Notifier::new(NotifySettings::default(), true), Some(hook));
+        monitor.update(&mut source).unwrap();
+        let hook = monitor.hook.as_ref().unwrap();
+        assert_eq!(hook.running(), 1);
+
+        // The next update has no transition to run the hook again, only to reap it
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        monitor.update(&mut source).unwrap();
+        let _ = std::fs::remove_file(&script);
+        assert_eq!(monitor.hook.as_ref().unwrap().running(), 0);
+    }
+
+    #[test]
+    fn staying_below_a_threshold_does_not_repeat() {
+        let states = vec![
+            discharging(30),
+            discharging(25),
+            discharging(25),
+            discharging(24),
+        ];
+        assert_eq!(thresholds("repeat", states), ["low"]);
+    }
+
+    #[test]
+    fn recharging_rearms_a_threshold() {
+        let states = vec![
+            discharging(26),
+            discharging(25),
+            state(25, ChargingStatus::Charging),
+            state(40, ChargingStatus::Charging),
+            discharging(40),
+            discharging(25),
+        ];
+        assert_eq!(thresholds("rearm", states), ["low", "low"]);
+    }
+
+    #[test]
+    fn unread_level_is_not_a_crossing() {
+        let unread = BatteryState::builder()
+            .status(ChargingStatus::Discharging)
+            .unread(BatteryValue::Level)
+            .build();
+        let states = vec![discharging(60), unread, discharging(60)];
+        assert!(thresholds("unread", states).is_empty());
+    }
+
+    #[test]
+    fn disabled_level_warnings_stay_quiet() {
+        let mut source = MockPowerSource::scripted("BAT0", vec![discharging(60), discharging(10)]);
+        let mut monitor = Monitor::new(Notifier::new(NotifySettings::default(), true), None);
+        monitor.set_level_warnings(false);
+        monitor.update(&mut source).unwrap();
+        assert_eq!(monitor.stats().notifications_sent, 0);
+    }
+}
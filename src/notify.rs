@@ -0,0 +1,651 @@
+use crate::actions::{self, Action};
+use crate::limit::{RateLimit, TokenBucket};
+use crate::Result;
+use batmon::{BatteryState, BatteryValue};
+use clap::ValueEnum;
+use glib::ToVariant;
+use libnotify::{Notification, Urgency};
+use std::time::{Duration, Instant};
+use strum::{Display, EnumCount, EnumIter, EnumString, IntoEnumIterator};
+
+/// A battery event that can produce a notification
+#[derive(
+    Debug, Clone, Copy, Display, EnumCount, EnumIter, EnumString, PartialEq, Eq, ValueEnum,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum Event {
+    Half,
+    Low,
+    Critical,
+    Charging,
+    Discharging,
+    Full,
+    /// The estimated time remaining dropped below the configured warning
+    Time,
+    /// The estimated time remaining dropped below the configured critical warning
+    #[strum(serialize = "time-critical")]
+    TimeCritical,
+    /// The battery has been kept at 100% on AC for the configured duration
+    #[strum(serialize = "full-plugged")]
+    FullPlugged,
+    /// The battery has been draining much faster than usual for a few minutes
+    Drain,
+}
+
+/// A value stored for every notification event
+#[derive(Debug, Clone)]
+pub struct EventMap<T>([T; Event::COUNT]);
+
+impl Event {
+    /// The notification category: battery.low for warnings, battery.charging when plugged in
+    /// and device.battery otherwise
+    pub fn category(self) -> &'static str {
+        match self {
+            Event::Low | Event::Critical | Event::Time | Event::TimeCritical => "battery.low",
+            Event::Charging | Event::Full | Event::FullPlugged => "battery.charging",
+            Event::Half | Event::Discharging | Event::Drain => "device.battery",
+        }
+    }
+}
+
+impl<T> EventMap<T> {
+    pub fn set(&mut self, event: Event, value: T) {
+        self.0[event as usize] = value;
+    }
+}
+
+impl<T> std::ops::Index<Event> for EventMap<T> {
+    type Output = T;
+    fn index(&self, event: Event) -> &Self::Output {
+        &self.0[event as usize]
+    }
+}
+
+impl Default for EventMap<Urgency> {
+    fn default() -> Self {
+        // Indexed in the same order as `Event`
+        Self([
+            Urgency::Low,
+            Urgency::Normal,
+            Urgency::Critical,
+            Urgency::Low,
+            Urgency::Normal,
+            Urgency::Low,
+            Urgency::Normal,
+            Urgency::Critical,
+            Urgency::Low,
+            Urgency::Normal,
+        ])
+    }
+}
+
+/// What to do with an event while the notification server is in do-not-disturb mode
+#[derive(Debug, Clone, Copy, Default, EnumString, PartialEq, Eq)]
+#[strum(serialize_all = "lowercase")]
+pub enum DndPolicy {
+    /// Send it anyway and let the notification server decide
+    #[default]
+    Bypass,
+    /// Hold it until do-not-disturb ends, discarding it if it no longer applies by then
+    Queue,
+    /// Discard it
+    Drop,
+}
+
+impl Default for EventMap<DndPolicy> {
+    fn default() -> Self {
+        Self([DndPolicy::default(); Event::COUNT])
+    }
+}
+
+impl Default for EventMap<Option<String>> {
+    fn default() -> Self {
+        let mut sounds = Self(std::array::from_fn(|_| None));
+        sounds.set(Event::Critical, Some(String::from("battery-caution")));
+        sounds.set(Event::TimeCritical, Some(String::from("battery-caution")));
+        sounds
+    }
+}
+
+/// A sound name from the freedesktop sound theme, where `none` or an empty name disables the sound
+pub fn parse_sound(s: &str) -> std::result::Result<Option<String>, String> {
+    match s {
+        "" | "none" => Ok(None),
+        _ => Ok(Some(s.to_string())),
+    }
+}
+
+pub fn parse_dnd_policy(s: &str) -> std::result::Result<DndPolicy, String> {
+    s.parse()
+        .map_err(|_| format!("invalid policy '{s}' (expected bypass, queue or drop)"))
+}
+
+pub fn parse_urgency(s: &str) -> std::result::Result<Urgency, String> {
+    match s {
+        "low" => Ok(Urgency::Low),
+        "normal" => Ok(Urgency::Normal),
+        "critical" => Ok(Urgency::Critical),
+        _ => Err(format!(
+            "invalid urgency '{s}' (expected low, normal or critical)"
+        )),
+    }
+}
+
+fn urgency_rank(urgency: Urgency) -> u8 {
+    match urgency {
+        Urgency::Low => 0,
+        Urgency::Critical => 2,
+        _ => 1,
+    }
+}
+
+fn urgency_name(urgency: Urgency) -> &'static str {
+    match urgency {
+        Urgency::Low => "low",
+        Urgency::Critical => "critical",
+        _ => "normal",
+    }
+}
+
+/// Parse an `EVENT=VALUE` setting, naming the offending entry on failure
+pub fn parse_event_setting<T>(
+    s: &str,
+    parse_value: impl Fn(&str) -> std::result::Result<T, String>,
+) -> std::result::Result<(Event, T), String> {
+    let (event, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("'{s}' is not of the form EVENT=VALUE"))?;
+
+    let event = event.trim().parse().map_err(|_| {
+        let events: Vec<_> = Event::iter().map(|e| e.to_string()).collect();
+        format!(
+            "unknown event '{}' in '{s}' (expected one of {})",
+            event.trim(),
+            events.join(", ")
+        )
+    })?;
+
+    let value = parse_value(value.trim()).map_err(|e| format!("{e} in '{s}'"))?;
+    Ok((event, value))
+}
+
+/// A typed notification hint, see the freedesktop notification specification for the keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hint {
+    /// `category`, so notification servers can theme, route or mute battery notifications
+    Category(&'static str),
+    /// `sound-name`
+    SoundName(String),
+    /// `suppress-sound`
+    SuppressSound,
+    /// `value`, a percentage some servers render as a progress bar
+    Value(u8),
+}
+
+impl Hint {
+    fn apply(&self, n: &Notification) {
+        match self {
+            Hint::Category(category) => n.set_category(category),
+            Hint::SoundName(name) => n.set_hint("sound-name", Some(name.to_variant())),
+            Hint::SuppressSound => n.set_hint("suppress-sound", Some(true.to_variant())),
+            Hint::Value(value) => n.set_hint("value", Some(i32::from(*value).to_variant())),
+        }
+    }
+}
+
+impl std::fmt::Display for Hint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Hint::Category(category) => write!(f, "category={category}"),
+            Hint::SoundName(name) => write!(f, "sound-name={name}"),
+            Hint::SuppressSound => write!(f, "suppress-sound=true"),
+            Hint::Value(value) => write!(f, "value={value}"),
+        }
+    }
+}
+
+/// Per-event notification settings
+#[derive(Debug, Clone)]
+pub struct NotifySettings {
+    pub urgency: EventMap<Urgency>,
+    pub dnd: EventMap<DndPolicy>,
+    pub sound: EventMap<Option<String>>,
+    /// Suppress sounds on every notification
+    pub silent: bool,
+    /// Set the `value` hint, which some servers render as a progress bar
+    pub progress_hint: bool,
+    pub rate_limit: RateLimit,
+    /// The rate limit for notifications with critical urgency
+    pub critical_rate_limit: RateLimit,
+}
+
+impl Default for NotifySettings {
+    fn default() -> Self {
+        Self {
+            urgency: EventMap::default(),
+            dnd: EventMap::default(),
+            sound: EventMap::default(),
+            silent: false,
+            progress_hint: true,
+            rate_limit: RateLimit {
+                count: 6,
+                period: Duration::from_secs(60),
+            },
+            critical_rate_limit: RateLimit {
+                count: 20,
+                period: Duration::from_secs(60),
+            },
+        }
+    }
+}
+
+impl NotifySettings {
+    pub fn hints(&self, event: Event, progress: Progress) -> Vec<Hint> {
+        let mut hints = vec![Hint::Category(event.category())];
+        if self.silent {
+            hints.push(Hint::SuppressSound);
+        } else if let Some(name) = &self.sound[event] {
+            hints.push(Hint::SoundName(name.clone()));
+        }
+        if let Some(value) = progress.value(event).filter(|_| self.progress_hint) {
+            hints.push(Hint::Value(value));
+        }
+        hints
+    }
+}
+
+/// What a notification's `value` hint shows: the level, or the charge as a percentage
+/// of the capacity for notifications about charging. Either is `None` when unread.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub level: Option<u8>,
+    pub charge: Option<u8>,
+}
+
+impl Progress {
+    pub fn of(state: &BatteryState) -> Self {
+        let read = |value| state.is_field_valid(value);
+        Self {
+            level: read(BatteryValue::Level).then_some(state.level),
+            charge: (read(BatteryValue::Charge)
+                && read(BatteryValue::Capacity)
+                && state.capacity > 0)
+                .then(|| state.percent_f64().round() as u8),
+        }
+    }
+
+    /// The value for a notification about `event`, at most 100
+    fn value(self, event: Event) -> Option<u8> {
+        let value = match event {
+            Event::Charging | Event::Full | Event::FullPlugged => self.charge.or(self.level),
+            _ => self.level,
+        };
+        value.map(|v| v.min(100))
+    }
+}
+
+/// How long to wait for the notification server to report whether it is inhibited
+const INHIBITED_TIMEOUT: Duration = Duration::from_millis(500);
+
+fn query_inhibited() -> bool {
+    let child = std::process::Command::new("busctl")
+        .args([
+            "--user",
+            "--timeout=1",
+            "get-property",
+            "org.freedesktop.Notifications",
+            "/org/freedesktop/Notifications",
+            "org.freedesktop.Notifications",
+            "Inhibited",
+        ])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            debug!("Failed to ask the notification server whether it is inhibited: {e}");
+            return false;
+        }
+    };
+
+    let deadline = Instant::now() + INHIBITED_TIMEOUT;
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            _ => {
+                debug!("Notification server did not report an inhibited state in time");
+                let _ = child.kill();
+                let _ = child.wait();
+                return false;
+            }
+        }
+    }
+
+    match child.wait_with_output() {
+        Ok(o) if o.status.success() => parse_inhibited(&String::from_utf8_lossy(&o.stdout)),
+        _ => {
+            debug!("Notification server does not report an inhibited state");
+            false
+        }
+    }
+}
+
+/// Whether `busctl get-property` printed a true boolean, like `b true`
+fn parse_inhibited(stdout: &str) -> bool {
+    stdout.trim() == "b true"
+}
+
+/// Sends notifications for battery events, either through libnotify or to standard output
+pub struct Notifier {
+    settings: NotifySettings,
+    dry_run: bool,
+    /// The last notification with action buttons, kept alive so its actions can be delivered
+    actionable: Option<Notification>,
+    budget: TokenBucket,
+    /// A separate budget for critical notifications so emergencies are never crowded out
+    critical_budget: TokenBucket,
+    /// Notifications dropped by the rate limit since the last report
+    suppressed: u32,
+    /// Print a description of every notification as well as showing it
+    echo: bool,
+    /// Whether the notification server was inhibited when first asked during this update
+    inhibited: Option<bool>,
+}
+
+impl Notifier {
+    pub fn new(settings: NotifySettings, dry_run: bool) -> Self {
+        let now = Instant::now();
+        Self {
+            budget: TokenBucket::new(settings.rate_limit, now),
+            critical_budget: TokenBucket::new(settings.critical_rate_limit, now),
+            settings,
+            dry_run,
+            actionable: None,
+            suppressed: 0,
+            echo: false,
+            inhibited: None,
+        }
+    }
+
+    pub fn set_echo(&mut self, echo: bool) {
+        self.echo = echo;
+    }
+
+    pub fn dnd_policy(&self, event: Event) -> DndPolicy {
+        self.settings.dnd[event]
+    }
+
+    /// Whether the notification server is in do-not-disturb mode. The server is asked at
+    /// most once per update (see `forget_inhibited`), and only waited on for
+    /// `INHIBITED_TIMEOUT`. Servers that don't answer in time or don't expose the `Inhibited`
+    /// property are never considered inhibited.
+    pub fn inhibited(&mut self) -> bool {
+        *self.inhibited.get_or_insert_with(query_inhibited)
+    }
+
+    /// Ask the notification server again the next time `inhibited` is called
+    pub fn forget_inhibited(&mut self) {
+        self.inhibited = None;
+    }
+
+    /// Show notifications as one, returning whether it was shown rather than rate limited.
+    /// The most urgent one (the last of those equally urgent) gives the title, body, urgency
+    /// and hints, and the titles of the rest are listed after its body.
+    pub fn send(
+        &mut self,
+        notifications: &[(Event, String, String)],
+        progress: Progress,
+    ) -> Result<bool> {
+        let Some(main) = (0..notifications.len())
+            .max_by_key(|&i| urgency_rank(self.settings.urgency[notifications[i].0]))
+        else {
+            return Ok(false);
+        };
+        let (event, title, body) = &notifications[main];
+        let event = *event;
+        let urgency = self.settings.urgency[event];
+
+        let budget = match urgency {
+            Urgency::Critical => &mut self.critical_budget,
+            _ => &mut self.budget,
+        };
+        if !budget.try_take(Instant::now()) {
+            self.suppressed += 1;
+            info!("Rate limited {event} notification '{title}'");
+            return Ok(false);
+        }
+
+        let mut body = body.clone();
+        let mut tags = vec![event.to_string()];
+        for (i, (other, title, _)) in notifications.iter().enumerate() {
+            if i != main {
+                body.push_str(&format!("\n{title}"));
+                tags.push(other.to_string());
+            }
+        }
+
+        let hints = self.settings.hints(event, progress);
+        let actionable = notifications
+            .iter()
+            .any(|(e, ..)| matches!(e, Event::Critical | Event::TimeCritical));
+        self.show(&tags.join("+"), title, &body, urgency, &hints, actionable)?;
+        Ok(true)
+    }
+
+    /// Report notifications dropped by the rate limit, once the limit allows it
+    pub fn report_suppressed(&mut self) -> Result<()> {
+        if self.suppressed == 0 || !self.budget.try_take(Instant::now()) {
+            return Ok(());
+        }
+
+        let body = format!("{} battery notifications were suppressed", self.suppressed);
+        self.suppressed = 0;
+        self.show(
+            "suppressed",
+            "Too many notifications",
+            &body,
+            Urgency::Normal,
+            &[Hint::Category("device.battery")],
+            false,
+        )
+    }
+
+    fn show(
+        &mut self,
+        tag: &str,
+        title: &str,
+        body: &str,
+        urgency: Urgency,
+        hints: &[Hint],
+        actionable: bool,
+    ) -> Result<()> {
+        if self.dry_run || self.echo {
+            let hints: Vec<_> = hints.iter().map(|h| h.to_string()).collect();
+            println!(
+                "[{tag}] {title} ({}) [{}]: {}",
+                urgency_name(urgency),
+                hints.join(", "),
+                body.replace('\n', " / ")
+            );
+        }
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let n = Notification::new(title, Some(body), None);
+        n.set_urgency(urgency);
+        for hint in hints {
+            hint.apply(&n);
+        }
+
+        if actionable && actions::supported() {
+            actions::add(&n, Action::Suspend);
+            actions::add(&n, Action::Snooze);
+            n.show()?;
+            self.actionable = Some(n);
+        } else {
+            n.show()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inhibited_property() {
+        assert!(parse_inhibited("b true\n"));
+        assert!(parse_inhibited("b true"));
+        assert!(!parse_inhibited("b false\n"));
+        assert!(!parse_inhibited(""));
+        // A server with the property under another type doesn't count
+        assert!(!parse_inhibited("s \"true\"\n"));
+    }
+
+    #[test]
+    fn progress_value() {
+        let state = BatteryState::builder()
+            .level(55)
+            .capacity(4_000_000)
+            .charge(3_000_000)
+            .build();
+        let progress = Progress::of(&state);
+        assert_eq!(progress.value(Event::Low), Some(55));
+        assert_eq!(progress.value(Event::Charging), Some(75));
+        assert_eq!(progress.value(Event::Full), Some(75));
+
+        // The level stands in for an unread charge, and nothing for an unread level
+        let state = BatteryState::builder()
+            .level(55)
+            .unread(BatteryValue::Charge)
+            .build();
+        assert_eq!(Progress::of(&state).value(Event::FullPlugged), Some(55));
+        let state = BatteryState::builder().unread(BatteryValue::Level).build();
+        assert_eq!(Progress::of(&state).value(Event::Critical), None);
+
+        // Rescaled levels can go past 100
+        let progress = Progress {
+            level: Some(104),
+            charge: Some(101),
+        };
+        assert_eq!(progress.value(Event::Half), Some(100));
+        assert_eq!(progress.value(Event::Full), Some(100));
+    }
+
+    #[test]
+    fn value_hint() {
+        let progress = Progress {
+            level: Some(9),
+            charge: None,
+        };
+        let mut settings = NotifySettings::default();
+        let hints = settings.hints(Event::Critical, progress);
+        assert_eq!(
+            hints,
+            [
+                Hint::Category("battery.low"),
+                Hint::SoundName(String::from("battery-caution")),
+                Hint::Value(9),
+            ]
+        );
+        // As shown in the dry-run output
+        let shown: Vec<_> = hints.iter().map(Hint::to_string).collect();
+        assert_eq!(
+            shown.join(", "),
+            "category=battery.low, sound-name=battery-caution, value=9"
+        );
+
+        settings.progress_hint = false;
+        assert_eq!(
+            settings.hints(Event::Half, progress),
+            [Hint::Category("device.battery")]
+        );
+    }
+
+    #[test]
+    fn parse_urgency_names() {
+        assert_eq!(parse_urgency("low"), Ok(Urgency::Low));
+        assert_eq!(parse_urgency("normal"), Ok(Urgency::Normal));
+        assert_eq!(parse_urgency("critical"), Ok(Urgency::Critical));
+        assert_eq!(
+            parse_urgency("urgent"),
+            Err(String::from(
+                "invalid urgency 'urgent' (expected low, normal or critical)"
+            ))
+        );
+        assert!(parse_urgency("Low").is_err());
+    }
+
+    #[test]
+    fn default_settings() {
+        let urgency = EventMap::<Urgency>::default();
+        // The array is indexed by `Event`, so check it against the events by name
+        for event in Event::iter() {
+            let expected = match event {
+                Event::Critical | Event::TimeCritical => Urgency::Critical,
+                Event::Low | Event::Discharging | Event::Time | Event::Drain => Urgency::Normal,
+                Event::Half | Event::Charging | Event::Full | Event::FullPlugged => Urgency::Low,
+            };
+            assert_eq!(urgency[event], expected, "{event}");
+        }
+
+        let dnd = EventMap::<DndPolicy>::default();
+        assert!(Event::iter().all(|event| dnd[event] == DndPolicy::Bypass));
+
+        let sound = EventMap::<Option<String>>::default();
+        for event in Event::iter() {
+            let expected =
+                matches!(event, Event::Critical | Event::TimeCritical).then_some("battery-caution");
+            assert_eq!(sound[event].as_deref(), expected, "{event}");
+        }
+    }
+
+    #[test]
+    fn event_settings() {
+        assert_eq!(
+            parse_event_setting(" time-critical = critical ", parse_urgency),
+            Ok((Event::TimeCritical, Urgency::Critical))
+        );
+        assert_eq!(
+            parse_event_setting("low=queue", parse_dnd_policy),
+            Ok((Event::Low, DndPolicy::Queue))
+        );
+        assert_eq!(
+            parse_event_setting("full=none", parse_sound),
+            Ok((Event::Full, None))
+        );
+    }
+
+    #[test]
+    fn invalid_settings_name_the_entry() {
+        let err = parse_event_setting("low", parse_urgency).unwrap_err();
+        assert_eq!(err, "'low' is not of the form EVENT=VALUE");
+
+        let err = parse_event_setting("lowest=critical", parse_urgency).unwrap_err();
+        assert!(
+            err.starts_with(
+                "unknown event 'lowest' in 'lowest=critical' (expected one of half, low"
+            ),
+            "{err}"
+        );
+
+        let err = parse_event_setting("low=loud", parse_urgency).unwrap_err();
+        assert_eq!(
+            err,
+            "invalid urgency 'loud' (expected low, normal or critical) in 'low=loud'"
+        );
+
+        let err = parse_event_setting("full=later", parse_dnd_policy).unwrap_err();
+        assert_eq!(
+            err,
+            "invalid policy 'later' (expected bypass, queue or drop) in 'full=later'"
+        );
+    }
+}
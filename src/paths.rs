@@ -0,0 +1,26 @@
+use std::path::PathBuf;
+
+fn xdg_home(var: &str, fallback: &str) -> Option<PathBuf> {
+    match std::env::var_os(var) {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => Some(PathBuf::from(std::env::var_os("HOME")?).join(fallback)),
+    }
+}
+
+/// The directory for data that should persist across restarts, like the state history
+pub fn state_dir() -> Option<PathBuf> {
+    Some(xdg_home("XDG_STATE_HOME", ".local/state")?.join("batmon"))
+}
+
+/// The directory systemd looks for user units in
+pub fn systemd_user_dir() -> Option<PathBuf> {
+    Some(xdg_home("XDG_CONFIG_HOME", ".config")?.join("systemd/user"))
+}
+
+/// The directory for files that only live as long as the session, like the daemon's pidfile
+pub fn runtime_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => std::env::temp_dir(),
+    }
+}
@@ -1,9 +1,208 @@
-use std::{error::Error, path::PathBuf, str::FromStr};
+use crate::units;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::{error::Error, io, str::FromStr, time::Duration};
+
+/// How many times an attribute is read before giving up on a transient error
+const READ_ATTEMPTS: u32 = 3;
+/// How long to wait before retrying a read, doubled on every attempt
+const RETRY_BACKOFF: Duration = Duration::from_millis(2);
+
+/// Whether a failed read is worth retrying, as the embedded controller can fail reads while busy
+fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+    ) || matches!(e.raw_os_error(), Some(libc::EBUSY | libc::EIO))
+}
+
+/// Call `read` until it returns something other than a transient error or an empty
+/// (short) read, up to `READ_ATTEMPTS` times
+fn read_with_retry(mut read: impl FnMut() -> io::Result<String>) -> io::Result<String> {
+    let mut backoff = RETRY_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        let result = read();
+        let retry = match &result {
+            Ok(data) => data.trim().is_empty(),
+            Err(e) => is_transient(e),
+        };
+        if !retry || attempt >= READ_ATTEMPTS {
+            return result;
+        }
+
+        debug!("Retrying transient read failure ({attempt}/{READ_ATTEMPTS})");
+        std::thread::sleep(backoff);
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
+/// A read for the worker: the file, and where to send its contents
+type ReadRequest = (PathBuf, mpsc::SyncSender<io::Result<String>>);
+
+/// Reads attributes on a helper thread so a read that hangs, as with a buggy ACPI method,
+/// can be given up on after `timeout`. One thread serves every read, and while a read hangs
+/// later ones fail at once rather than piling up behind it.
+#[derive(Debug)]
+pub(crate) struct ReadWorker {
+    requests: mpsc::Sender<ReadRequest>,
+    /// Set while a read is outstanding, which is still the case after it timed out
+    busy: Arc<AtomicBool>,
+    timeout: Duration,
+}
+
+impl ReadWorker {
+    /// Start the worker thread, which stops once the worker is dropped and its read returns
+    pub fn spawn(timeout: Duration) -> io::Result<Arc<Self>> {
+        let (requests, queue) = mpsc::channel::<ReadRequest>();
+        let busy = Arc::new(AtomicBool::new(false));
+        let worker_busy = Arc::clone(&busy);
+        std::thread::Builder::new()
+            .name(String::from("batmon-read"))
+            .spawn(move || {
+                for (path, reply) in queue {
+                    let data = read_with_retry(|| std::fs::read_to_string(&path));
+                    worker_busy.store(false, Ordering::Release);
+                    // Nobody is waiting if the read timed out
+                    let _ = reply.send(data);
+                }
+            })?;
+        Ok(Arc::new(Self {
+            requests,
+            busy,
+            timeout,
+        }))
+    }
+
+    pub fn read(&self, path: &Path) -> io::Result<String> {
+        if self.busy.swap(true, Ordering::Acquire) {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "an earlier read is still hanging",
+            ));
+        }
+        let (reply, data) = mpsc::sync_channel(1);
+        if self.requests.send((path.to_owned(), reply)).is_err() {
+            self.busy.store(false, Ordering::Release);
+            return Err(io::Error::other("the read worker has stopped"));
+        }
+        data.recv_timeout(self.timeout).unwrap_or_else(|_| {
+            Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("timed out after {:?}", self.timeout),
+            ))
+        })
+    }
+}
+
+/// Explain a denied read of `path`: its mode and owner, and how access could be granted.
+/// A group-readable file only needs its group; anything else needs a udev rule, as the
+/// kernel resets the permissions of sysfs attributes whenever the device appears.
+pub fn denied_hint(path: &Path) -> String {
+    let attribute = path.file_name().unwrap_or_default().to_string_lossy();
+    let device = path
+        .parent()
+        .and_then(Path::file_name)
+        .unwrap_or_default()
+        .to_string_lossy();
+    let udev = format!(
+        "grant read access with a udev rule like \
+         'SUBSYSTEM==\"power_supply\", KERNEL==\"{device}\", RUN+=\"/bin/chmod a+r %S%p/{attribute}\"'"
+    );
+    match std::fs::metadata(path) {
+        Ok(meta) if meta.mode() & 0o040 != 0 => format!(
+            "permission denied reading {} (mode {:04o}, owner {}:{}); it is readable by group {}, \
+             so add your user to that group or {udev}",
+            path.display(),
+            meta.mode() & 0o7777,
+            meta.uid(),
+            meta.gid(),
+            meta.gid()
+        ),
+        Ok(meta) => format!(
+            "permission denied reading {} (mode {:04o}, owner {}:{}); {udev}",
+            path.display(),
+            meta.mode() & 0o7777,
+            meta.uid(),
+            meta.gid()
+        ),
+        Err(e) => format!(
+            "permission denied reading {} (its mode can't be read: {e}); {udev}",
+            path.display()
+        ),
+    }
+}
+
+/// Parse the content of an attribute file, tolerating the noise some drivers add: the value
+/// is the first non-empty line, and if that doesn't parse whole, the number it starts with
+/// (as in `55 (estimated)`). Blank content parses as an empty string, which some values
+/// (like a status read mid-hotplug) accept. Returns whether the content was noisy along with
+/// the value.
+fn parse_lenient<T: FromStr>(data: &str) -> Option<(T, bool)> {
+    let Some(line) = data.lines().map(str::trim).find(|l| !l.is_empty()) else {
+        return "".parse().ok().map(|value| (value, false));
+    };
+    if let Ok(value) = line.parse() {
+        return Some((value, line != data.trim_end_matches('\n')));
+    }
+
+    let token = line.split_whitespace().next()?;
+    let sign = usize::from(token.starts_with(['-', '+']));
+    let end = token[sign..]
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or(token.len(), |i| i + sign);
+    // Neither a decimal nor a number with a unit (as in `4200 mV`) can be salvaged
+    // by cutting it short
+    let rest = &line[end..];
+    if end == sign || rest.starts_with(['.', ',']) || units::starts_with_unit(rest.trim_start()) {
+        return None;
+    }
+    Some((token[..end].parse().ok()?, true))
+}
+
+/// How often a battery's attributes are re-read.
+/// Attributes that change on human timescales (the capacity and cycle count) are only read
+/// every `slow_every` updates, everything else on every update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PollSchedule {
+    pub slow_every: u32,
+}
+
+impl Default for PollSchedule {
+    fn default() -> Self {
+        Self { slow_every: 1 }
+    }
+}
+
+impl PollSchedule {
+    /// Whether the slow attributes are due on the update numbered `tick`.
+    /// Counting updates rather than time means a long suspend never causes a burst of reads.
+    pub fn slow_due(self, tick: u64) -> bool {
+        tick.is_multiple_of(u64::from(self.slow_every.max(1)))
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct PolledValue<T> {
     value: T,
     path: PathBuf,
+    /// Whether the value has been read successfully, rather than still being the initial value
+    read: bool,
+    /// Whether noisy content has been logged, so it is only logged once
+    noise_logged: bool,
+    /// Whether the last read was denied, and whether that has been warned about,
+    /// so it is only warned about once
+    denied: bool,
+    denied_warned: bool,
+    /// Whether a timed out read has been warned about, so it is only warned about once
+    timeout_warned: bool,
+    /// The worker to read through, to give up on reads that hang
+    reader: Option<Arc<ReadWorker>>,
+    /// The micro-unit to convert values with a unit suffix to, when suffixes are accepted
+    unit: Option<&'static str>,
 }
 
 impl<T> PolledValue<T> {
@@ -11,6 +210,13 @@ impl<T> PolledValue<T> {
         let p = Self {
             value: initial_value,
             path: path.into(),
+            read: false,
+            noise_logged: false,
+            denied: false,
+            denied_warned: false,
+            timeout_warned: false,
+            reader: None,
+            unit: None,
         };
 
         if std::fs::metadata(&p.path).is_err() {
@@ -22,15 +228,103 @@ impl<T> PolledValue<T> {
 
         p
     }
+
+    /// Whether the value has ever been read successfully
+    pub fn is_read(&self) -> bool {
+        self.read
+    }
+
+    /// Whether the last read failed for lack of permission
+    pub fn is_denied(&self) -> bool {
+        self.denied
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The name of the attribute the value is read from
+    pub fn attribute(&self) -> String {
+        self.path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Accept values with a unit suffix (e.g. `4200 mV`), converting them to `micro_unit`.
+    /// Without one, as by default, a value with a unit suffix fails to parse.
+    pub fn set_unit(&mut self, micro_unit: Option<&'static str>) {
+        self.unit = micro_unit;
+    }
+
+    /// Read through `reader`, giving up on reads that hang, or directly without one
+    pub(crate) fn set_reader(&mut self, reader: Option<Arc<ReadWorker>>) {
+        self.reader = reader;
+    }
 }
 
 impl<T> PolledValue<T>
 where
     T: FromStr + Copy,
 {
+    /// Re-read the value, keeping the last good value if it can't be read or parsed
     pub fn update(&mut self) -> Result<(), Box<dyn Error>> {
-        let data = std::fs::read_to_string(&self.path)?;
-        self.value = data.trim().parse().map_err(|_| "failed to parse")?;
+        let start = std::time::Instant::now();
+        let data = match &self.reader {
+            Some(reader) => reader.read(&self.path),
+            None => read_with_retry(|| std::fs::read_to_string(&self.path)),
+        };
+        trace!(
+            "Read {} in {:?}",
+            self.path.to_string_lossy(),
+            start.elapsed()
+        );
+
+        self.denied = data
+            .as_ref()
+            .is_err_and(|e| e.kind() == io::ErrorKind::PermissionDenied);
+        if self.denied && !self.denied_warned {
+            warn!("{}", denied_hint(&self.path));
+            self.denied_warned = true;
+        }
+        if let Err(e) = &data {
+            if e.kind() == io::ErrorKind::TimedOut && !self.timeout_warned {
+                warn!(
+                    "Gave up reading {}: {e}; its last value is kept while it hangs",
+                    self.path.to_string_lossy()
+                );
+                self.timeout_warned = true;
+            }
+        }
+        let data = data?;
+        let (value, noisy) = match self.unit {
+            Some(unit) => {
+                let line = data.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+                let micro = units::parse_suffixed(line, unit)?;
+                if line.trim() != micro.to_string() && !self.noise_logged {
+                    debug!(
+                        "Converted {data:?} in {} to {micro} {unit}",
+                        self.path.to_string_lossy()
+                    );
+                    self.noise_logged = true;
+                }
+                (
+                    micro.to_string().parse().map_err(|_| "failed to parse")?,
+                    false,
+                )
+            }
+            None => parse_lenient(&data).ok_or("failed to parse")?,
+        };
+        if noisy && !self.noise_logged {
+            debug!(
+                "Ignoring noise around the value in {}: {data:?}",
+                self.path.to_string_lossy()
+            );
+            self.noise_logged = true;
+        }
+        self.value = value;
+        self.read = true;
         Ok(())
     }
 }
@@ -41,3 +335,138 @@ impl<T> std::ops::Deref for PolledValue<T> {
         &self.value
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::ChargingStatus;
+    use crate::testutil::{make_fifo, FakeRoot};
+    use std::io::Write;
+    use std::time::Instant;
+
+    /// Run `read_with_retry` over `results` in order, returning its result and the number of reads
+    fn retried(results: Vec<io::Result<String>>) -> (io::Result<String>, usize) {
+        let mut results = results.into_iter();
+        let mut calls = 0;
+        let result = read_with_retry(|| {
+            calls += 1;
+            results.next().expect("read too many times")
+        });
+        (result, calls)
+    }
+
+    #[test]
+    fn transient_failure_is_retried() {
+        let (result, calls) = retried(vec![
+            Err(io::ErrorKind::Interrupted.into()),
+            Err(io::Error::from_raw_os_error(libc::EBUSY)),
+            Ok(String::from("55\n")),
+        ]);
+        assert_eq!(result.unwrap(), "55\n");
+        assert_eq!(calls, 3);
+
+        // A short read is retried too
+        let (result, calls) = retried(vec![Ok(String::new()), Ok(String::from("55\n"))]);
+        assert_eq!(result.unwrap(), "55\n");
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn retries_give_up() {
+        let eio = || Err(io::Error::from_raw_os_error(libc::EIO));
+        let (result, calls) = retried(vec![eio(), eio(), eio()]);
+        assert_eq!(result.unwrap_err().raw_os_error(), Some(libc::EIO));
+        assert_eq!(calls, READ_ATTEMPTS as usize);
+
+        let (result, calls) = retried((0..3).map(|_| Ok(String::from(" \n"))).collect());
+        assert_eq!(result.unwrap(), " \n");
+        assert_eq!(calls, READ_ATTEMPTS as usize);
+    }
+
+    #[test]
+    fn lasting_failure_is_not_retried() {
+        let (result, calls) = retried(vec![Err(io::ErrorKind::NotFound.into())]);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn lenient_parse() {
+        let cases: [(&str, Option<(u64, bool)>); 9] = [
+            ("55\n", Some((55, false))),
+            ("55", Some((55, false))),
+            ("55 \n", Some((55, true))),
+            ("55\n\n", Some((55, false))),
+            ("\n55\n", Some((55, true))),
+            ("55 (estimated)\n", Some((55, true))),
+            ("4.2\n", None),
+            ("4200 mV\n", None),
+            ("garbage\n", None),
+        ];
+        for (data, expected) in cases {
+            assert_eq!(parse_lenient::<u64>(data), expected, "{data:?}");
+        }
+        assert_eq!(parse_lenient::<i64>("-1000 (avg)\n"), Some((-1000, true)));
+        assert_eq!(parse_lenient::<u64>(""), None);
+        assert_eq!(parse_lenient::<u64>("\n"), None);
+        assert_eq!(
+            parse_lenient::<ChargingStatus>("\n"),
+            Some((ChargingStatus::Unknown, false))
+        );
+    }
+
+    #[test]
+    fn empty_status_is_unknown() {
+        let root = FakeRoot::new();
+        let dir = root.device("BAT0", &[("status", "Full")]);
+        let mut status = PolledValue::new(ChargingStatus::Unknown, dir.join("status"));
+        status.update().unwrap();
+        assert_eq!(*status, ChargingStatus::Full);
+
+        // Read mid-hotplug, which must not leave the stale status behind
+        std::fs::write(dir.join("status"), "").unwrap();
+        status.update().unwrap();
+        assert_eq!(*status, ChargingStatus::Unknown);
+    }
+
+    #[test]
+    fn hanging_read_times_out() {
+        let root = FakeRoot::new();
+        let dir = root.device("BAT0", &[("voltage_now", "12000000")]);
+        let fifo = dir.join("current_now");
+        make_fifo(&fifo);
+
+        let timeout = Duration::from_millis(100);
+        let reader = ReadWorker::spawn(timeout).unwrap();
+        let mut current = PolledValue::new(5i64, &fifo);
+        current.set_reader(Some(Arc::clone(&reader)));
+        let mut voltage = PolledValue::new(0u64, dir.join("voltage_now"));
+        voltage.set_reader(Some(Arc::clone(&reader)));
+
+        let start = Instant::now();
+        let e = current.update().unwrap_err();
+        assert!(start.elapsed() < timeout * 5);
+        let e = e.downcast::<io::Error>().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(*current, 5);
+
+        // Fails at once rather than queueing behind the hanging read
+        let start = Instant::now();
+        assert!(voltage.update().is_err());
+        assert!(start.elapsed() < timeout);
+
+        // Once the hanging read returns, the worker serves reads again
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&fifo)
+            .unwrap()
+            .write_all(b"7\n")
+            .unwrap();
+        let start = Instant::now();
+        while voltage.update().is_err() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(*voltage, 12_000_000);
+    }
+}
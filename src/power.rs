@@ -0,0 +1,114 @@
+use crate::adapter::Adapter;
+use crate::battery::{Battery, BatteryState, FindOptions, ValueSet};
+use crate::status::ChargingStatus;
+use std::time::SystemTime;
+
+/// Every system battery and adapter, answering what the power situation is as a whole
+#[derive(Debug)]
+pub struct SystemPower {
+    batteries: Vec<Battery>,
+    adapters: Vec<Adapter>,
+}
+
+impl SystemPower {
+    /// Find every system battery and adapter, in name order
+    pub fn discover() -> Self {
+        Self {
+            batteries: Battery::find_all(&FindOptions::default()),
+            adapters: Adapter::find_all(),
+        }
+    }
+
+    pub fn update(&mut self) {
+        for bat in &mut self.batteries {
+            bat.update();
+        }
+        for adapter in &mut self.adapters {
+            adapter.update();
+        }
+    }
+
+    pub fn batteries(&self) -> &[Battery] {
+        &self.batteries
+    }
+
+    pub fn adapters(&self) -> &[Adapter] {
+        &self.adapters
+    }
+
+    /// Whether any adapter is plugged in
+    pub fn on_ac(&self) -> bool {
+        self.adapters.iter().any(Adapter::is_online)
+    }
+
+    /// The status of the system as a whole, see `resolve_status`
+    pub fn overall_status(&self) -> ChargingStatus {
+        let statuses: Vec<_> = self.batteries.iter().map(|b| b.state().status).collect();
+        let on_ac = (!self.adapters.is_empty()).then(|| self.on_ac());
+        resolve_status(on_ac, &statuses)
+    }
+
+    /// The batteries combined into one, with the overall status. Capacities, charges and
+    /// currents are summed over the batteries reporting in the same units as the first,
+    /// and the level follows from them. Without a capacity to go by the levels are averaged.
+    pub fn aggregate_state(&self) -> BatteryState {
+        let states: Vec<_> = self.batteries.iter().map(Battery::state).collect();
+        let family = states.first().map(|s| s.family).unwrap_or_default();
+        let same: Vec<_> = states.iter().filter(|s| s.family == family).collect();
+
+        let capacity = same.iter().map(|s| s.capacity).sum::<u64>();
+        let charge = same.iter().map(|s| s.charge).sum::<u64>();
+        let level = match charge.saturating_mul(100).checked_div(capacity) {
+            Some(level) => level.min(100) as u8,
+            None => {
+                let levels = states.iter().map(|s| u64::from(s.level));
+                levels
+                    .sum::<u64>()
+                    .checked_div(states.len() as u64)
+                    .unwrap_or(0) as u8
+            }
+        };
+
+        BatteryState {
+            level,
+            capacity,
+            charge,
+            current: same.iter().map(|s| s.current).sum(),
+            cycles: states.iter().map(|s| s.cycles).max().unwrap_or(0),
+            status: self.overall_status(),
+            family,
+            voltage: states.first().map_or(0, |s| s.voltage),
+            captured_at: SystemTime::now(),
+            capacity_level: None,
+            unread: states
+                .iter()
+                .fold(ValueSet::default(), |unread, s| unread.union(s.unread)),
+        }
+    }
+}
+
+/// Resolve the statuses of the batteries into one, given whether any adapter is online
+/// (`None` when the system has no adapter to ask):
+///
+/// - Without batteries, as on a desktop, the status is unknown.
+/// - On AC, all batteries full is full, any battery charging is charging, and anything
+///   else is not charging.
+/// - Off AC, the system is discharging.
+/// - Without an adapter, the batteries are trusted: any discharging is discharging, then
+///   any charging is charging, then all full is full, and anything else is unknown.
+pub fn resolve_status(on_ac: Option<bool>, statuses: &[ChargingStatus]) -> ChargingStatus {
+    let any = |status| statuses.contains(&status);
+    let all_full = statuses.iter().all(|&s| s == ChargingStatus::Full);
+
+    match on_ac {
+        _ if statuses.is_empty() => ChargingStatus::Unknown,
+        Some(true) if all_full => ChargingStatus::Full,
+        Some(true) if any(ChargingStatus::Charging) => ChargingStatus::Charging,
+        Some(true) => ChargingStatus::NotCharging,
+        Some(false) => ChargingStatus::Discharging,
+        None if any(ChargingStatus::Discharging) => ChargingStatus::Discharging,
+        None if any(ChargingStatus::Charging) => ChargingStatus::Charging,
+        None if all_full => ChargingStatus::Full,
+        None => ChargingStatus::Unknown,
+    }
+}
@@ -0,0 +1,138 @@
+//! Switching power-profiles-daemon to power-saver while the battery is low.
+
+use crate::Result;
+use batmon::{BatteryState, BatteryValue, ChargingStatus};
+use std::process::{Command, Stdio};
+
+const POWER_SAVER: &str = "power-saver";
+
+/// Where the power profile is read and set, so switching can be driven without a bus
+pub trait ProfileService {
+    fn active(&mut self) -> Result<String>;
+    fn set_active(&mut self, profile: &str) -> Result<()>;
+}
+
+/// power-profiles-daemon on the system bus, reached through busctl
+pub struct PowerProfiles;
+
+const ACTIVE_PROFILE: [&str; 4] = [
+    "net.hadess.PowerProfiles",
+    "/net/hadess/PowerProfiles",
+    "net.hadess.PowerProfiles",
+    "ActiveProfile",
+];
+
+fn busctl(args: &[&str]) -> Result<String> {
+    let output = Command::new("busctl")
+        .arg("--system")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("busctl failed: {}", stderr.trim()).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+impl ProfileService for PowerProfiles {
+    fn active(&mut self) -> Result<String> {
+        let mut args = vec!["get-property"];
+        args.extend(ACTIVE_PROFILE);
+        // Printed as `s "balanced"`
+        let output = busctl(&args)?;
+        let profile = output
+            .trim()
+            .strip_prefix("s ")
+            .ok_or_else(|| format!("unexpected ActiveProfile '{}'", output.trim()))?;
+        Ok(profile.trim_matches('"').to_owned())
+    }
+
+    fn set_active(&mut self, profile: &str) -> Result<()> {
+        let mut args = vec!["set-property"];
+        args.extend(ACTIVE_PROFILE);
+        args.extend(["s", profile]);
+        busctl(&args).map(|_| ())
+    }
+}
+
+/// Switches to power-saver once the level drops to a threshold while discharging, and back to
+/// the previous profile once the battery stops discharging. A profile is only restored if
+/// batmon switched away from it and nobody has changed the profile since.
+pub struct ProfileSwitcher {
+    service: Box<dyn ProfileService>,
+    threshold: u8,
+    /// The profile to restore, while batmon has switched to power-saver
+    previous: Option<String>,
+    /// Whether the low level has been acted on in this discharge, so it is only acted on once
+    acted: bool,
+    /// Whether a failure to reach the service has been warned about, so it is only warned once
+    warned: bool,
+}
+
+impl ProfileSwitcher {
+    pub fn new(service: Box<dyn ProfileService>, threshold: u8) -> Self {
+        Self {
+            service,
+            threshold,
+            previous: None,
+            acted: false,
+            warned: false,
+        }
+    }
+
+    pub fn update(&mut self, state: &BatteryState) {
+        let res = match state.status {
+            ChargingStatus::Discharging => {
+                let low = state.is_field_valid(BatteryValue::Level) && state.is_low(self.threshold);
+                if !low || self.acted {
+                    return;
+                }
+                self.acted = true;
+                self.switch()
+            }
+            ChargingStatus::Unknown => return,
+            _ => {
+                self.acted = false;
+                self.restore()
+            }
+        };
+
+        if let Err(e) = res {
+            if self.warned {
+                debug!("Failed to reach power-profiles-daemon: {e}");
+            } else {
+                warn!("Failed to reach power-profiles-daemon: {e}");
+                self.warned = true;
+            }
+        }
+    }
+
+    fn switch(&mut self) -> Result<()> {
+        let active = self.service.active()?;
+        if active == POWER_SAVER {
+            debug!("Power profile is already {POWER_SAVER}");
+            return Ok(());
+        }
+        self.service.set_active(POWER_SAVER)?;
+        info!("Switched power profile from {active} to {POWER_SAVER}");
+        self.previous = Some(active);
+        Ok(())
+    }
+
+    fn restore(&mut self) -> Result<()> {
+        // Kept until the service is reached, so a failure is retried on the next update
+        let Some(previous) = self.previous.clone() else {
+            return Ok(());
+        };
+        let active = self.service.active()?;
+        if active == POWER_SAVER {
+            self.service.set_active(&previous)?;
+            info!("Restored power profile {previous}");
+        } else {
+            info!("Not restoring power profile {previous}, as it was changed to {active}");
+        }
+        self.previous = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use std::path::{Path, PathBuf};
+
+/// Parse a size in bytes, optionally with a K, M or G (binary) suffix
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (number, multiplier) = match s.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&s[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&s[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    let number: u64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("'{s}' is not a size (e.g. 512K, 50M or 1G)"))?;
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("'{s}' is too large"))
+}
+
+fn generation(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
+
+/// Rotate `path` to `<path>.1` if it is larger than `max_size`, shifting older generations up
+/// and keeping at most `keep` of them. Returns whether the file was rotated.
+///
+/// Only renames are used, so a crash at any point leaves every line in one of the files.
+/// Writers holding the file open keep writing to the rotated generation until they reopen it.
+pub fn rotate_if_larger(path: &Path, max_size: u64, keep: usize) -> std::io::Result<bool> {
+    match std::fs::metadata(path) {
+        Ok(meta) if meta.len() > max_size => {}
+        _ => return Ok(false),
+    }
+
+    if keep == 0 {
+        std::fs::remove_file(path)?;
+        return Ok(true);
+    }
+
+    for n in (1..keep).rev() {
+        let from = generation(path, n);
+        if from.exists() {
+            std::fs::rename(from, generation(path, n + 1))?;
+        }
+    }
+    std::fs::rename(path, generation(path, 1))?;
+    Ok(true)
+}
@@ -0,0 +1,170 @@
+use crate::Result;
+use batmon::{BatmonError, Battery, Envelope};
+use serde::Serialize;
+use std::path::Path;
+
+/// The outcome of one check. Only failed required checks fail the self-test,
+/// the rest are reported as warnings.
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    pub required: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(
+        name: impl Into<String>,
+        required: bool,
+        res: std::result::Result<String, String>,
+    ) -> Self {
+        let (passed, detail) = match res {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, detail),
+        };
+        Self {
+            name: name.into(),
+            passed,
+            required,
+            detail,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match (self.passed, self.required) {
+            (true, _) => "PASS",
+            (false, true) => "FAIL",
+            (false, false) => "WARN",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    passed: bool,
+    checks: &'a [Check],
+}
+
+/// Check everything the daemon relies on, printing each result.
+/// Fails if any required check failed, after every check has run.
+pub fn run(found: Result<Battery>, cache_file: Option<&Path>, json: bool) -> Result<()> {
+    let mut checks = Vec::new();
+
+    match found {
+        Ok(mut bat) => {
+            checks.push(Check::new("battery", true, Ok(bat.name.clone())));
+            check_battery(&mut bat, &mut checks);
+        }
+        Err(e) => checks.push(Check::new("battery", true, Err(e.to_string()))),
+    }
+
+    checks.push(Check::new("notification", true, send_test_notification()));
+    let runtime_dir = crate::paths::runtime_dir();
+    checks.push(Check::new(
+        "runtime dir",
+        true,
+        check_writable(&runtime_dir),
+    ));
+    if let Some(dir) = cache_file.and_then(Path::parent) {
+        checks.push(Check::new("cache dir", false, check_writable(dir)));
+    }
+
+    let passed = checks.iter().all(|c| c.passed || !c.required);
+    if json {
+        let report = Report {
+            passed,
+            checks: &checks,
+        };
+        println!("{}", serde_json::to_string(&Envelope::new(&report))?);
+    } else {
+        for check in &checks {
+            println!("{} {}: {}", check.label(), check.name, check.detail);
+        }
+    }
+
+    if !passed {
+        let failed: Vec<_> = checks
+            .iter()
+            .filter(|c| !c.passed && c.required)
+            .map(|c| c.name.as_str())
+            .collect();
+        return Err(format!("Self-test failed: {}", failed.join(", ")).into());
+    }
+    Ok(())
+}
+
+fn check_battery(bat: &mut Battery, checks: &mut Vec<Check>) {
+    let (capacity, charge, current) = bat.family().attributes();
+    let design = bat.design_attribute();
+    for attribute in [
+        "status",
+        "capacity",
+        capacity,
+        charge,
+        current,
+        "cycle_count",
+        "voltage_now",
+        design,
+    ] {
+        let res = match bat.read_raw(attribute) {
+            Ok(data) => Ok(String::from_utf8_lossy(&data).trim().to_owned()),
+            Err(e) => match e.downcast_ref::<BatmonError>() {
+                Some(BatmonError::Unsupported { .. }) => Err(String::from("not provided")),
+                _ => Err(e.to_string()),
+            },
+        };
+        checks.push(Check::new(format!("attribute {attribute}"), false, res));
+    }
+
+    let failed = bat.update();
+    let level = if !failed.is_empty() {
+        Err(format!("failed to read {}", failed.join(", ")))
+    } else if bat.level_known() {
+        Ok(format!("{}%, {}", bat.state().level, bat.state().status))
+    } else {
+        Err(String::from("the device reports no level"))
+    };
+    checks.push(Check::new("readings", true, level));
+
+    let denied: Vec<_> = bat
+        .denied()
+        .iter()
+        .map(|p| batmon::denied_hint(p))
+        .collect();
+    let permissions = match denied.is_empty() {
+        true => Ok(String::from("every attribute is readable")),
+        false => Err(denied.join("\n  ")),
+    };
+    checks.push(Check::new("permissions", false, permissions));
+
+    let remaining = match bat.remaining_duration() {
+        Some(_) => Ok(bat.remaining()),
+        None => Err(String::from(
+            "no estimate while the battery is idle or has no draw",
+        )),
+    };
+    checks.push(Check::new("estimate", false, remaining));
+}
+
+/// Show a low urgency notification and close it straight away
+fn send_test_notification() -> std::result::Result<String, String> {
+    if !libnotify::is_initted() {
+        libnotify::init(crate::APP_NAME).map_err(|e| e.to_string())?;
+    }
+    let n = libnotify::Notification::new("batmon self-test", None, None);
+    n.set_urgency(libnotify::Urgency::Low);
+    n.show().map_err(|e| e.to_string())?;
+    n.close().map_err(|e| e.to_string())?;
+    Ok(String::from("sent and closed"))
+}
+
+/// Whether a file can be created in `dir`, creating the directory if it is missing
+fn check_writable(dir: &Path) -> std::result::Result<String, String> {
+    let probe = dir.join(format!(".batmon-selftest-{}", std::process::id()));
+    std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&probe, b""))
+        .and_then(|_| std::fs::remove_file(&probe))
+        .map_err(|e| format!("{}: {e}", dir.display()))?;
+    Ok(dir.display().to_string())
+}
@@ -0,0 +1,79 @@
+use crate::Result;
+use std::path::Path;
+
+/// Flags that only control installing the service or don't make sense under systemd,
+/// and so are left out of its command line
+const SERVICE_FLAGS: [&str; 4] = [
+    "--install-service",
+    "--print-service",
+    "--force",
+    "--daemonize",
+];
+
+/// Quote an argument for an ExecStart line
+fn quote(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+
+    if escaped.is_empty() || escaped.contains(char::is_whitespace) || escaped != arg {
+        format!("\"{escaped}\"")
+    } else {
+        escaped
+    }
+}
+
+/// Render a systemd user unit running `exe` with `args`
+pub fn render_unit(exe: &Path, args: &[String]) -> String {
+    let mut exec = quote(&exe.to_string_lossy());
+    for arg in args.iter().filter(|a| !SERVICE_FLAGS.contains(&a.as_str())) {
+        exec.push(' ');
+        exec.push_str(&quote(arg));
+    }
+
+    format!(
+        "[Unit]
+Description=Battery monitoring notification daemon
+PartOf=graphical-session.target
+After=graphical-session.target
+
+[Service]
+Type=simple
+ExecStart={exec}
+Restart=on-failure
+
+[Install]
+WantedBy=graphical-session.target
+"
+    )
+}
+
+/// The unit for the running invocation of batmon
+pub fn current_unit() -> Result<String> {
+    let exe = std::env::current_exe()?;
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    Ok(render_unit(&exe, &args))
+}
+
+pub fn install(force: bool) -> Result<()> {
+    let dir =
+        crate::paths::systemd_user_dir().ok_or("Failed to locate the systemd user directory")?;
+    let path = dir.join("batmon.service");
+
+    if path.exists() && !force {
+        return Err(format!(
+            "{} already exists, use --force to overwrite it",
+            path.to_string_lossy()
+        )
+        .into());
+    }
+
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(&path, current_unit()?)?;
+
+    println!("Installed {}", path.to_string_lossy());
+    println!("Enable it with: systemctl --user enable --now batmon");
+    Ok(())
+}
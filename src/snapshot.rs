@@ -0,0 +1,68 @@
+use crate::battery::{Battery, BatteryState};
+use crate::error::BatmonError;
+use crate::units::UnitFamily;
+use serde::Serialize;
+use std::time::Duration;
+
+/// The version of the JSON output format.
+///
+/// Adding fields is not a breaking change and keeps the version, so consumers should ignore
+/// fields they don't know. Removing, renaming or changing the meaning of a field bumps it.
+pub const OUTPUT_VERSION: u32 = 1;
+
+/// Wraps JSON output with the version of its format, as `{"version": 1, "battery": {...}}`
+#[derive(Debug, Clone, Serialize)]
+pub struct Envelope<T> {
+    pub version: u32,
+    pub battery: T,
+}
+
+impl<T: Serialize> Envelope<T> {
+    pub fn new(battery: T) -> Self {
+        Self {
+            version: OUTPUT_VERSION,
+            battery,
+        }
+    }
+}
+
+/// Everything known about a battery at one point in time
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    pub name: String,
+    pub state: BatteryState,
+    /// The estimated time until the battery is charged or discharged,
+    /// `None` if there is no current draw to estimate it from.
+    /// Serialized as whole `remaining_seconds`: 0 when full and null when unknown.
+    #[serde(rename = "remaining_seconds", serialize_with = "serialize_seconds")]
+    pub remaining: Option<Duration>,
+    /// The full capacity as a percentage of the design capacity,
+    /// `None` if the device doesn't report its design capacity
+    pub health: Option<f64>,
+    pub family: UnitFamily,
+}
+
+impl Battery {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            name: self.name.clone(),
+            state: self.state(),
+            remaining: self.remaining_duration(),
+            health: self.health(),
+            family: self.family(),
+        }
+    }
+}
+
+fn serialize_seconds<S: serde::Serializer>(
+    remaining: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    remaining.map(|r| r.as_secs()).serialize(serializer)
+}
+
+/// Find the system battery and take a snapshot of it
+pub fn snapshot() -> Result<Snapshot, BatmonError> {
+    let bat = Battery::find(false).ok_or(BatmonError::NoBattery)?;
+    Ok(bat.snapshot())
+}
@@ -0,0 +1,109 @@
+//! Snoozing non-emergency notifications for a while, with `batmon snooze`.
+//!
+//! The deadline is kept in a file in the state directory, which the daemon reads on every
+//! update, so a snooze reaches a running daemon and survives it being restarted.
+
+use crate::Result;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What `batmon snooze` was asked to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    For(Duration),
+    Off,
+    Status,
+}
+
+impl FromStr for Request {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Request::Off),
+            "status" => Ok(Request::Status),
+            _ => humantime::parse_duration(s)
+                .map(Request::For)
+                .map_err(|e| format!("{e} (expected a duration like 30m, off or status)")),
+        }
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    Some(crate::paths::state_dir()?.join("snooze"))
+}
+
+/// The end of the current snooze, if notifications are snoozed at `now`
+pub fn until(now: SystemTime) -> Option<SystemTime> {
+    let secs = std::fs::read_to_string(path()?).ok()?.trim().parse().ok()?;
+    let until = UNIX_EPOCH.checked_add(Duration::from_secs(secs))?;
+    (until > now).then_some(until)
+}
+
+pub fn run(request: Request) -> Result<()> {
+    let path = path().ok_or("Failed to locate the state directory")?;
+    let now = SystemTime::now();
+    match request {
+        Request::For(duration) => {
+            let until = now
+                .checked_add(duration)
+                .ok_or("The snooze is too long")?
+                .duration_since(UNIX_EPOCH)?
+                .as_secs();
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(&path, until.to_string())?;
+            println!(
+                "Snoozed notifications for {}",
+                humantime::format_duration(duration)
+            );
+        }
+        Request::Off => {
+            match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e)?,
+                _ => {}
+            }
+            println!("Notifications are no longer snoozed");
+        }
+        Request::Status => match until(now) {
+            Some(until) => {
+                let left = until.duration_since(now).unwrap_or_default();
+                println!(
+                    "Snoozed for another {}",
+                    humantime::format_duration(Duration::from_secs(left.as_secs()))
+                );
+            }
+            None => println!("Notifications are not snoozed"),
+        },
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_request() {
+        assert_eq!("off".parse(), Ok(Request::Off));
+        assert_eq!("status".parse(), Ok(Request::Status));
+        assert_eq!(
+            "30m".parse(),
+            Ok(Request::For(Duration::from_secs(30 * 60)))
+        );
+        assert_eq!(
+            "1h 15m".parse(),
+            Ok(Request::For(Duration::from_secs(75 * 60)))
+        );
+
+        let err = "later".parse::<Request>().unwrap_err();
+        assert!(
+            err.contains("expected a duration like 30m, off or status"),
+            "{err}"
+        );
+        assert!("".parse::<Request>().is_err());
+        assert!("Off".parse::<Request>().is_err());
+    }
+}
@@ -0,0 +1,254 @@
+use crate::battery::{Battery, BatteryState};
+use crate::format::TimeFormat;
+use crate::status::ChargingStatus;
+use std::time::Duration;
+
+/// What an update failed to read
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdateReport {
+    /// The names of the values that couldn't be read, like `charge`
+    pub failed: Vec<&'static str>,
+}
+
+/// Anything that can be monitored like a battery. `Battery` reads sysfs, and
+/// `MockPowerSource` (with the `test-util` feature) plays back scripted states.
+pub trait PowerSource {
+    /// Re-read the source
+    fn update(&mut self) -> UpdateReport;
+
+    /// The values as of the last update
+    fn state(&self) -> BatteryState;
+
+    fn name(&self) -> &str;
+
+    /// Whether the level has been read, rather than being a placeholder
+    fn level_known(&self) -> bool {
+        true
+    }
+
+    /// Whether the source reports a numeric level, or enough to derive one
+    fn reports_level(&self) -> bool {
+        true
+    }
+
+    /// The level, never rescaled for display
+    fn raw_level(&self) -> u8 {
+        self.state().level
+    }
+
+    /// The estimated time until empty or full, by default at the current draw
+    fn remaining_duration(&self) -> Option<Duration> {
+        let state = self.state();
+        state.remaining_at_draw(state.capacity)
+    }
+
+    /// The remaining time, or Unknown if it can't be estimated
+    fn remaining(&self) -> String {
+        match self.remaining_duration() {
+            Some(remaining) => TimeFormat::default().format(remaining),
+            None => String::from("Unknown"),
+        }
+    }
+
+    /// The remaining time with what it is the time until, or the status if it isn't changing
+    fn remaining_labelled(&self) -> String {
+        label_remaining(
+            self.state().status,
+            false,
+            self.remaining_duration(),
+            TimeFormat::default(),
+        )
+    }
+}
+
+/// The `remaining` time written with what it is the time until, or the status if it isn't
+/// changing. `held` tells a battery held back by a charge threshold apart from one that just
+/// isn't charging.
+pub(crate) fn label_remaining(
+    status: ChargingStatus,
+    held: bool,
+    remaining: Option<Duration>,
+    format: TimeFormat,
+) -> String {
+    let label = match status {
+        ChargingStatus::Full => return String::from("Full"),
+        ChargingStatus::NotCharging if held => return String::from("held (threshold)"),
+        ChargingStatus::NotCharging => return String::from("Not charging"),
+        ChargingStatus::Unknown => return String::from("Unknown"),
+        ChargingStatus::Charging => "until full",
+        ChargingStatus::Discharging => "remaining",
+    };
+    match remaining {
+        Some(remaining) => format!("{} {label}", format.format(remaining)),
+        None => String::from("Unknown"),
+    }
+}
+
+impl PowerSource for Battery {
+    fn update(&mut self) -> UpdateReport {
+        UpdateReport {
+            failed: Battery::update(self),
+        }
+    }
+
+    fn state(&self) -> BatteryState {
+        Battery::state(self)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn level_known(&self) -> bool {
+        Battery::level_known(self)
+    }
+
+    fn reports_level(&self) -> bool {
+        Battery::reports_level(self)
+    }
+
+    fn raw_level(&self) -> u8 {
+        Battery::raw_level(self)
+    }
+
+    fn remaining_duration(&self) -> Option<Duration> {
+        Battery::remaining_duration(self)
+    }
+
+    fn remaining(&self) -> String {
+        Battery::remaining(self)
+    }
+
+    fn remaining_labelled(&self) -> String {
+        Battery::remaining_labelled(self)
+    }
+}
+
+/// A FreeBSD battery unit, whose remaining time is the firmware's estimate
+#[cfg(feature = "freebsd")]
+impl PowerSource for crate::freebsd::Battery {
+    fn update(&mut self) -> UpdateReport {
+        crate::freebsd::Battery::update(self);
+        UpdateReport::default()
+    }
+
+    fn state(&self) -> BatteryState {
+        crate::freebsd::Battery::state(self)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn level_known(&self) -> bool {
+        self.state()
+            .is_field_valid(crate::battery::BatteryValue::Level)
+    }
+
+    fn remaining_duration(&self) -> Option<Duration> {
+        crate::freebsd::Battery::remaining_duration(self)
+    }
+
+    fn remaining(&self) -> String {
+        crate::freebsd::Battery::remaining(self)
+    }
+}
+
+/// A power source that plays back states instead of reading a device, for testing code
+/// built on `PowerSource`. Like a battery, it takes its first state when it is created.
+#[cfg(feature = "test-util")]
+pub struct MockPowerSource {
+    name: String,
+    next: Box<dyn FnMut(u64) -> BatteryState>,
+    state: BatteryState,
+    updates: u64,
+    reports_level: bool,
+}
+
+#[cfg(feature = "test-util")]
+impl MockPowerSource {
+    /// Take each state of `states` in turn, one per update, then stay at the last one
+    pub fn scripted(name: impl Into<String>, states: Vec<BatteryState>) -> Self {
+        let last = states.len().saturating_sub(1);
+        Self::from_fn(name, move |n| {
+            let i = usize::try_from(n).unwrap_or(usize::MAX).min(last);
+            states.get(i).cloned().unwrap_or_default()
+        })
+    }
+
+    /// Take the state `next` returns for the number of updates so far
+    pub fn from_fn(
+        name: impl Into<String>,
+        mut next: impl FnMut(u64) -> BatteryState + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            state: next(0),
+            next: Box::new(next),
+            updates: 0,
+            reports_level: true,
+        }
+    }
+
+    /// Act like a device with only a coarse capacity level, see `PowerSource::reports_level`
+    pub fn without_numeric_level(mut self) -> Self {
+        self.reports_level = false;
+        self
+    }
+
+    /// The number of times the source has been updated
+    pub fn updates(&self) -> u64 {
+        self.updates
+    }
+}
+
+#[cfg(feature = "test-util")]
+impl PowerSource for MockPowerSource {
+    fn update(&mut self) -> UpdateReport {
+        self.updates += 1;
+        self.state = (self.next)(self.updates);
+        UpdateReport::default()
+    }
+
+    fn state(&self) -> BatteryState {
+        self.state.clone()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn reports_level(&self) -> bool {
+        self.reports_level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labelled_remaining_time() {
+        let hour = Some(Duration::from_secs(60 * 60));
+        for (status, held, remaining, expected) in [
+            (
+                ChargingStatus::Discharging,
+                false,
+                hour,
+                "01:00:00 remaining",
+            ),
+            (ChargingStatus::Charging, false, hour, "01:00:00 until full"),
+            (ChargingStatus::Discharging, false, None, "Unknown"),
+            (ChargingStatus::Full, false, hour, "Full"),
+            (ChargingStatus::NotCharging, false, hour, "Not charging"),
+            (ChargingStatus::NotCharging, true, hour, "held (threshold)"),
+            (ChargingStatus::Unknown, false, hour, "Unknown"),
+        ] {
+            assert_eq!(
+                label_remaining(status, held, remaining, TimeFormat::default()),
+                expected,
+                "{status:?}"
+            );
+        }
+    }
+}
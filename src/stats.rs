@@ -0,0 +1,294 @@
+//! Statistics over the battery states recorded by the daemon, for `batmon stats`.
+
+use crate::history::History;
+use crate::Result;
+use batmon::{BatteryState, BatteryValue, ChargingStatus, Envelope};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+/// Samples further apart than this are a gap (the daemon wasn't running, or the system was
+/// suspended) rather than time spent in the state of the earlier one
+const MAX_GAP: Duration = Duration::from_secs(10 * 60);
+/// Discharges shorter than this are too short to take a worst-case rate from
+const MIN_DISCHARGE: Duration = Duration::from_secs(10 * 60);
+
+/// Whether a sample was taken on battery or on AC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Power {
+    Battery,
+    Ac,
+}
+
+impl Power {
+    /// `None` for an unknown status, which is taken to continue the session it is in
+    fn of(state: &BatteryState) -> Option<Self> {
+        match state.status {
+            ChargingStatus::Discharging => Some(Power::Battery),
+            ChargingStatus::Unknown => None,
+            _ => Some(Power::Ac),
+        }
+    }
+}
+
+/// A run of consecutive samples on the same power without a gap, as indices into the samples
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Session {
+    power: Power,
+    start: usize,
+    end: usize,
+}
+
+/// Group `samples`, which must be in time order, into sessions on battery and on AC.
+/// A session ends when the power changes or at a gap; unknown statuses join the session
+/// they are in, and samples before the first known status are left out.
+fn sessions(samples: &[(SystemTime, BatteryState)]) -> Vec<Session> {
+    let mut sessions: Vec<Session> = Vec::new();
+    for (i, (_, state)) in samples.iter().enumerate() {
+        let gap = i == 0 || is_gap(samples, i - 1);
+        let current = sessions.last_mut().filter(|s| !gap && s.end == i - 1);
+        match (current, Power::of(state)) {
+            (Some(session), None) => session.end = i,
+            (Some(session), Some(power)) if power == session.power => session.end = i,
+            (_, Some(power)) => sessions.push(Session {
+                power,
+                start: i,
+                end: i,
+            }),
+            (None, None) => {}
+        }
+    }
+    sessions
+}
+
+/// Whether there is a gap between sample `i` and the next one
+fn is_gap(samples: &[(SystemTime, BatteryState)], i: usize) -> bool {
+    let elapsed = samples[i + 1].0.duration_since(samples[i].0);
+    elapsed.map_or(true, |elapsed| elapsed > MAX_GAP)
+}
+
+/// Aggregate statistics over a period of recorded history
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Stats {
+    #[serde(rename = "on_battery_seconds", serialize_with = "seconds")]
+    pub on_battery: Duration,
+    #[serde(rename = "on_ac_seconds", serialize_with = "seconds")]
+    pub on_ac: Duration,
+    /// The number of times the battery started charging
+    pub charges_started: usize,
+    /// The discharge rate in percentage points per hour, over all discharging and at worst
+    pub average_rate: Option<f64>,
+    pub worst_rate: Option<f64>,
+    /// The discharging draw in W, where the energy is known
+    pub average_w: Option<f64>,
+    pub worst_w: Option<f64>,
+    /// The lowest level reached while discharging
+    pub deepest_level: Option<u8>,
+    /// The level averaged over time
+    pub average_level: Option<f64>,
+    /// The time on battery for each day (UTC), as `YYYY-MM-DD`
+    #[serde(rename = "on_battery_per_day_seconds", serialize_with = "seconds_map")]
+    pub on_battery_per_day: BTreeMap<String, Duration>,
+}
+
+fn seconds<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_secs())
+}
+
+fn seconds_map<S: serde::Serializer>(
+    map: &BTreeMap<String, Duration>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_map(map.iter().map(|(day, d)| (day, d.as_secs())))
+}
+
+fn day(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string()[..10].to_owned()
+}
+
+impl Stats {
+    /// Compute the statistics over `samples`, which must be in time order
+    pub fn compute(samples: &[(SystemTime, BatteryState)]) -> Self {
+        let mut stats = Stats::default();
+        let mut level_time = 0.0;
+        let mut timed = Duration::ZERO;
+        // Totals over every discharge, for the averages
+        let (mut lost, mut hours) = (0.0, 0.0);
+        let (mut used_wh, mut energy_hours) = (0.0, 0.0);
+
+        for session in sessions(samples) {
+            let run = &samples[session.start..=session.end];
+            // The time until the next session starts is spent in this one
+            let timed_end = match session.end + 1 < samples.len() && !is_gap(samples, session.end) {
+                true => session.end + 1,
+                false => session.end,
+            };
+            for pair in samples[session.start..=timed_end].windows(2) {
+                let ((start, state), (end, _)) = (&pair[0], &pair[1]);
+                let elapsed = end.duration_since(*start).unwrap_or_default();
+                match session.power {
+                    Power::Battery => {
+                        stats.on_battery += elapsed;
+                        *stats.on_battery_per_day.entry(day(*start)).or_default() += elapsed;
+                    }
+                    Power::Ac => stats.on_ac += elapsed,
+                }
+                if state.is_field_valid(BatteryValue::Level) {
+                    level_time += f64::from(state.level) * elapsed.as_secs_f64();
+                    timed += elapsed;
+                }
+            }
+
+            if session.power != Power::Battery {
+                continue;
+            }
+            let levels = run
+                .iter()
+                .filter(|(_, s)| s.is_field_valid(BatteryValue::Level) && s.is_discharging());
+            if let Some(deepest) = levels.map(|(_, s)| s.level).min() {
+                stats.deepest_level = Some(stats.deepest_level.map_or(deepest, |d| d.min(deepest)));
+            }
+
+            let ((start, first), (end, last)) = (&run[0], &run[run.len() - 1]);
+            let duration = end.duration_since(*start).unwrap_or_default();
+            if duration.is_zero() {
+                continue;
+            }
+            let session_hours = duration.as_secs_f64() / 3600.0;
+            let session_lost = f64::from(first.level.saturating_sub(last.level));
+            lost += session_lost;
+            hours += session_hours;
+            let energy = first
+                .energy_now_wh()
+                .zip(last.energy_now_wh())
+                .map(|(first, last)| (first - last).max(0.0));
+            if let Some(used) = energy {
+                used_wh += used;
+                energy_hours += session_hours;
+            }
+
+            if duration >= MIN_DISCHARGE {
+                let max =
+                    |worst: Option<f64>, value: f64| Some(worst.map_or(value, |w| w.max(value)));
+                stats.worst_rate = max(stats.worst_rate, session_lost / session_hours);
+                if let Some(used) = energy {
+                    stats.worst_w = max(stats.worst_w, used / session_hours);
+                }
+            }
+        }
+
+        stats.charges_started = samples
+            .windows(2)
+            .filter(|pair| !pair[0].1.is_charging() && pair[1].1.is_charging())
+            .count();
+        stats.average_rate = (hours > 0.0).then(|| lost / hours);
+        stats.average_w = (energy_hours > 0.0).then(|| used_wh / energy_hours);
+        stats.average_level = (!timed.is_zero()).then(|| level_time / timed.as_secs_f64());
+        stats
+    }
+}
+
+/// The duration to the minute, as `6h 12m`
+fn format_duration(duration: Duration) -> String {
+    match duration.as_secs() / 60 {
+        0 => String::from("0m"),
+        minutes => humantime::format_duration(Duration::from_secs(minutes * 60)).to_string(),
+    }
+}
+
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let na = || String::from("N/A");
+        let fmt = |v: Option<f64>, unit: &str| v.map_or_else(na, |v| format!("{v:.1} {unit}"));
+
+        writeln!(f, "On battery: {}", format_duration(self.on_battery))?;
+        writeln!(f, "On AC: {}", format_duration(self.on_ac))?;
+        writeln!(f, "Charges started: {}", self.charges_started)?;
+        writeln!(
+            f,
+            "Discharge rate: {} average, {} worst",
+            fmt(self.average_rate, "%/h"),
+            fmt(self.worst_rate, "%/h")
+        )?;
+        writeln!(
+            f,
+            "Discharge power: {} average, {} worst",
+            fmt(self.average_w, "W"),
+            fmt(self.worst_w, "W")
+        )?;
+        writeln!(
+            f,
+            "Deepest discharge: {}",
+            self.deepest_level.map_or_else(na, |l| format!("{l}%"))
+        )?;
+        write!(
+            f,
+            "Average level: {}",
+            self.average_level.map_or_else(na, |l| format!("{l:.0}%"))
+        )?;
+        if !self.on_battery_per_day.is_empty() {
+            write!(f, "\nOn battery per day:")?;
+            for (day, duration) in &self.on_battery_per_day {
+                write!(f, "\n  {day}  {}", format_duration(*duration))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How far back the history reaches when it starts after `cutoff`, so it covers less than
+/// was asked for
+fn shortfall(oldest: SystemTime, cutoff: SystemTime, now: SystemTime) -> Option<Duration> {
+    (oldest > cutoff).then(|| now.duration_since(oldest).unwrap_or_default())
+}
+
+/// Print the statistics over the recorded history, or only the last `since` of it
+pub fn run(since: Option<Duration>, json: bool) -> Result<()> {
+    let path = History::default_path().ok_or("Failed to locate the history file")?;
+    let history = History::load(path, usize::MAX);
+    let now = SystemTime::now();
+    let cutoff = since.and_then(|since| now.checked_sub(since));
+    let oldest = history.records().next().map(|r| r.time());
+    if let (Some(since), Some(cutoff), Some(oldest)) = (since, cutoff, oldest) {
+        if let Some(covered) = shortfall(oldest, cutoff, now) {
+            warn!(
+                "The recorded history only covers the last {} rather than {}",
+                format_duration(covered),
+                humantime::format_duration(since)
+            );
+        }
+    }
+    let samples: Vec<_> = history
+        .records()
+        .filter(|r| cutoff.is_none_or(|cutoff| r.time() >= cutoff))
+        .map(|r| (r.time(), r.state.clone()))
+        .collect();
+    if samples.len() < 2 {
+        return Err("Not enough history recorded for statistics".into());
+    }
+
+    let stats = Stats::compute(&samples);
+    if json {
+        println!("{}", serde_json::to_string(&Envelope::new(&stats))?);
+    } else {
+        println!("{stats}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortfall_when_the_history_starts_after_the_cutoff() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(30 * 24 * 3600);
+        let hour = Duration::from_secs(3600);
+        let week_ago = now - 7 * 24 * hour;
+        assert_eq!(shortfall(now - hour, week_ago, now), Some(hour));
+        assert_eq!(shortfall(now - 8 * 24 * hour, week_ago, now), None);
+    }
+}
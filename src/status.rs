@@ -1,11 +1,14 @@
+use serde::{Deserialize, Serialize};
 use strum::Display;
 
-#[derive(Debug, Clone, Copy, Display, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Display, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ChargingStatus {
     Charging,
     Discharging,
     Full,
     NotCharging,
+    /// The device doesn't know, or the status was read while it was being updated
+    Unknown,
 }
 
 impl ChargingStatus {
@@ -29,7 +32,32 @@ impl std::str::FromStr for ChargingStatus {
             "Discharging" => Ok(Self::Discharging),
             "Full" => Ok(Self::Full),
             "Not charging" => Ok(Self::NotCharging),
+            // Transiently empty while the device is being hotplugged
+            "Unknown" | "" => Ok(Self::Unknown),
             _ => Err(StatusParseError),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_status() {
+        for (s, status) in [
+            ("Charging", ChargingStatus::Charging),
+            ("Discharging", ChargingStatus::Discharging),
+            ("Full", ChargingStatus::Full),
+            ("Not charging", ChargingStatus::NotCharging),
+            ("Unknown", ChargingStatus::Unknown),
+            // Read while the device is being hotplugged
+            ("", ChargingStatus::Unknown),
+        ] {
+            assert_eq!(s.parse::<ChargingStatus>(), Ok(status), "{s:?}");
+        }
+        for s in ["charging", "Not Charging", " ", "Full\n"] {
+            assert_eq!(s.parse::<ChargingStatus>(), Err(StatusParseError), "{s:?}");
+        }
+    }
+}
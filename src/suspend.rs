@@ -0,0 +1,100 @@
+//! Measuring how much the battery drains while the system is suspended.
+//!
+//! A suspend shows up as a gap in the wall clock between two samples, longer than the time
+//! between samples can explain.
+
+use batmon::{BatteryState, BatteryValue};
+use std::time::{Duration, SystemTime};
+
+/// How much longer than expected a gap between samples must be to be taken for a suspend,
+/// so a slow read or a busy system isn't mistaken for one
+pub const SLACK: Duration = Duration::from_secs(60);
+
+/// The battery drained over one suspend
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuspendDrain {
+    /// When the last sample before the suspend was taken
+    pub start: SystemTime,
+    pub duration: Duration,
+    /// The percentage points lost
+    pub lost: u8,
+    /// The average power drawn in W, where the energy is known before and after
+    pub average_w: Option<f64>,
+}
+
+impl SuspendDrain {
+    /// The drain between the last sample before a suspend and the first one after it.
+    /// `None` unless the battery was discharging before and hasn't gained charge since,
+    /// as when it was plugged in while suspended.
+    pub fn between(
+        before: (SystemTime, &BatteryState),
+        after: (SystemTime, &BatteryState),
+    ) -> Option<Self> {
+        let (start, before) = before;
+        let (end, after) = after;
+        let duration = end.duration_since(start).ok().filter(|d| !d.is_zero())?;
+        let levels_read = [before, after]
+            .iter()
+            .all(|s| s.is_field_valid(BatteryValue::Level));
+        if !before.is_discharging() || !levels_read || after.level > before.level {
+            return None;
+        }
+
+        let hours = duration.as_secs_f64() / 3600.0;
+        let average_w = match (before.energy_now_wh(), after.energy_now_wh()) {
+            (Some(before), Some(after)) if before >= after => Some((before - after) / hours),
+            _ => None,
+        };
+        Some(Self {
+            start,
+            duration,
+            lost: before.level - after.level,
+            average_w,
+        })
+    }
+
+    /// The duration to the minute, as `6h 12m`
+    fn rounded_duration(&self) -> Duration {
+        Duration::from_secs(self.duration.as_secs() / 60 * 60)
+    }
+}
+
+impl std::fmt::Display for SuspendDrain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Lost {}%", self.lost)?;
+        if let Some(average) = self.average_w {
+            write!(f, " ({average:.1} W average)")?;
+        }
+        write!(
+            f,
+            " over {} suspended",
+            humantime::format_duration(self.rounded_duration())
+        )
+    }
+}
+
+/// Whether the time between two samples is longer than `expected` can explain
+pub fn is_gap(before: SystemTime, after: SystemTime, expected: Duration) -> bool {
+    after
+        .duration_since(before)
+        .is_ok_and(|elapsed| elapsed > expected + SLACK)
+}
+
+/// The drain over every suspend in `samples`, which must be in time order. Without the
+/// daemon's interval, the usual time between samples (the median) is taken for it.
+pub fn suspends(samples: &[(SystemTime, BatteryState)]) -> Vec<SuspendDrain> {
+    let mut spacings: Vec<_> = samples
+        .windows(2)
+        .filter_map(|pair| pair[1].0.duration_since(pair[0].0).ok())
+        .collect();
+    spacings.sort();
+    let Some(&expected) = spacings.get(spacings.len() / 2) else {
+        return Vec::new();
+    };
+
+    samples
+        .windows(2)
+        .filter(|pair| is_gap(pair[0].0, pair[1].0, expected))
+        .filter_map(|pair| SuspendDrain::between((pair[0].0, &pair[0].1), (pair[1].0, &pair[1].1)))
+        .collect()
+}
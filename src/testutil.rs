@@ -0,0 +1,66 @@
+//! Fake sysfs trees for tests
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Distinguishes the roots of tests running at the same time
+static NEXT_ROOT: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary directory laid out like /sys/class/power_supply, removed when dropped
+pub struct FakeRoot {
+    pub path: PathBuf,
+}
+
+impl FakeRoot {
+    pub fn new() -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "batmon-test-{}-{}",
+            std::process::id(),
+            NEXT_ROOT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    /// Add a device with the given attributes, returning its directory
+    pub fn device(&self, name: &str, attributes: &[(&str, &str)]) -> PathBuf {
+        let path = self.path.join(name);
+        std::fs::create_dir_all(&path).unwrap();
+        for (attribute, value) in attributes {
+            std::fs::write(path.join(attribute), format!("{value}\n")).unwrap();
+        }
+        path
+    }
+
+    /// Add a discharging system battery with every attribute batmon reads, at `level` percent
+    pub fn battery(&self, name: &str, level: u8) -> PathBuf {
+        let charge = (u64::from(level) * 40_000).to_string();
+        self.device(
+            name,
+            &[
+                ("type", "Battery"),
+                ("scope", "System"),
+                ("status", "Discharging"),
+                ("capacity", &level.to_string()),
+                ("charge_full", "4000000"),
+                ("charge_now", &charge),
+                ("current_now", "1000000"),
+                ("voltage_now", "12000000"),
+                ("cycle_count", "120"),
+            ],
+        )
+    }
+}
+
+impl Drop for FakeRoot {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Replace `path` with a FIFO, which blocks readers until something writes to it
+pub fn make_fifo(path: &Path) {
+    let _ = std::fs::remove_file(path);
+    let c_path = std::ffi::CString::new(path.as_os_str().as_encoded_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o644) }, 0);
+}
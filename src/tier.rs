@@ -0,0 +1,69 @@
+use crate::monitor::LEVELS;
+use batmon::BatteryState;
+use strum::EnumString;
+
+/// How urgently a battery's state should stand out, shared by every coloured output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Tier {
+    Charging,
+    Low,
+    Critical,
+}
+
+impl Tier {
+    /// The tier of a state, or `None` when nothing about it needs to stand out
+    pub fn of(s: &BatteryState) -> Option<Self> {
+        let [_, low, critical] = &LEVELS;
+        if s.is_charging() {
+            Some(Tier::Charging)
+        } else if s.is_critical(critical.level) {
+            Some(Tier::Critical)
+        } else if s.is_critical(low.level) {
+            Some(Tier::Low)
+        } else {
+            None
+        }
+    }
+
+    /// The SGR colour code for terminals
+    pub fn ansi(self) -> &'static str {
+        match self {
+            Tier::Charging => "32",
+            Tier::Low => "33",
+            Tier::Critical => "31",
+        }
+    }
+
+    /// The default colour for xmobar
+    pub fn xmobar(self) -> &'static str {
+        match self {
+            Tier::Charging => "#00ff00",
+            Tier::Low => "#ffff00",
+            Tier::Critical => "#ff0000",
+        }
+    }
+
+    /// The matching colour name, as understood by Pango markup and conky
+    pub fn color_name(self) -> &'static str {
+        match self {
+            Tier::Charging => "green",
+            Tier::Low => "yellow",
+            Tier::Critical => "red",
+        }
+    }
+}
+
+/// Parse a `TIER=COLOR` setting
+pub fn parse_color_setting(s: &str) -> Result<(Tier, String), String> {
+    let (tier, color) = s
+        .split_once('=')
+        .ok_or_else(|| format!("'{s}' is not of the form TIER=COLOR"))?;
+    let tier = tier.trim().parse().map_err(|_| {
+        format!(
+            "unknown tier '{}' in '{s}' (expected charging, low or critical)",
+            tier.trim()
+        )
+    })?;
+    Ok((tier, color.trim().to_owned()))
+}
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A point in time as written out, with both a machine and a human readable form
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Repr {
+    Full { epoch: u64, rfc3339: String },
+    Epoch(u64),
+}
+
+pub fn serialize<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    let epoch = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let rfc3339 = humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(epoch));
+    Repr::Full {
+        epoch,
+        rfc3339: rfc3339.to_string(),
+    }
+    .serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+    let epoch = match Repr::deserialize(deserializer)? {
+        Repr::Full { epoch, .. } | Repr::Epoch(epoch) => epoch,
+    };
+    Ok(UNIX_EPOCH + Duration::from_secs(epoch))
+}
+
+/// States written before timestamps were recorded are treated as captured at the epoch
+pub fn unknown() -> SystemTime {
+    UNIX_EPOCH
+}
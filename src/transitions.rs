@@ -0,0 +1,90 @@
+//! A log of the events worth knowing about after the fact, like the battery dying without a
+//! warning: status changes, threshold crossings and notifications. Unlike the verbose log it
+//! has no per-sample noise, and unlike the history it keeps no raw samples.
+//!
+//! Each event is appended as one line of space separated fields:
+//!
+//! ```text
+//! <rfc3339 time> <kind> <key>=<value>... level=<percent> status=<status> remaining=<seconds>
+//! ```
+//!
+//! where `kind` is one of
+//!
+//! - `status`, with `from` and `to` statuses
+//! - `threshold`, with the `event` whose threshold was crossed
+//! - `notify`, with the `event` and its `result`: `sent`, `rate-limited`, `dropped` or
+//!   `queued` while do not disturb is on, `snoozed`, or `failed`
+//!
+//! Statuses are written without spaces (`NotCharging`), and `remaining` is `-` when there
+//! is no estimate. Fields may be added to the end of a line,
+//! but existing ones are never removed, renamed or reordered.
+
+use batmon::{BatteryState, PowerSource};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+pub struct TransitionLog {
+    path: PathBuf,
+    /// Whether a write has failed since the last successful one, so failures are only
+    /// warned about once
+    failing: bool,
+}
+
+impl TransitionLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            failing: false,
+        }
+    }
+
+    /// Append an event with its `fields`, followed by the battery's state.
+    /// Failures are logged and otherwise ignored, the log must never stop the daemon.
+    pub fn record(&mut self, battery: &impl PowerSource, kind: &str, fields: &[(&str, &str)]) {
+        let line = format_line(&battery.state(), battery.remaining_duration(), kind, fields);
+        let res = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut f| f.write_all(line.as_bytes()));
+
+        match res {
+            Ok(()) => self.failing = false,
+            Err(e) if !self.failing => {
+                self.failing = true;
+                warn!(
+                    "Failed to write transitions log '{}': {e}",
+                    self.path.to_string_lossy()
+                );
+            }
+            Err(e) => debug!("Failed to write transitions log: {e}"),
+        }
+    }
+}
+
+fn format_line(
+    state: &BatteryState,
+    remaining: Option<Duration>,
+    kind: &str,
+    fields: &[(&str, &str)],
+) -> String {
+    let epoch = state
+        .captured_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let time = humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(epoch));
+
+    let mut line = format!("{time} {kind}");
+    for (key, value) in fields {
+        line.push_str(&format!(" {key}={value}"));
+    }
+    let remaining = remaining.map_or(String::from("-"), |r| r.as_secs().to_string());
+    line.push_str(&format!(
+        " level={} status={:?} remaining={remaining}\n",
+        state.level, state.status
+    ));
+    line
+}
@@ -0,0 +1,222 @@
+//! Estimating the remaining time from how fast the charge actually changed between states,
+//! for devices whose current draw is missing or doesn't match the charge.
+
+use crate::battery::{BatteryState, BatteryValue};
+use crate::clock::Clock;
+use crate::status::ChargingStatus;
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+/// The charge over the states captured in a sliding window, fitted with a least-squares line.
+/// Times come from when the states were captured, so a `FakeClock` gives exact samples.
+#[derive(Debug, Clone)]
+pub struct ChargeTrend {
+    window: Duration,
+    /// When each state was captured and its charge, oldest first
+    samples: VecDeque<(SystemTime, u64)>,
+    /// The status of the samples, which are only fitted together while it doesn't change
+    status: ChargingStatus,
+}
+
+impl Default for ChargeTrend {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_WINDOW)
+    }
+}
+
+impl ChargeTrend {
+    pub const DEFAULT_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            samples: VecDeque::new(),
+            status: ChargingStatus::Unknown,
+        }
+    }
+
+    /// Add the charge of `state`, dropping samples older than the window. A status change or
+    /// the clock going backwards starts over, and states without a charge are skipped.
+    pub fn push(&mut self, state: &BatteryState) {
+        if !state.is_field_valid(BatteryValue::Charge) {
+            return;
+        }
+        let went_back = self
+            .samples
+            .back()
+            .is_some_and(|&(last, _)| state.captured_at < last);
+        if state.status != self.status || went_back {
+            self.samples.clear();
+            self.status = state.status;
+        }
+
+        self.samples.push_back((state.captured_at, state.charge));
+        while let Some(&(oldest, _)) = self.samples.front() {
+            match state.captured_at.duration_since(oldest) {
+                Ok(age) if age > self.window => self.samples.pop_front(),
+                _ => break,
+            };
+        }
+    }
+
+    /// The fitted line as the charge at the first sample and its change per second, or `None`
+    /// until the samples span some time
+    fn fit(&self) -> Option<(SystemTime, f64, f64)> {
+        let &(start, _) = self.samples.front()?;
+        let points: Vec<(f64, f64)> = self
+            .samples
+            .iter()
+            .map(|&(t, charge)| {
+                let t = t.duration_since(start).unwrap_or_default().as_secs_f64();
+                (t, charge as f64)
+            })
+            .collect();
+        let n = points.len() as f64;
+        let mean_t = points.iter().map(|p| p.0).sum::<f64>() / n;
+        let mean_c = points.iter().map(|p| p.1).sum::<f64>() / n;
+        let spread: f64 = points.iter().map(|p| (p.0 - mean_t).powi(2)).sum();
+        if spread == 0.0 {
+            return None;
+        }
+        let slope = points
+            .iter()
+            .map(|p| (p.0 - mean_t) * (p.1 - mean_c))
+            .sum::<f64>()
+            / spread;
+        Some((start, mean_c - slope * mean_t, slope))
+    }
+
+    /// The change of the charge per second, negative while discharging
+    pub fn rate(&self) -> Option<f64> {
+        self.fit().map(|(_, _, slope)| slope)
+    }
+
+    /// The time until empty, or until full against `capacity`, at the fitted rate, counted
+    /// from the time `clock` gives. `None` without a rate in the direction of the status.
+    pub fn remaining_at(&self, clock: &impl Clock, capacity: u64) -> Option<Duration> {
+        let (start, intercept, slope) = self.fit()?;
+        let elapsed = clock
+            .now()
+            .duration_since(start)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let charge = (intercept + slope * elapsed).clamp(0.0, capacity as f64);
+        let seconds = match self.status {
+            ChargingStatus::Discharging if slope < 0.0 => charge / -slope,
+            ChargingStatus::Charging if slope > 0.0 => (capacity as f64 - charge) / slope,
+            _ => return None,
+        };
+        // Whole seconds, like the estimate at the current draw
+        Some(Duration::from_secs(seconds.round() as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FakeClock;
+    use std::time::UNIX_EPOCH;
+
+    fn sample(clock: &FakeClock, charge: u64, status: ChargingStatus) -> BatteryState {
+        BatteryState::builder()
+            .charge(charge)
+            .status(status)
+            .captured_at(clock.now())
+            .build()
+    }
+
+    fn assert_rate(trend: &ChargeTrend, expected: f64) {
+        let rate = trend.rate().unwrap();
+        assert!((rate - expected).abs() < 1e-6, "{rate} != {expected}");
+    }
+
+    /// A trend fed `charges` a minute apart, with the clock left at the last sample
+    fn trend(clock: &FakeClock, charges: &[u64], status: ChargingStatus) -> ChargeTrend {
+        let mut trend = ChargeTrend::default();
+        for (i, &charge) in charges.iter().enumerate() {
+            if i > 0 {
+                clock.advance(Duration::from_secs(60));
+            }
+            trend.push(&sample(clock, charge, status));
+        }
+        trend
+    }
+
+    #[test]
+    fn discharging_projection() {
+        let clock = FakeClock::new(UNIX_EPOCH + Duration::from_secs(1000));
+        // 10000 a minute with 2000000 left
+        let charges = [2_040_000, 2_030_000, 2_020_000, 2_010_000, 2_000_000];
+        let trend = trend(&clock, &charges, ChargingStatus::Discharging);
+        assert_rate(&trend, -10_000.0 / 60.0);
+        assert_eq!(
+            trend.remaining_at(&clock, 4_000_000),
+            Some(Duration::from_secs(200 * 60))
+        );
+
+        // The projection counts down between samples
+        clock.advance(Duration::from_secs(30 * 60));
+        assert_eq!(
+            trend.remaining_at(&clock, 4_000_000),
+            Some(Duration::from_secs(170 * 60))
+        );
+    }
+
+    #[test]
+    fn charging_projection() {
+        let clock = FakeClock::new(UNIX_EPOCH);
+        let charges = [1_000_000, 1_020_000, 1_040_000];
+        let trend = trend(&clock, &charges, ChargingStatus::Charging);
+        // 20000 a minute with 2960000 to go
+        assert_eq!(
+            trend.remaining_at(&clock, 4_000_000),
+            Some(Duration::from_secs(148 * 60))
+        );
+    }
+
+    #[test]
+    fn noisy_samples_are_fitted() {
+        let clock = FakeClock::new(UNIX_EPOCH);
+        let charges = [2_040_000, 2_032_000, 2_018_000, 2_012_000, 2_000_000];
+        let trend = trend(&clock, &charges, ChargingStatus::Discharging);
+        let rate = trend.rate().unwrap();
+        assert!((rate + 10_000.0 / 60.0).abs() < 10.0, "{rate}");
+        let remaining = trend.remaining_at(&clock, 4_000_000).unwrap();
+        assert!(remaining.abs_diff(Duration::from_secs(200 * 60)) < Duration::from_secs(5 * 60));
+    }
+
+    #[test]
+    fn no_projection_without_a_trend() {
+        let clock = FakeClock::new(UNIX_EPOCH);
+        let single = trend(&clock, &[2_000_000], ChargingStatus::Discharging);
+        assert_eq!(single.remaining_at(&clock, 4_000_000), None);
+
+        // Flat, or rising while the status says discharging
+        let flat = trend(&clock, &[2_000_000; 3], ChargingStatus::Discharging);
+        assert_eq!(flat.remaining_at(&clock, 4_000_000), None);
+        let rising = trend(&clock, &[1_000_000, 1_100_000], ChargingStatus::Discharging);
+        assert_eq!(rising.remaining_at(&clock, 4_000_000), None);
+    }
+
+    #[test]
+    fn status_change_starts_over() {
+        let clock = FakeClock::new(UNIX_EPOCH);
+        let mut trend = trend(&clock, &[1_000_000, 1_100_000], ChargingStatus::Charging);
+        clock.advance(Duration::from_secs(60));
+        trend.push(&sample(&clock, 1_100_000, ChargingStatus::Discharging));
+        assert_eq!(trend.rate(), None);
+        clock.advance(Duration::from_secs(60));
+        trend.push(&sample(&clock, 1_090_000, ChargingStatus::Discharging));
+        assert_rate(&trend, -10_000.0 / 60.0);
+    }
+
+    #[test]
+    fn old_samples_leave_the_window() {
+        let clock = FakeClock::new(UNIX_EPOCH);
+        let mut charges = vec![3_000_000; 5];
+        // Ten minutes flat, then dropping 10000 a minute
+        charges.extend((1..=10).map(|i| 3_000_000 - i * 10_000));
+        let trend = trend(&clock, &charges, ChargingStatus::Discharging);
+        assert_rate(&trend, -10_000.0 / 60.0);
+    }
+}
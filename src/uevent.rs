@@ -0,0 +1,353 @@
+//! Kernel uevents for power supply devices.
+//!
+//! Every device has a `uevent` file listing its properties as `POWER_SUPPLY_*=value` lines,
+//! which `UeventData` parses. With the `netlink` feature, the kernel also announces power
+//! supply changes on the `NETLINK_KOBJECT_UEVENT` socket, so the daemon can update as soon as
+//! something changes instead of waiting for the next poll.
+
+use crate::status::ChargingStatus;
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+#[cfg(feature = "netlink")]
+use std::{
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+};
+
+/// The prefix of the properties of power supply devices
+const PREFIX: &str = "POWER_SUPPLY_";
+
+/// The properties in a `uevent` file, like `POWER_SUPPLY_CAPACITY=55`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UeventData {
+    properties: HashMap<String, String>,
+}
+
+/// A `uevent` line with an empty or malformed key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseUeventError {
+    /// The line number, counting from 1
+    pub line: usize,
+}
+
+impl std::fmt::Display for ParseUeventError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid uevent property on line {}", self.line)
+    }
+}
+
+impl std::error::Error for ParseUeventError {}
+
+impl UeventData {
+    /// Parse the contents of a `uevent` file. Lines without a `=` carry no property and are
+    /// skipped, a value may itself contain `=`, and the last of duplicate keys wins.
+    pub fn parse(data: &str) -> Result<Self, ParseUeventError> {
+        let mut properties = HashMap::new();
+        for (i, line) in data.lines().enumerate() {
+            let Some((key, value)) = line.trim().split_once('=') else {
+                continue;
+            };
+            let valid = |c: char| c.is_ascii_alphanumeric() || c == '_';
+            if key.is_empty() || !key.chars().all(valid) {
+                return Err(ParseUeventError { line: i + 1 });
+            }
+            properties.insert(key.to_owned(), value.to_owned());
+        }
+        Ok(Self { properties })
+    }
+
+    /// Parse the raw contents of a `uevent` file. Drivers copy strings like the model name
+    /// from the hardware, so bytes that aren't UTF-8 are replaced rather than failing.
+    pub fn parse_bytes(data: &[u8]) -> Result<Self, ParseUeventError> {
+        Self::parse(&String::from_utf8_lossy(data))
+    }
+
+    /// Read and parse the `uevent` file of the device at `path`
+    pub fn read(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self::parse_bytes(&std::fs::read(path.join("uevent"))?)?)
+    }
+
+    /// The value of a property by its full key (e.g. `POWER_SUPPLY_CAPACITY`)
+    pub fn get_raw(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(String::as_str)
+    }
+
+    /// The value of a power supply property by its attribute name (e.g. `capacity`)
+    pub fn get(&self, attribute: &str) -> Option<&str> {
+        self.get_raw(&format!("{PREFIX}{}", attribute.to_uppercase()))
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    }
+
+    fn parsed<T: FromStr>(&self, attribute: &str) -> Option<T> {
+        self.get(attribute)?.parse().ok()
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.get("name")
+    }
+
+    /// The `type` of the device, like `Battery` or `Mains`
+    pub fn supply_type(&self) -> Option<&str> {
+        self.get("type")
+    }
+
+    pub fn status(&self) -> Option<ChargingStatus> {
+        self.parsed("status")
+    }
+
+    pub fn present(&self) -> Option<bool> {
+        self.parsed::<u8>("present").map(|p| p != 0)
+    }
+
+    pub fn online(&self) -> Option<bool> {
+        self.parsed::<u8>("online").map(|o| o != 0)
+    }
+
+    /// The level in percent
+    pub fn capacity(&self) -> Option<u8> {
+        self.parsed("capacity")
+    }
+
+    /// The coarse level, like `Normal` or `Critical`
+    pub fn capacity_level(&self) -> Option<&str> {
+        self.get("capacity_level")
+    }
+
+    /// In uAh
+    pub fn charge_now(&self) -> Option<u64> {
+        self.parsed("charge_now")
+    }
+
+    /// In uAh
+    pub fn charge_full(&self) -> Option<u64> {
+        self.parsed("charge_full")
+    }
+
+    /// In uWh
+    pub fn energy_now(&self) -> Option<u64> {
+        self.parsed("energy_now")
+    }
+
+    /// In uWh
+    pub fn energy_full(&self) -> Option<u64> {
+        self.parsed("energy_full")
+    }
+
+    /// In uA, negative while discharging on some drivers
+    pub fn current_now(&self) -> Option<i64> {
+        self.parsed("current_now")
+    }
+
+    /// In uW, negative while discharging on some drivers
+    pub fn power_now(&self) -> Option<i64> {
+        self.parsed("power_now")
+    }
+
+    /// In uV
+    pub fn voltage_now(&self) -> Option<u64> {
+        self.parsed("voltage_now")
+    }
+
+    pub fn cycle_count(&self) -> Option<u64> {
+        self.parsed("cycle_count")
+    }
+}
+
+/// The multicast group the kernel sends uevents to (udev rebroadcasts them to group 2)
+#[cfg(feature = "netlink")]
+const KERNEL_GROUP: u32 = 1;
+
+/// A non-blocking listener for kernel uevents
+#[cfg(feature = "netlink")]
+pub struct UeventListener {
+    fd: OwnedFd,
+}
+
+#[cfg(feature = "netlink")]
+impl UeventListener {
+    pub fn open() -> io::Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK,
+                libc::NETLINK_KOBJECT_UEVENT,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = KERNEL_GROUP;
+        let res = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { fd })
+    }
+
+    /// Read every pending uevent, returning whether any of them was for a power supply
+    pub fn power_supply_changed(&mut self) -> bool {
+        let mut buf = [0u8; 8192];
+        let mut changed = false;
+        loop {
+            let n = unsafe {
+                libc::recv(
+                    self.fd.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    0,
+                )
+            };
+            match usize::try_from(n) {
+                Ok(0) => return changed,
+                Ok(n) => changed |= is_power_supply(&buf[..n]),
+                Err(_) => {
+                    let e = io::Error::last_os_error();
+                    return match e.raw_os_error() {
+                        Some(libc::EAGAIN) => changed,
+                        // The socket overflowed, so a power supply change may have been lost
+                        Some(libc::ENOBUFS) => true,
+                        _ => {
+                            debug!("Failed to read uevent: {e}");
+                            changed
+                        }
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Whether a uevent, an `action@devpath` header followed by NUL separated `KEY=value` fields,
+/// is for a power supply
+#[cfg(feature = "netlink")]
+fn is_power_supply(message: &[u8]) -> bool {
+    message
+        .split(|&b| b == 0)
+        .any(|field| field == b"SUBSYSTEM=power_supply")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> UeventData {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/uevent")
+            .join(name);
+        UeventData::parse_bytes(&std::fs::read(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn energy_battery() {
+        let uevent = fixture("thinkpad_bat0.uevent");
+        assert_eq!(uevent.name(), Some("BAT0"));
+        assert_eq!(uevent.supply_type(), Some("Battery"));
+        assert_eq!(uevent.status(), Some(ChargingStatus::Discharging));
+        assert_eq!(uevent.present(), Some(true));
+        assert_eq!(uevent.capacity(), Some(68));
+        assert_eq!(uevent.capacity_level(), Some("Normal"));
+        assert_eq!(uevent.energy_now(), Some(34_210_000));
+        assert_eq!(uevent.energy_full(), Some(50_230_000));
+        assert_eq!(uevent.power_now(), Some(6_443_000));
+        assert_eq!(uevent.voltage_now(), Some(15_867_000));
+        assert_eq!(uevent.cycle_count(), Some(219));
+        assert_eq!(uevent.charge_now(), None);
+        // The raw value keeps the padding that `get` trims
+        assert_eq!(uevent.get_raw("POWER_SUPPLY_SERIAL_NUMBER"), Some(" 1234"));
+        assert_eq!(uevent.get("serial_number"), Some("1234"));
+    }
+
+    #[test]
+    fn charge_battery() {
+        let uevent = fixture("dell_bat0.uevent");
+        assert_eq!(uevent.status(), Some(ChargingStatus::Charging));
+        assert_eq!(uevent.charge_now(), Some(2_187_000));
+        assert_eq!(uevent.charge_full(), Some(3_270_000));
+        assert_eq!(uevent.current_now(), Some(1_850_000));
+        assert_eq!(uevent.get("model_name"), Some("DELL 0VKR7A2"));
+        assert_eq!(uevent.energy_now(), None);
+    }
+
+    #[test]
+    fn fuel_gauge_with_negative_current() {
+        let uevent = fixture("bq27xxx_battery.uevent");
+        assert_eq!(uevent.name(), Some("bq27xxx_battery"));
+        assert_eq!(uevent.current_now(), Some(-412_000));
+        assert_eq!(uevent.capacity(), Some(47));
+        assert_eq!(uevent.get("health"), Some("Good"));
+    }
+
+    #[test]
+    fn peripheral_and_adapter() {
+        let mouse = fixture("hid_mouse.uevent");
+        assert_eq!(mouse.get("scope"), Some("Device"));
+        assert_eq!(mouse.capacity(), Some(60));
+        assert_eq!(mouse.online(), Some(true));
+
+        let ac = fixture("ac.uevent");
+        assert_eq!(ac.supply_type(), Some("Mains"));
+        assert_eq!(ac.online(), Some(true));
+        assert_eq!(ac.status(), None);
+    }
+
+    #[test]
+    fn malformed() {
+        let uevent = fixture("malformed.uevent");
+        // Quotes aren't special, the kernel never writes them
+        assert_eq!(uevent.get("model_name"), Some("\"Primary Pack\""));
+        // The line without a `=` is skipped, and the rest still parses
+        assert_eq!(uevent.status(), Some(ChargingStatus::Full));
+        // The last of duplicate keys wins
+        assert_eq!(uevent.capacity(), Some(91));
+        // Bytes that aren't UTF-8 are replaced
+        assert_eq!(uevent.get("manufacturer"), Some("Gr\u{fffd}n\u{fffd} AG"));
+        assert_eq!(uevent.get("extra"), Some("a=b=c"));
+        // An empty value is no value
+        assert_eq!(uevent.get_raw("POWER_SUPPLY_ONLINE"), Some(""));
+        assert_eq!(uevent.online(), None);
+    }
+
+    #[test]
+    fn invalid_keys() {
+        assert_eq!(
+            UeventData::parse("POWER_SUPPLY_NAME=BAT0\n=55\n"),
+            Err(ParseUeventError { line: 2 })
+        );
+        assert_eq!(
+            UeventData::parse("POWER SUPPLY=1\n"),
+            Err(ParseUeventError { line: 1 })
+        );
+        assert_eq!(UeventData::parse(""), Ok(UeventData::default()));
+    }
+
+    #[cfg(feature = "netlink")]
+    #[test]
+    fn power_supply_messages() {
+        let battery = b"change@/devices/LNXSYSTM:00/PNP0C0A:00/power_supply/BAT0\0\
+            ACTION=change\0DEVPATH=/devices/LNXSYSTM:00/PNP0C0A:00/power_supply/BAT0\0\
+            SUBSYSTEM=power_supply\0POWER_SUPPLY_NAME=BAT0\0SEQNUM=4242\0";
+        assert!(is_power_supply(battery));
+
+        let usb = b"add@/devices/pci0000:00/usb1/1-1\0ACTION=add\0SUBSYSTEM=usb\0SEQNUM=4243\0";
+        assert!(!is_power_supply(usb));
+        // Only a whole field counts
+        assert!(!is_power_supply(
+            b"change@/x\0SUBSYSTEM=power_supply_extra\0"
+        ));
+        assert!(!is_power_supply(b"change@/x\0X_SUBSYSTEM=power_supply\0"));
+        assert!(!is_power_supply(b""));
+    }
+}
@@ -0,0 +1,246 @@
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+/// The family of sysfs attributes a battery reports its charge with.
+///
+/// Charge batteries report capacity and charge in uAh and draw in uA,
+/// energy batteries report capacity and charge in uWh and draw in uW.
+#[derive(Debug, Clone, Copy, Default, Display, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnitFamily {
+    #[default]
+    Charge,
+    Energy,
+}
+
+impl UnitFamily {
+    /// The sysfs attributes holding the full capacity, the current charge and the current draw
+    pub fn attributes(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            UnitFamily::Charge => ("charge_full", "charge_now", "current_now"),
+            UnitFamily::Energy => ("energy_full", "energy_now", "power_now"),
+        }
+    }
+
+    /// The unit capacity and charge are reported in
+    pub fn charge_unit(self) -> &'static str {
+        match self {
+            UnitFamily::Charge => "uAh",
+            UnitFamily::Energy => "uWh",
+        }
+    }
+
+    /// The unit the current draw is reported in
+    pub fn current_unit(self) -> &'static str {
+        match self {
+            UnitFamily::Charge => "uA",
+            UnitFamily::Energy => "uW",
+        }
+    }
+
+    /// Format a charge out of a capacity (both in the family's micro-units) for people,
+    /// as whole mAh for charge batteries and Wh with `precision` decimals for energy batteries
+    /// (e.g. `3520/4200 mAh`)
+    pub fn format_absolute(self, charge: u64, capacity: u64, precision: usize) -> String {
+        match self {
+            UnitFamily::Charge => format!("{}/{} mAh", charge / 1000, capacity / 1000),
+            UnitFamily::Energy => format!(
+                "{}/{} Wh",
+                format_decimal(charge as f64 / 1e6, precision),
+                format_decimal(capacity as f64 / 1e6, precision)
+            ),
+        }
+    }
+}
+
+/// Format `value` with exactly `precision` decimals, rounding halves away from zero.
+/// Trailing zeros are kept (`2.50` at precision 2) so columns of values line up.
+pub fn format_decimal(value: f64, precision: usize) -> String {
+    let scale = 10f64.powi(precision as i32);
+    let rounded = (value * scale).round() / scale;
+    format!("{rounded:.precision$}")
+}
+
+/// Voltages read as fewer uV than this are taken to be in volts
+const VOLTS_BELOW: u64 = 100;
+/// Voltages read as fewer uV than this (0.1 V, far below any battery) are taken to be in mV
+const MILLIVOLTS_BELOW: u64 = 100_000;
+/// Draws that would take longer than this many hours to drain the full capacity are taken
+/// to be in milli-units, as even an idle system drains its battery in days
+const MAX_HOURS_AT_DRAW: u64 = 1000;
+
+/// How much to scale a voltage read as `uv` uV by, for drivers that report it in V or mV.
+/// Unreported voltages (0) are left alone.
+pub(crate) fn voltage_scale(uv: u64) -> u64 {
+    match uv {
+        0 => 1,
+        uv if uv < VOLTS_BELOW => 1_000_000,
+        uv if uv < MILLIVOLTS_BELOW => 1_000,
+        _ => 1,
+    }
+}
+
+/// How much to scale a draw by, for drivers that report it in mA or mW, given the full
+/// `capacity` in the same family. Without a draw or a capacity to compare with, nothing is.
+pub(crate) fn draw_scale(draw: u64, capacity: u64) -> u64 {
+    if draw > 0 && draw.saturating_mul(MAX_HOURS_AT_DRAW) < capacity {
+        1_000
+    } else {
+        1
+    }
+}
+
+/// The base units values may be suffixed with, see `parse_suffixed`
+const BASE_UNITS: [&str; 5] = ["V", "A", "Ah", "W", "Wh"];
+
+/// Split a unit suffix like `mAh` into its scale relative to the micro-unit and its base unit
+fn split_unit(suffix: &str) -> Option<(i64, &str)> {
+    let (scale, base) = if let Some(base) = suffix.strip_prefix(['u', 'µ']) {
+        (1, base)
+    } else if let Some(base) = suffix.strip_prefix('m') {
+        (1_000, base)
+    } else {
+        (1_000_000, suffix)
+    };
+    BASE_UNITS.contains(&base).then_some((scale, base))
+}
+
+/// Whether `s` starts with a unit suffix like `mV`, so the number before it isn't in micro-units
+pub(crate) fn starts_with_unit(s: &str) -> bool {
+    s.split_whitespace()
+        .next()
+        .is_some_and(|word| split_unit(word).is_some())
+}
+
+/// Parse a value with an optional unit suffix (e.g. `4200 mV`, `2100mAh` or `4.2V`) into
+/// `micro_unit` (e.g. `uV`). A value without a suffix is taken to be in the micro-unit
+/// already. Unknown suffixes and suffixes for another quantity are an error.
+pub(crate) fn parse_suffixed(s: &str, micro_unit: &str) -> Result<i64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.')))
+        .unwrap_or(s.len());
+    let (number, suffix) = (&s[..split], s[split..].trim_start());
+
+    let expected = micro_unit.trim_start_matches('u');
+    let scale = match split_unit(suffix) {
+        _ if suffix.is_empty() => 1,
+        Some((scale, base)) if base == expected => scale,
+        Some(_) => return Err(format!("expected a value in {micro_unit}, found {suffix}")),
+        None => return Err(format!("unknown unit '{suffix}'")),
+    };
+
+    let invalid = || format!("invalid number '{number}'");
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let negative = whole.starts_with('-');
+    let whole: i64 = whole.parse().map_err(|_| invalid())?;
+    let mut value = whole.checked_mul(scale).ok_or_else(invalid)?;
+
+    if !fraction.is_empty() {
+        let places = u32::try_from(fraction.len()).map_err(|_| invalid())?;
+        let divisor = 10i64.checked_pow(places).filter(|d| scale % d == 0);
+        let divisor = divisor.ok_or_else(|| format!("'{s}' is more precise than {micro_unit}"))?;
+        let fraction = fraction.parse::<i64>().map_err(|_| invalid())? * (scale / divisor);
+        value = if negative {
+            value.checked_sub(fraction)
+        } else {
+            value.checked_add(fraction)
+        }
+        .ok_or_else(invalid)?;
+    }
+    Ok(value)
+}
+
+/// Which attribute the current draw is read from.
+/// Draws read from the attribute of the other family are converted using the battery voltage.
+#[derive(Debug, Clone, Copy, Default, Display, EnumString, PartialEq, Eq)]
+#[strum(serialize_all = "lowercase")]
+pub enum CurrentSource {
+    /// The attribute native to the battery's family, or the other one if it is missing
+    #[default]
+    Auto,
+    /// `current_now`, in uA
+    Current,
+    /// `power_now`, in uW
+    Power,
+}
+
+impl CurrentSource {
+    pub fn attribute(self, family: UnitFamily) -> &'static str {
+        match (self, family) {
+            (CurrentSource::Current, _) | (CurrentSource::Auto, UnitFamily::Charge) => {
+                "current_now"
+            }
+            (CurrentSource::Power, _) | (CurrentSource::Auto, UnitFamily::Energy) => "power_now",
+        }
+    }
+
+    /// The unit the draw read from `attribute(family)` is in
+    pub fn unit(self, family: UnitFamily) -> &'static str {
+        match self.attribute(family) {
+            "current_now" => "uA",
+            _ => "uW",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suffixed_values() {
+        let cases = [
+            ("4200 mV", "uV", 4_200_000),
+            ("4200mV", "uV", 4_200_000),
+            ("4.2V", "uV", 4_200_000),
+            ("4.2 V", "uV", 4_200_000),
+            ("12000000 uV", "uV", 12_000_000),
+            ("12000000 µV", "uV", 12_000_000),
+            ("12000000", "uV", 12_000_000),
+            ("2100mAh", "uAh", 2_100_000),
+            ("2.1 Ah", "uAh", 2_100_000),
+            ("-1500 mA", "uA", -1_500_000),
+            ("-1.5 A", "uA", -1_500_000),
+            ("45 Wh", "uWh", 45_000_000),
+            ("800 mW", "uW", 800_000),
+        ];
+        for (value, unit, expected) in cases {
+            assert_eq!(
+                parse_suffixed(value, unit),
+                Ok(expected),
+                "{value} in {unit}"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_suffixed_values() {
+        let cases = [
+            // Unknown and ambiguous units
+            ("4200 kV", "uV"),
+            ("4200 mv", "uV"),
+            ("4200 volts", "uV"),
+            // Another quantity
+            ("4200 mA", "uV"),
+            ("2100 mAh", "uA"),
+            // More precise than the micro-unit
+            ("1.0000001 V", "uV"),
+            ("abc", "uV"),
+            ("4.2.1 V", "uV"),
+        ];
+        for (value, unit) in cases {
+            assert!(parse_suffixed(value, unit).is_err(), "{value} in {unit}");
+        }
+    }
+
+    #[test]
+    fn unit_prefixes() {
+        assert!(starts_with_unit("mV"));
+        assert!(starts_with_unit("Wh left"));
+        assert!(!starts_with_unit("(estimated)"));
+        assert!(!starts_with_unit(""));
+    }
+}